@@ -15,7 +15,11 @@ use tracing::info;
 
 use crate::{
     android::DevServer,
-    commands::build::{Builder, Strategy},
+    commands::build::{
+        Builder, Strategy,
+        layout::{is_ignored, read_ignore},
+    },
+    home_manager::HomeManager,
     utils::relative,
 };
 
@@ -26,6 +30,7 @@ async fn spawn_file_reader(
     sender: Sender<Message>,
 ) {
     let root = local.clone();
+    let ignore = read_ignore(&root);
     tokio::spawn(async move {
         let (mut c, mut r) = AsyncDebouncer::new_with_channel(
             Duration::from_millis(20),
@@ -43,6 +48,10 @@ async fn spawn_file_reader(
                     continue;
                 }
             }
+            let data: Vec<_> = data
+                .into_iter()
+                .filter(|x| !is_ignored(&ignore, &x.path))
+                .collect();
             if data
                 .iter()
                 .filter(|x| !x.path.starts_with(root.join(".build")))
@@ -141,6 +150,7 @@ pub async fn watch(base_path: Option<PathBuf>, ip: String) {
             .bundle
             .iter()
             .chain(builder.config.layout.external.iter())
+            .filter(|x| !x.starts_with('!'))
             .map(|x| PathBuf::from(x))
             .collect(),
         sender.clone(),
@@ -194,3 +204,10 @@ pub async fn watch(base_path: Option<PathBuf>, ip: String) {
         }
     }
 }
+
+pub async fn keygen(force: bool) -> color_eyre::Result<()> {
+    let home = HomeManager::new().await?;
+    let keystore = home.ensure_debug_keystore(force).await?;
+    println!("Debug keystore at {}", keystore.display());
+    Ok(())
+}
@@ -1,9 +1,26 @@
+use std::path::{Path, PathBuf};
+
+use color_eyre::{
+    Section,
+    eyre::{Context, ContextCompat, eyre},
+};
 use fs_err::tokio::{File, create_dir_all, remove_dir_all};
-use image::{GenericImageView, imageops::FilterType};
+use image::GenericImageView;
 use tokio::{io::AsyncWriteExt, process::Command};
 use tracing::info;
+use walkdir::WalkDir;
+use zip::ZipArchive;
 
-use crate::{commands::build::Builder, home_manager::Target};
+use crate::{
+    commands::build::{
+        Builder,
+        android_icons::{fit, write_adaptive_icons},
+    },
+    home_manager::{DEBUG_KEY_ALIAS, DEBUG_KEY_PASSWORD, HomeManager, Target},
+    toml_conf::{AndroidConfig, ManifestMode},
+    xml_utils::{XmlElement, XmlNode},
+    zip_utils::Zipper,
+};
 
 macro_rules! create {
     ($name: expr, $value :expr) => {{
@@ -21,60 +38,56 @@ macro_rules! create {
             .expect("Failed to create folder");
     }};
 }
-pub async fn build_android(builder: &Builder, data: &[u8]) -> color_eyre::Result<()> {
-    tracing::warn!("Remember to sign your android build");
-    let Some(config) = &builder.config.android else {
-        eprintln!("No valid android config, skipping android build...");
-        tracing::warn!("No valid android config, skipping android build...");
-        return Ok(());
-    };
+async fn apktool(home: &HomeManager, args: &[&str]) -> color_eyre::Result<()> {
+    let status = Command::new(home.get_java_path())
+        .arg("-jar")
+        .arg(home.get_apktool_path())
+        .args(args)
+        .status()
+        .await
+        .context("Failed to spawn apktool")?;
+    if !status.success() {
+        return Err(eyre!("apktool {} failed with {}", args[0], status));
+    }
+    Ok(())
+}
 
-    let dist_folder = builder.paths.dist.join("Android");
-    let project_name = &builder.config.project_name;
-    let love_version = &builder.config.love;
+/// Decodes the love apk into `build_folder` and puts the game, icon and manifest in it
+async fn prepare_project(
+    builder: &Builder,
+    config: &AndroidConfig,
+    data: &[u8],
+    build_folder: &Path,
+) -> color_eyre::Result<()> {
     let home = &builder.home;
 
     home.ensure_java().await?;
     home.ensure_apktool().await?;
 
-    let apk = home
-        .get_path(love_version, Target::Android)
-        .await
+    let apk = builder
+        .runtime_path(Target::Android)
+        .await?
         .join("love2d.apk");
 
-    let apktool_jar = home.get_apktool_path();
-    let java = home.get_java_path();
-
-    let build_folder = dist_folder.join("build");
-
-    let apktool = async |args: &[&str]| {
-        Command::new(&java)
-            .args(
-                ["-jar", &apktool_jar.to_string_lossy()]
-                    .into_iter()
-                    .chain(args.to_owned()),
-            )
-            .spawn()
-            .expect("Failed to spawn apktool")
-            .wait()
-            .await
-            .expect("Apktool failed")
-    };
-
     println!("Unzipping apk file");
 
-    apktool(&[
-        "d",
-        "-f",
-        "-s",
-        "-o",
-        &build_folder.to_string_lossy(),
-        &apk.to_string_lossy(),
-    ])
-    .await;
+    apktool(
+        home,
+        &[
+            "d",
+            "-f",
+            "-s",
+            "-p",
+            &home.get_framework_path().to_string_lossy(),
+            "-o",
+            &build_folder.to_string_lossy(),
+            &apk.to_string_lossy(),
+        ],
+    )
+    .await?;
 
     if let Some(icon) = &builder.config.icon {
-        let img = image::open(icon)?;
+        let img = image::open(builder.paths.root.join(icon))?;
 
         for logo in glob::glob(
             &build_folder
@@ -88,35 +101,709 @@ pub async fn build_android(builder: &Builder, data: &[u8]) -> color_eyre::Result
                 let original_image = image::open(&logo)?;
                 original_image.dimensions()
             };
-            let resized = img
-                .resize_exact(width, height, FilterType::Lanczos3)
-                .to_rgba8();
-            resized.save(logo)?;
+            fit(&img, width, height).save(logo)?;
         }
     }
 
+    if config.has_adaptive_icon() {
+        write_adaptive_icons(builder, config, build_folder)?;
+    }
+
     let android_manifest = build_folder.join("AndroidManifest.xml");
 
     // Creates AndroidManifest.xml
-    create!(&android_manifest, config.to_string(project_name).as_bytes());
+    create!(
+        &android_manifest,
+        render_manifest(builder, config).await?.as_bytes()
+    );
+    update_sdk_info(build_folder, config).await?;
 
     let bundle = build_folder.join("assets").join("game.love");
     create!(ensure_path => &bundle.parent().unwrap());
     create!(&bundle, &data);
 
+    Ok(())
+}
+
+fn apply_placeholders(text: &str, placeholders: &[(&str, String)]) -> String {
+    placeholders
+        .iter()
+        .fold(text.to_string(), |text, (name, value)| {
+            text.replace(&format!("${{{}}}", name), value)
+        })
+}
+
+/// Elements that can only appear once, so they are merged instead of added
+const SINGLETONS: [&str; 3] = ["application", "uses-sdk", "supports-screens"];
+
+/// Adds the elements of `custom` on top of `generated`. Elements with the same
+/// android:name (and the singletons) get their attributes and children merged.
+fn merge_manifest(generated: &mut XmlElement, custom: XmlElement) {
+    for (key, value) in custom.attributes {
+        generated.set_attr(&key, value);
+    }
+    for child in custom.children {
+        let XmlNode::Element(child) = child else {
+            continue;
+        };
+        let existing = generated.elements_mut().find(|x| {
+            x.name == child.name
+                && match child.get_attr("android:name") {
+                    Some(name) => x.get_attr("android:name") == Some(name),
+                    None => SINGLETONS.contains(&child.name.as_str()),
+                }
+        });
+        match existing {
+            Some(existing) => merge_manifest(existing, child),
+            None => generated.children.push(XmlNode::Element(child)),
+        }
+    }
+}
+
+async fn render_manifest(builder: &Builder, config: &AndroidConfig) -> color_eyre::Result<String> {
+    let project_name = &builder.config.project_name;
+    let manifest = config.manifest(project_name);
+
+    let Some(custom) = &builder.config.custom_android_manifest else {
+        return Ok(manifest.to_document());
+    };
+    let path = builder.paths.root.join(custom);
+    let text = fs_err::tokio::read_to_string(&path)
+        .await
+        .suggestion("Check the custom_android_manifest field in kaledis.toml")?;
+    custom_manifest(config, project_name, manifest, &text)
+        .with_context(|| format!("Parsing {}", path.display()))
+}
+
+/// The manifest the custom one gives, with its placeholders expanded
+fn custom_manifest(
+    config: &AndroidConfig,
+    project_name: &str,
+    mut generated: XmlElement,
+    text: &str,
+) -> color_eyre::Result<String> {
+    let text = apply_placeholders(text, &config.placeholders(project_name));
+    match config.manifest_mode {
+        ManifestMode::Replace => Ok(text),
+        ManifestMode::Merge => {
+            merge_manifest(&mut generated, XmlElement::parse(&text)?);
+            Ok(generated.to_document())
+        }
+    }
+}
+
+/// apktool writes the sdk versions from apktool.yml over the manifest ones when building
+async fn update_sdk_info(build_folder: &Path, config: &AndroidConfig) -> color_eyre::Result<()> {
+    if config.min_sdk.is_none() && config.target_sdk.is_none() {
+        return Ok(());
+    }
+    let path = build_folder.join("apktool.yml");
+    let yml = fs_err::tokio::read_to_string(&path).await?;
+    let mut lines = vec![];
+    for line in yml.lines() {
+        let trimmed = line.trim_start();
+        let value = [
+            ("minSdkVersion:", config.min_sdk),
+            ("targetSdkVersion:", config.target_sdk),
+        ]
+        .into_iter()
+        .find_map(|(key, value)| Some((key, value?)).filter(|_| trimmed.starts_with(key)));
+        match value {
+            Some((key, value)) => {
+                let indent = &line[..line.len() - trimmed.len()];
+                // Older apktool versions quote the versions
+                let quote = if trimmed[key.len()..].trim().starts_with('\'') {
+                    "'"
+                } else {
+                    ""
+                };
+                lines.push(format!("{}{} {}{}{}", indent, key, quote, value, quote));
+            }
+            None => lines.push(line.to_string()),
+        }
+    }
+    fs_err::tokio::write(&path, lines.join("\n") + "\n").await?;
+    Ok(())
+}
+
+pub async fn build_android(builder: &Builder, data: &[u8]) -> color_eyre::Result<()> {
+    let Some(config) = &builder.config.android else {
+        eprintln!("No valid android config, skipping android build...");
+        tracing::warn!("No valid android config, skipping android build...");
+        return Ok(());
+    };
+
+    let dist_folder = builder.paths.dist.join("Android");
+    let build_folder = dist_folder.join("build");
+
+    prepare_project(builder, config, data, &build_folder).await?;
+
     info!("Building apk...");
 
-    apktool(&[
-        "b",
+    let output = dist_folder.join("app.apk");
+    apktool(
+        &builder.home,
+        &[
+            "b",
+            "-o",
+            &output.to_string_lossy(),
+            &build_folder.to_string_lossy(),
+        ],
+    )
+    .await?;
+
+    info!("Signing apk...");
+
+    sign_apk(builder, config, &output).await?;
+
+    info!("Cleaning build folder...");
+
+    create!(remove => &build_folder);
+
+    Ok(())
+}
+
+/// Reads the sdk versions apktool saved from the original manifest
+async fn read_sdk_info(
+    build_folder: &Path,
+) -> color_eyre::Result<(Option<String>, Option<String>)> {
+    let yml = fs_err::tokio::read_to_string(build_folder.join("apktool.yml")).await?;
+    let find = |key: &str| {
+        yml.lines().find_map(|line| {
+            let value = line.trim().strip_prefix(key)?.strip_prefix(':')?;
+            Some(value.trim().trim_matches(['\'', '"']).to_string())
+        })
+    };
+    Ok((find("minSdkVersion"), find("targetSdkVersion")))
+}
+
+/// Lays out the base module the way bundletool expects it, taking the
+/// proto resources from aapt2 and the rest from the decoded apk
+fn create_base_module(linked: &Path, build_folder: &Path) -> color_eyre::Result<Vec<u8>> {
+    let mut zip = Zipper::new();
+
+    let mut archive = ZipArchive::new(std::fs::File::open(linked)?)?;
+    for index in 0..archive.len() {
+        let file = archive.by_index_raw(index)?;
+        let name = match file.name() {
+            "AndroidManifest.xml" => "manifest/AndroidManifest.xml".to_string(),
+            name if name == "resources.pb" || name.starts_with("res/") => name.to_string(),
+            _ => continue,
+        };
+        zip.inner.raw_copy_file_rename(file, name)?;
+    }
+
+    for entry in fs_err::read_dir(build_folder)? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|x| x == "dex") {
+            zip.copy_from_path(&path, Path::new("dex").join(path.file_name().unwrap()))?;
+        }
+    }
+
+    for (folder, prefix) in [("assets", "assets"), ("lib", "lib"), ("unknown", "root")] {
+        let folder = build_folder.join(folder);
+        for entry in WalkDir::new(&folder).into_iter().filter_map(Result::ok) {
+            if entry.file_type().is_file() {
+                let name = Path::new(prefix).join(entry.path().strip_prefix(&folder)?);
+                zip.copy_from_path(entry.path(), name)?;
+            }
+        }
+    }
+
+    Ok(zip.finish())
+}
+
+pub async fn build_android_bundle(builder: &Builder, data: &[u8]) -> color_eyre::Result<()> {
+    let Some(config) = &builder.config.android else {
+        eprintln!("No valid android config, skipping android bundle build...");
+        tracing::warn!("No valid android config, skipping android bundle build...");
+        return Ok(());
+    };
+
+    let home = &builder.home;
+    let dist_folder = builder.paths.dist.join(Target::AndroidBundle.as_ref());
+    let build_folder = dist_folder.join("build");
+    let work_folder = dist_folder.join("intermediates");
+    create!(ensure_path => &work_folder);
+
+    home.ensure_aapt2().await?;
+    home.ensure_bundletool().await?;
+
+    prepare_project(builder, config, data, &build_folder).await?;
+
+    let aapt2 = async |args: &[&str]| -> color_eyre::Result<()> {
+        let status = Command::new(home.get_aapt2_path())
+            .args(args)
+            .status()
+            .await
+            .context("Failed to spawn aapt2")?;
+        if !status.success() {
+            return Err(eyre!("aapt2 {} failed with {}", args[0], status));
+        }
+        Ok(())
+    };
+
+    info!("Compiling resources...");
+
+    let compiled = work_folder.join("compiled.zip");
+    aapt2(&[
+        "compile",
+        "--legacy",
+        "--dir",
+        &build_folder.join("res").to_string_lossy(),
         "-o",
-        &dist_folder.join("app.apk").to_string_lossy(),
-        &build_folder.to_string_lossy(),
+        &compiled.to_string_lossy(),
     ])
-    .await;
+    .await?;
+
+    let framework = home.get_framework_path().join("1.apk");
+    let linked = work_folder.join("linked.apk");
+    let (min_sdk, target_sdk) = read_sdk_info(&build_folder).await?;
+    let mut link = vec![
+        "link".to_string(),
+        "--proto-format".to_string(),
+        "--auto-add-overlay".to_string(),
+        "--no-version-vectors".to_string(),
+        "-I".to_string(),
+        framework.to_string_lossy().to_string(),
+        "--manifest".to_string(),
+        build_folder
+            .join("AndroidManifest.xml")
+            .to_string_lossy()
+            .to_string(),
+        "-o".to_string(),
+        linked.to_string_lossy().to_string(),
+    ];
+    if let Some(min_sdk) = min_sdk {
+        link.extend(["--min-sdk-version".to_string(), min_sdk]);
+    }
+    if let Some(target_sdk) = target_sdk {
+        link.extend(["--target-sdk-version".to_string(), target_sdk]);
+    }
+    link.push(compiled.to_string_lossy().to_string());
+    aapt2(&link.iter().map(String::as_str).collect::<Vec<_>>()).await?;
+
+    info!("Building bundle...");
+
+    let base = work_folder.join("base.zip");
+    let module = {
+        let linked = linked.clone();
+        let build_folder = build_folder.clone();
+        tokio::task::spawn_blocking(move || create_base_module(&linked, &build_folder)).await??
+    };
+    create!(&base, &module);
+
+    let output = dist_folder.join("app.aab");
+    if output.exists() {
+        fs_err::tokio::remove_file(&output).await?;
+    }
+    let status = Command::new(home.get_java_path())
+        .arg("-jar")
+        .arg(home.get_bundletool_path())
+        .arg("build-bundle")
+        .arg(format!("--modules={}", base.display()))
+        .arg(format!("--output={}", output.display()))
+        .status()
+        .await
+        .context("Failed to spawn bundletool")?;
+    if !status.success() {
+        return Err(eyre!("bundletool failed with {}", status));
+    }
+
+    if config.signing.is_some() {
+        info!("Signing bundle...");
+        sign_bundle(builder, config, &output).await?;
+    } else {
+        tracing::warn!("No [android.signing] config, the bundle is left unsigned");
+    }
 
     info!("Cleaning build folder...");
 
     create!(remove => &build_folder);
+    create!(remove => &work_folder);
 
     Ok(())
 }
+
+/// The name of an environment variable holding a password, once it is set
+fn password_env(name: &str) -> color_eyre::Result<String> {
+    if std::env::var_os(name).is_none() {
+        return Err(eyre!("The {} environment variable is not set", name))
+            .suggestion("Set it to the password of your keystore");
+    }
+    Ok(name.to_string())
+}
+
+/// Where the signing tools read the passwords from, they never go in the arguments of a
+/// process since any user can list those
+enum Passwords {
+    /// The environment variables [android.signing] names
+    Env { store: String, key: String },
+    /// The debug keystore, whose password is public
+    Debug,
+}
+
+struct Credentials {
+    keystore: PathBuf,
+    alias: String,
+    passwords: Passwords,
+}
+
+async fn credentials(builder: &Builder, config: &AndroidConfig) -> color_eyre::Result<Credentials> {
+    let credentials = match &config.signing {
+        Some(signing) => {
+            let store = password_env(&signing.store_password_env)?;
+            let key = match &signing.key_password_env {
+                Some(env) => password_env(env)?,
+                None => store.clone(),
+            };
+            Credentials {
+                keystore: builder.paths.root.join(&signing.keystore),
+                alias: signing.alias.clone(),
+                passwords: Passwords::Env { store, key },
+            }
+        }
+        None => {
+            tracing::warn!(
+                "No [android.signing] config, signing with the debug keystore. Don't publish this apk"
+            );
+            Credentials {
+                keystore: builder.home.ensure_debug_keystore(false).await?,
+                alias: DEBUG_KEY_ALIAS.to_string(),
+                passwords: Passwords::Debug,
+            }
+        }
+    };
+
+    if !credentials.keystore.exists() {
+        return Err(eyre!(
+            "Keystore {} not found",
+            credentials.keystore.display()
+        ))
+        .suggestion("Check the keystore field of [android.signing]");
+    }
+    Ok(credentials)
+}
+
+/// The version of a build-tools folder, like 34.0.0 or 35.0.0-rc1
+fn build_tools_version(folder: &Path) -> Vec<u32> {
+    folder
+        .file_name()
+        .map(|x| x.to_string_lossy().to_string())
+        .unwrap_or_default()
+        .split(['.', '-'])
+        .map(|x| x.parse().unwrap_or(0))
+        .collect()
+}
+
+/// zipalign and apksigner from the newest build-tools of the Android SDK, or from PATH
+fn find_build_tools() -> Option<(PathBuf, PathBuf)> {
+    let (zipalign, apksigner) = if cfg!(windows) {
+        ("zipalign.exe", "apksigner.bat")
+    } else {
+        ("zipalign", "apksigner")
+    };
+    let mut folders = vec![];
+    for sdk in ["ANDROID_HOME", "ANDROID_SDK_ROOT"]
+        .into_iter()
+        .filter_map(std::env::var_os)
+    {
+        let mut versions: Vec<PathBuf> = std::fs::read_dir(PathBuf::from(sdk).join("build-tools"))
+            .into_iter()
+            .flatten()
+            .filter_map(Result::ok)
+            .map(|x| x.path())
+            .collect();
+        versions.sort_by_key(|x| std::cmp::Reverse(build_tools_version(x)));
+        folders.extend(versions);
+    }
+    folders.extend(
+        std::env::var_os("PATH")
+            .iter()
+            .flat_map(std::env::split_paths),
+    );
+    folders
+        .into_iter()
+        .find(|folder| folder.join(zipalign).is_file() && folder.join(apksigner).is_file())
+        .map(|folder| (folder.join(zipalign), folder.join(apksigner)))
+}
+
+/// Zipaligns and signs the apk in place. The keys of [android.signing] go through the
+/// apksigner of the Android SDK, which reads the passwords from the environment
+async fn sign_apk(builder: &Builder, config: &AndroidConfig, apk: &Path) -> color_eyre::Result<()> {
+    let Credentials {
+        keystore,
+        alias,
+        passwords,
+    } = credentials(builder, config).await?;
+    let Passwords::Env { store, key } = passwords else {
+        return sign_debug_apk(&builder.home, &keystore, apk).await;
+    };
+
+    let (zipalign, apksigner) = find_build_tools()
+        .wrap_err(
+            "apksigner was not found, it is needed to sign with the keystore of [android.signing]",
+        )
+        .suggestion(
+            "Install the build-tools of the Android SDK and set ANDROID_HOME, or add them to PATH",
+        )?;
+    let aligned = apk.with_extension("aligned.apk");
+    let status = Command::new(zipalign)
+        .args(["-p", "-f", "4"])
+        .arg(apk)
+        .arg(&aligned)
+        .status()
+        .await
+        .context("Failed to spawn zipalign")?;
+    if !status.success() {
+        return Err(eyre!("zipalign failed with {}", status));
+    }
+
+    let status = Command::new(apksigner)
+        .arg("sign")
+        .arg("--ks")
+        .arg(&keystore)
+        .args([
+            "--ks-key-alias",
+            &alias,
+            "--ks-pass",
+            &format!("env:{}", store),
+            "--key-pass",
+            &format!("env:{}", key),
+            "--out",
+        ])
+        .arg(apk)
+        .arg(&aligned)
+        .status()
+        .await
+        .context("Failed to spawn apksigner")?;
+    fs_err::tokio::remove_file(&aligned).await?;
+    if !status.success() {
+        return Err(eyre!("apksigner failed with {}", status));
+    }
+
+    Ok(())
+}
+
+/// Zipaligns and signs the apk in place with uber-apk-signer and the debug keystore
+async fn sign_debug_apk(home: &HomeManager, keystore: &Path, apk: &Path) -> color_eyre::Result<()> {
+    home.ensure_signer().await?;
+    let status = Command::new(home.get_java_path())
+        .arg("-jar")
+        .arg(home.get_signer_path())
+        .arg("--apks")
+        .arg(apk)
+        .arg("--ks")
+        .arg(keystore)
+        .args([
+            "--ksAlias",
+            DEBUG_KEY_ALIAS,
+            "--ksPass",
+            DEBUG_KEY_PASSWORD,
+            "--ksKeyPass",
+            DEBUG_KEY_PASSWORD,
+            "--allowResign",
+            "--overwrite",
+        ])
+        .status()
+        .await
+        .context("Failed to spawn uber-apk-signer")?;
+    if !status.success() {
+        return Err(eyre!("uber-apk-signer failed with {}", status));
+    }
+
+    Ok(())
+}
+
+/// The JRE kaledis downloads has no jarsigner, so it has to come from a JDK
+fn find_jarsigner() -> Option<PathBuf> {
+    let name = if cfg!(windows) {
+        "jarsigner.exe"
+    } else {
+        "jarsigner"
+    };
+    std::env::var_os("JAVA_HOME")
+        .map(|home| PathBuf::from(home).join("bin"))
+        .into_iter()
+        .chain(
+            std::env::var_os("PATH")
+                .iter()
+                .flat_map(std::env::split_paths),
+        )
+        .map(|folder| folder.join(name))
+        .find(|path| path.is_file())
+}
+
+/// Signs the bundle with jarsigner, which is what the Play Console expects for upload keys
+async fn sign_bundle(
+    builder: &Builder,
+    config: &AndroidConfig,
+    aab: &Path,
+) -> color_eyre::Result<()> {
+    // An unsigned bundle can't be uploaded, so this fails instead of skipping the signature
+    let jarsigner = find_jarsigner()
+        .wrap_err("jarsigner was not found, it is needed to sign the bundle")
+        .suggestion("Install a JDK and add its bin folder to PATH or set JAVA_HOME")?;
+    let Credentials {
+        keystore,
+        alias,
+        passwords,
+    } = credentials(builder, config).await?;
+    let mut command = Command::new(jarsigner);
+    // jarsigner reads the passwords from the environment variables named after :env
+    let (store, key) = match passwords {
+        Passwords::Env { store, key } => (store, key),
+        Passwords::Debug => {
+            command.env("KALEDIS_DEBUG_KEY_PASSWORD", DEBUG_KEY_PASSWORD);
+            (
+                "KALEDIS_DEBUG_KEY_PASSWORD".to_string(),
+                "KALEDIS_DEBUG_KEY_PASSWORD".to_string(),
+            )
+        }
+    };
+
+    let status = command
+        .arg("-keystore")
+        .arg(&keystore)
+        .args([
+            "-storepass:env",
+            &store,
+            "-keypass:env",
+            &key,
+            "-sigalg",
+            "SHA256withRSA",
+            "-digestalg",
+            "SHA-256",
+        ])
+        .arg(aab)
+        .arg(&alias)
+        .status()
+        .await
+        .context("Failed to spawn jarsigner")?;
+    if !status.success() {
+        return Err(eyre!("jarsigner failed with {}", status));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(mode: &str) -> AndroidConfig {
+        toml::from_str(&format!(
+            r#"
+version_code = 7
+game_id = "com.example.game"
+version_name = "1.2"
+game_name = "My Game"
+min_sdk = 21
+manifest_mode = "{}"
+"#,
+            mode
+        ))
+        .unwrap()
+    }
+
+    const CUSTOM: &str = r#"<manifest xmlns:android="http://schemas.android.com/apk/res/android" android:versionName="${version_code}-beta">
+    <uses-permission android:name="android.permission.CAMERA"/>
+    <uses-permission android:name="android.permission.INTERNET" android:maxSdkVersion="30"/>
+    <uses-sdk android:targetSdkVersion="34"/>
+    <application android:label="${game_name} (${game_id})">
+        <meta-data android:name="${game_id}.key" android:value="${nope}"/>
+        <activity android:name="org.love2d.android.GameActivity" android:screenOrientation="landscape"/>
+    </application>
+</manifest>"#;
+
+    fn named<'a>(element: &'a XmlElement, name: &str) -> Vec<&'a XmlElement> {
+        element.elements().filter(|x| x.name == name).collect()
+    }
+
+    #[test]
+    fn expands_the_placeholders() {
+        let config = config("Replace");
+        let text = "${game_id} ${version_code} ${version_name} ${game_name} ${project_name} ${min_sdk} ${target_sdk} ${nope}";
+        assert_eq!(
+            apply_placeholders(text, &config.placeholders("game")),
+            "com.example.game 7 1.2 My Game game 21  ${nope}"
+        );
+    }
+
+    #[test]
+    fn replaces_the_generated_manifest() {
+        let config = config("Replace");
+        let manifest = custom_manifest(&config, "game", config.manifest("game"), CUSTOM).unwrap();
+        assert_eq!(
+            manifest,
+            CUSTOM
+                .replace("${version_code}", "7")
+                .replace("${game_name}", "My Game")
+                .replace("${game_id}", "com.example.game")
+        );
+    }
+
+    #[test]
+    fn merges_into_the_generated_manifest() {
+        let config = config("Merge");
+        let text = custom_manifest(&config, "game", config.manifest("game"), CUSTOM).unwrap();
+        let manifest = XmlElement::parse(&text).unwrap();
+        assert_eq!(manifest.get_attr("package"), Some("com.example.game"));
+        assert_eq!(manifest.get_attr("android:versionCode"), Some("7"));
+        assert_eq!(manifest.get_attr("android:versionName"), Some("7-beta"));
+
+        // Elements with the same android:name are merged, the others added
+        let permissions = named(&manifest, "uses-permission");
+        let internet: Vec<_> = permissions
+            .iter()
+            .filter(|x| x.get_attr("android:name") == Some("android.permission.INTERNET"))
+            .collect();
+        assert_eq!(internet.len(), 1);
+        assert_eq!(internet[0].get_attr("android:maxSdkVersion"), Some("30"));
+        assert!(
+            permissions
+                .iter()
+                .any(|x| x.get_attr("android:name") == Some("android.permission.CAMERA"))
+        );
+
+        // The singletons are merged even without a name
+        let sdk = named(&manifest, "uses-sdk");
+        assert_eq!(sdk.len(), 1);
+        assert_eq!(sdk[0].get_attr("android:minSdkVersion"), Some("21"));
+        assert_eq!(sdk[0].get_attr("android:targetSdkVersion"), Some("34"));
+        let application = named(&manifest, "application");
+        assert_eq!(application.len(), 1);
+        assert_eq!(
+            application[0].get_attr("android:label"),
+            Some("My Game (com.example.game)")
+        );
+        assert_eq!(application[0].get_attr("android:allowBackup"), Some("true"));
+
+        let activity = named(application[0], "activity");
+        assert_eq!(activity.len(), 1);
+        assert_eq!(
+            activity[0].get_attr("android:screenOrientation"),
+            Some("landscape")
+        );
+        assert_eq!(
+            activity[0].get_attr("android:launchMode"),
+            Some("singleInstance")
+        );
+        assert_eq!(named(activity[0], "intent-filter").len(), 2);
+        let metadata = named(application[0], "meta-data");
+        assert_eq!(
+            metadata[0].get_attr("android:name"),
+            Some("com.example.game.key")
+        );
+        assert_eq!(metadata[0].get_attr("android:value"), Some("${nope}"));
+    }
+
+    #[test]
+    fn reports_broken_custom_manifests_when_merging() {
+        let config = config("Merge");
+        assert!(custom_manifest(&config, "game", config.manifest("game"), "<manifest>").is_err());
+        // Replacing uses the text as it is
+        let config = self::config("Replace");
+        assert!(custom_manifest(&config, "game", config.manifest("game"), "<manifest>").is_ok());
+    }
+}
@@ -0,0 +1,267 @@
+use std::path::Path;
+
+use color_eyre::{
+    Section,
+    eyre::{Context, ContextCompat},
+};
+use image::{DynamicImage, Rgba, RgbaImage, imageops, imageops::FilterType};
+
+use crate::{commands::build::Builder, toml_conf::AndroidConfig, xml_utils::XmlElement};
+
+// Adaptive icons are made of two 108dp layers, only the center 72dp is visible
+// and launchers can mask anything outside the 66dp safe zone.
+// https://developer.android.com/develop/ui/views/launch/icon_design_adaptive
+const LAYER_DP: u32 = 108;
+const VISIBLE_DP: u32 = 72;
+const SAFE_ZONE_DP: u32 = 66;
+const LEGACY_DP: u32 = 48;
+
+// (qualifier, scale * 2) so xhdpi and friends stay integers
+const DENSITIES: [(&str, u32); 5] = [
+    ("mdpi", 2),
+    ("hdpi", 3),
+    ("xhdpi", 4),
+    ("xxhdpi", 6),
+    ("xxxhdpi", 8),
+];
+
+enum Background {
+    Color(Rgba<u8>),
+    Image(DynamicImage),
+}
+
+/// Parses android colors, #RGB, #RRGGBB and #AARRGGBB
+fn parse_color(color: &str) -> Option<Rgba<u8>> {
+    let hex = color.strip_prefix('#')?;
+    let hex = match hex.len() {
+        3 => hex.chars().flat_map(|x| [x, x]).collect(),
+        6 => hex.to_string(),
+        8 => format!("{}{}", &hex[2..], &hex[..2]),
+        _ => return None,
+    };
+    let mut bytes = [255; 4];
+    for (index, byte) in bytes.iter_mut().enumerate().take(hex.len() / 2) {
+        *byte = u8::from_str_radix(hex.get(index * 2..index * 2 + 2)?, 16).ok()?;
+    }
+    Some(Rgba(bytes))
+}
+
+/// Resizes keeping the aspect ratio and centers the result in a transparent canvas
+pub fn fit(image: &DynamicImage, width: u32, height: u32) -> RgbaImage {
+    let resized = image.resize(width, height, FilterType::Lanczos3).to_rgba8();
+    let mut canvas = RgbaImage::new(width, height);
+    imageops::overlay(
+        &mut canvas,
+        &resized,
+        ((width - resized.width()) / 2) as i64,
+        ((height - resized.height()) / 2) as i64,
+    );
+    canvas
+}
+
+fn foreground_layer(foreground: &DynamicImage, size: u32) -> RgbaImage {
+    let safe_zone = size * SAFE_ZONE_DP / LAYER_DP;
+    let mut canvas = RgbaImage::new(size, size);
+    imageops::overlay(
+        &mut canvas,
+        &fit(foreground, safe_zone, safe_zone),
+        ((size - safe_zone) / 2) as i64,
+        ((size - safe_zone) / 2) as i64,
+    );
+    canvas
+}
+
+fn background_layer(background: &Background, size: u32) -> RgbaImage {
+    match background {
+        Background::Color(color) => RgbaImage::from_pixel(size, size, *color),
+        Background::Image(image) => image
+            .resize_to_fill(size, size, FilterType::Lanczos3)
+            .to_rgba8(),
+    }
+}
+
+/// Icon for launchers older than android 8, the visible part of both layers
+fn legacy_icon(
+    foreground: &DynamicImage,
+    background: &Background,
+    size: u32,
+    round: bool,
+) -> RgbaImage {
+    let layer_size = size * LAYER_DP / VISIBLE_DP;
+    let mut layer = background_layer(background, layer_size);
+    imageops::overlay(&mut layer, &foreground_layer(foreground, layer_size), 0, 0);
+    let offset = (layer_size - size) / 2;
+    let mut icon = imageops::crop_imm(&layer, offset, offset, size, size).to_image();
+
+    if round {
+        let radius = size as f32 / 2.0;
+        for (x, y, pixel) in icon.enumerate_pixels_mut() {
+            let distance =
+                ((x as f32 + 0.5 - radius).powi(2) + (y as f32 + 0.5 - radius).powi(2)).sqrt();
+            // One pixel of anti aliasing on the border
+            let coverage = (radius - distance + 0.5).clamp(0.0, 1.0);
+            pixel[3] = (pixel[3] as f32 * coverage) as u8;
+        }
+    }
+    icon
+}
+
+/// Writes the adaptive, legacy and round launcher icons into the decoded apk
+pub fn write_adaptive_icons(
+    builder: &Builder,
+    config: &AndroidConfig,
+    build_folder: &Path,
+) -> color_eyre::Result<()> {
+    let root = &builder.paths.root;
+    let foreground = config
+        .icon_foreground
+        .as_ref()
+        .or(builder.config.icon.as_ref())
+        .wrap_err("No foreground for the adaptive icon")
+        .suggestion("Set android.icon_foreground or the icon of the project")?;
+    let foreground = image::open(root.join(foreground))
+        .with_context(|| format!("Opening the icon foreground {}", foreground))?;
+
+    let background = match config.icon_background.as_deref() {
+        None => Background::Color(Rgba([255; 4])),
+        Some(color) if color.starts_with('#') => Background::Color(
+            parse_color(color)
+                .wrap_err_with(|| format!("Invalid icon background color {}", color))?,
+        ),
+        Some(path) => Background::Image(
+            image::open(root.join(path))
+                .with_context(|| format!("Opening the icon background {}", path))?,
+        ),
+    };
+
+    let res = build_folder.join("res");
+    for (density, scale) in DENSITIES {
+        let folder = res.join(format!("mipmap-{}", density));
+        fs_err::create_dir_all(&folder)?;
+
+        let layer_size = LAYER_DP * scale / 2;
+        foreground_layer(&foreground, layer_size)
+            .save(folder.join("ic_launcher_foreground.png"))?;
+        background_layer(&background, layer_size)
+            .save(folder.join("ic_launcher_background.png"))?;
+
+        let legacy_size = LEGACY_DP * scale / 2;
+        legacy_icon(&foreground, &background, legacy_size, false)
+            .save(folder.join("ic_launcher.png"))?;
+        legacy_icon(&foreground, &background, legacy_size, true)
+            .save(folder.join("ic_launcher_round.png"))?;
+    }
+
+    let adaptive = XmlElement::new("adaptive-icon")
+        .attr(
+            "xmlns:android",
+            "http://schemas.android.com/apk/res/android",
+        )
+        .child(
+            XmlElement::new("background")
+                .attr("android:drawable", "@mipmap/ic_launcher_background"),
+        )
+        .child(
+            XmlElement::new("foreground")
+                .attr("android:drawable", "@mipmap/ic_launcher_foreground"),
+        )
+        .to_document();
+    let folder = res.join("mipmap-anydpi-v26");
+    fs_err::create_dir_all(&folder)?;
+    fs_err::write(folder.join("ic_launcher.xml"), &adaptive)?;
+    fs_err::write(folder.join("ic_launcher_round.xml"), &adaptive)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RED: Rgba<u8> = Rgba([255, 0, 0, 255]);
+    const BLUE: Rgba<u8> = Rgba([0, 0, 255, 255]);
+
+    fn solid(width: u32, height: u32) -> DynamicImage {
+        DynamicImage::ImageRgba8(RgbaImage::from_pixel(width, height, RED))
+    }
+
+    /// The first and last column and row with something drawn
+    fn drawn_bounds(image: &RgbaImage) -> (u32, u32, u32, u32) {
+        let drawn: Vec<(u32, u32)> = image
+            .enumerate_pixels()
+            .filter(|(_, _, pixel)| pixel[3] > 0)
+            .map(|(x, y, _)| (x, y))
+            .collect();
+        (
+            drawn.iter().map(|x| x.0).min().unwrap(),
+            drawn.iter().map(|x| x.1).min().unwrap(),
+            drawn.iter().map(|x| x.0).max().unwrap(),
+            drawn.iter().map(|x| x.1).max().unwrap(),
+        )
+    }
+
+    #[test]
+    fn parses_android_colors() {
+        assert_eq!(parse_color("#abc"), Some(Rgba([0xaa, 0xbb, 0xcc, 255])));
+        assert_eq!(parse_color("#1E1E2E"), Some(Rgba([0x1e, 0x1e, 0x2e, 255])));
+        // The alpha comes first
+        assert_eq!(
+            parse_color("#801E1E2E"),
+            Some(Rgba([0x1e, 0x1e, 0x2e, 0x80]))
+        );
+        assert_eq!(parse_color("#12"), None);
+        assert_eq!(parse_color("#12345g"), None);
+        assert_eq!(parse_color("red"), None);
+    }
+
+    #[test]
+    fn keeps_the_foreground_in_the_safe_zone() {
+        for (_, scale) in DENSITIES {
+            let size = LAYER_DP * scale / 2;
+            // 66 of the 108dp, centered
+            let safe = SAFE_ZONE_DP * scale / 2;
+            let start = (size - safe) / 2;
+            let end = start + safe - 1;
+
+            let layer = foreground_layer(&solid(300, 300), size);
+            assert_eq!(layer.dimensions(), (size, size));
+            assert_eq!(drawn_bounds(&layer), (start, start, end, end));
+
+            // Wide images keep their aspect ratio in the middle of the safe zone
+            let layer = foreground_layer(&solid(300, 150), size);
+            let (left, top, right, bottom) = drawn_bounds(&layer);
+            assert_eq!((left, right), (start, end));
+            assert_eq!(bottom - top + 1, safe.div_ceil(2));
+            // Odd margins can't be split evenly
+            assert!((top - start).abs_diff(end - bottom) <= 1);
+        }
+    }
+
+    #[test]
+    fn crops_the_visible_part_for_legacy_icons() {
+        let background = Background::Color(BLUE);
+        for (_, scale) in DENSITIES {
+            let size = LEGACY_DP * scale / 2;
+            let icon = legacy_icon(&solid(300, 300), &background, size, false);
+            assert_eq!(icon.dimensions(), (size, size));
+            // The 72dp visible out of 108dp, so the 66dp safe zone leaves 3dp on each side
+            let margin = 3 * size / VISIBLE_DP;
+            assert_eq!(*icon.get_pixel(margin - 1, margin - 1), BLUE);
+            assert_eq!(*icon.get_pixel(margin, size / 2), RED);
+            assert_eq!(*icon.get_pixel(size - margin - 1, size / 2), RED);
+            assert_eq!(*icon.get_pixel(size - margin, size - margin), BLUE);
+        }
+    }
+
+    #[test]
+    fn rounds_the_legacy_round_icon() {
+        let icon = legacy_icon(&solid(300, 300), &Background::Color(BLUE), 96, true);
+        for (x, y) in [(0, 0), (95, 0), (0, 95), (95, 95), (10, 10)] {
+            assert_eq!(icon.get_pixel(x, y)[3], 0, "{},{}", x, y);
+        }
+        assert_eq!(*icon.get_pixel(48, 48), RED);
+        // The middle of the sides is inside of the circle
+        assert_eq!(*icon.get_pixel(48, 1), BLUE);
+        assert_eq!(*icon.get_pixel(1, 48), BLUE);
+    }
+}
@@ -0,0 +1,546 @@
+use std::{
+    collections::BTreeSet,
+    path::{Path, PathBuf},
+};
+
+use clap::ValueEnum;
+use color_eyre::{
+    Section,
+    eyre::{Context, eyre},
+};
+use colored::Colorize;
+use indexmap::IndexMap;
+use indicatif::ProgressBar;
+use serde::Deserialize;
+use serde_json::{Map, Value, json};
+
+use crate::{
+    commands::build::{
+        Builder,
+        assets::love_path,
+        maps::{resolve, write_lua},
+    },
+    home_manager::Target,
+    toml_conf::AsepriteConfig,
+};
+
+#[derive(Deserialize, Clone, Copy)]
+struct Rect {
+    x: i64,
+    y: i64,
+    w: i64,
+    h: i64,
+}
+
+#[derive(Deserialize, Clone, Copy)]
+struct Size {
+    w: i64,
+    h: i64,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Frame {
+    frame: Rect,
+    #[serde(default)]
+    rotated: bool,
+    sprite_source_size: Rect,
+    source_size: Size,
+    /// Milliseconds
+    duration: u32,
+}
+
+#[derive(Deserialize)]
+struct NamedFrame {
+    filename: String,
+    #[serde(flatten)]
+    frame: Frame,
+}
+
+// "Hash" and "Array" in the export dialog, the hash keeps the frames in the order of its keys
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Frames {
+    Array(Vec<NamedFrame>),
+    Hash(IndexMap<String, Frame>),
+}
+
+#[derive(Deserialize)]
+struct Tag {
+    name: String,
+    from: usize,
+    to: usize,
+    #[serde(default = "forward")]
+    direction: String,
+    /// Saved as a string, missing or "0" loops forever
+    #[serde(default)]
+    repeat: Option<Value>,
+}
+
+fn forward() -> String {
+    "forward".to_string()
+}
+
+#[derive(Deserialize)]
+struct Point {
+    x: i64,
+    y: i64,
+}
+
+#[derive(Deserialize)]
+struct SliceKey {
+    frame: usize,
+    bounds: Rect,
+    center: Option<Rect>,
+    pivot: Option<Point>,
+}
+
+#[derive(Deserialize)]
+struct Slice {
+    name: String,
+    color: Option<String>,
+    data: Option<String>,
+    keys: Vec<SliceKey>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Meta {
+    image: String,
+    size: Size,
+    #[serde(default)]
+    frame_tags: Vec<Tag>,
+    #[serde(default)]
+    slices: Vec<Slice>,
+}
+
+#[derive(Deserialize)]
+struct Sheet {
+    frames: Frames,
+    meta: Meta,
+}
+
+impl Sheet {
+    fn frames(&self) -> Vec<(&str, &Frame)> {
+        match &self.frames {
+            Frames::Array(frames) => frames
+                .iter()
+                .map(|x| (x.filename.as_str(), &x.frame))
+                .collect(),
+            Frames::Hash(frames) => frames.iter().map(|(x, y)| (x.as_str(), y)).collect(),
+        }
+    }
+}
+
+fn rect(rect: Rect) -> Value {
+    json!({ "x": rect.x, "y": rect.y, "width": rect.w, "height": rect.h })
+}
+
+/// The frames a tag plays in order, counted from 1
+fn tag_frames(tag: &Tag) -> color_eyre::Result<Vec<usize>> {
+    let (from, to) = (tag.from + 1, tag.to + 1);
+    let forward: Vec<usize> = (from..=to).collect();
+    let reverse: Vec<usize> = (from..=to).rev().collect();
+    // The ends aren't repeated when bouncing back
+    let inner = |frames: &[usize]| frames[1..frames.len().saturating_sub(1).max(1)].to_vec();
+    Ok(match tag.direction.as_str() {
+        "forward" => forward,
+        "reverse" => reverse,
+        "pingpong" => [forward, inner(&reverse)].concat(),
+        "pingpong_reverse" => [reverse, inner(&(from..=to).collect::<Vec<_>>())].concat(),
+        other => {
+            return Err(eyre!(
+                "The tag {} has an unknown direction {}",
+                tag.name,
+                other
+            ));
+        }
+    })
+}
+
+/// The table of a sheet, without the path of its image. The frames and tags are checked
+/// against the image and the sheet
+fn sheet_table(
+    sheet: &Sheet,
+    image: &str,
+    width: u32,
+    height: u32,
+) -> color_eyre::Result<Map<String, Value>> {
+    if (width as i64, height as i64) != (sheet.meta.size.w, sheet.meta.size.h) {
+        return Err(eyre!(
+            "{} is {}x{} but the sheet was exported for {}x{}",
+            image,
+            width,
+            height,
+            sheet.meta.size.w,
+            sheet.meta.size.h
+        ))
+        .suggestion("Export the sheet and its JSON data again");
+    }
+
+    let frames = sheet.frames();
+    let mut values = vec![];
+    for (name, frame) in &frames {
+        let bounds = frame.frame;
+        if frame.rotated {
+            return Err(eyre!(
+                "The frame {} is rotated, quads can't be rotated",
+                name
+            ));
+        }
+        if bounds.x < 0
+            || bounds.y < 0
+            || bounds.x + bounds.w > width as i64
+            || bounds.y + bounds.h > height as i64
+        {
+            return Err(eyre!(
+                "The frame {} goes outside of {}, it is at {},{} sized {}x{}",
+                name,
+                image,
+                bounds.x,
+                bounds.y,
+                bounds.w,
+                bounds.h
+            ))
+            .suggestion("Export the sheet and its JSON data again");
+        }
+        let mut value = rect(bounds).as_object().unwrap().clone();
+        value.insert("name".to_string(), json!(name));
+        value.insert(
+            "duration".to_string(),
+            json!(frame.duration as f64 / 1000.0),
+        );
+        // Trimmed frames are drawn moved by the space that was cut
+        value.insert("offsetx".to_string(), json!(frame.sprite_source_size.x));
+        value.insert("offsety".to_string(), json!(frame.sprite_source_size.y));
+        value.insert("sourcewidth".to_string(), json!(frame.source_size.w));
+        value.insert("sourceheight".to_string(), json!(frame.source_size.h));
+        values.push(Value::Object(value));
+    }
+
+    let mut tags = Map::new();
+    for tag in &sheet.meta.frame_tags {
+        if tag.from > tag.to || tag.to >= frames.len() {
+            return Err(eyre!(
+                "The tag {} goes from frame {} to {} but the sheet has {}",
+                tag.name,
+                tag.from,
+                tag.to,
+                frames.len()
+            ));
+        }
+        let order = tag_frames(tag)?;
+        let durations: Vec<u32> = order.iter().map(|x| frames[x - 1].1.duration).collect();
+        let repeat = match &tag.repeat {
+            Some(Value::String(count)) => count.parse().unwrap_or(0),
+            Some(Value::Number(count)) => count.as_u64().unwrap_or(0),
+            _ => 0,
+        };
+        tags.insert(
+            tag.name.clone(),
+            json!({
+                "direction": tag.direction,
+                "frames": order,
+                "durations": durations.iter().map(|x| *x as f64 / 1000.0).collect::<Vec<_>>(),
+                "duration": durations.iter().sum::<u32>() as f64 / 1000.0,
+                "repeat": repeat,
+            }),
+        );
+    }
+
+    let mut slices = Map::new();
+    for slice in &sheet.meta.slices {
+        let keys: Vec<Value> = slice
+            .keys
+            .iter()
+            .map(|key| {
+                let mut value = rect(key.bounds).as_object().unwrap().clone();
+                value.insert("frame".to_string(), json!(key.frame + 1));
+                if let Some(center) = key.center {
+                    value.insert("center".to_string(), rect(center));
+                }
+                if let Some(pivot) = &key.pivot {
+                    value.insert("pivot".to_string(), json!({ "x": pivot.x, "y": pivot.y }));
+                }
+                Value::Object(value)
+            })
+            .collect();
+        slices.insert(
+            slice.name.clone(),
+            json!({ "color": slice.color, "data": slice.data, "keys": keys }),
+        );
+    }
+
+    let mut table = Map::new();
+    table.insert("width".to_string(), json!(width));
+    table.insert("height".to_string(), json!(height));
+    table.insert("frames".to_string(), json!(values));
+    table.insert("tags".to_string(), Value::Object(tags));
+    table.insert("slices".to_string(), Value::Object(slices));
+    Ok(table)
+}
+
+impl Builder {
+    fn aseprite_config(&self) -> Option<&AsepriteConfig> {
+        self.config.assets.as_ref()?.aseprite.as_ref()
+    }
+
+    fn sheet_files(&self, config: &AsepriteConfig) -> color_eyre::Result<BTreeSet<PathBuf>> {
+        Ok(self.layout_files(&config.files).into_iter().collect())
+    }
+
+    /// Whether a file is the data of a sheet, it is turned into a module instead of being added by itself
+    pub fn is_sheet_source(&self, path: &Path) -> bool {
+        self.aseprite_config()
+            .is_some_and(|config| self.in_layout(&config.files, path))
+    }
+
+    fn read_sheet(&self, path: &Path) -> color_eyre::Result<(Sheet, PathBuf)> {
+        let sheet: Sheet = serde_json::from_str(&fs_err::read_to_string(path)?)
+            .wrap_err("It isn't a sprite sheet exported by Aseprite")
+            .suggestion("Export it with the JSON data in the Hash or Array format")?;
+        let image = resolve(path.parent().unwrap(), &sheet.meta.image);
+        Ok((sheet, image))
+    }
+
+    /// The images the sheets draw from, the layout bundles them with its own files
+    pub fn sheet_images(&self) -> Vec<PathBuf> {
+        let Some(config) = self.aseprite_config() else {
+            return vec![];
+        };
+        let mut images = BTreeSet::new();
+        // Broken sheets are reported when the modules are generated
+        for file in self.sheet_files(config).unwrap_or_default() {
+            if let Ok((_, image)) = self.read_sheet(&file)
+                && image.is_file()
+            {
+                images.insert(image);
+            }
+        }
+        images.into_iter().collect()
+    }
+
+    /// The paths LÖVE opens the images the sheets draw from with, in the builds of any target
+    pub fn sheet_references(&self) -> BTreeSet<String> {
+        let Some(config) = self.aseprite_config() else {
+            return BTreeSet::new();
+        };
+        self.sheet_files(config)
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|x| self.read_sheet(x).ok())
+            .flat_map(|(_, image)| {
+                Target::value_variants()
+                    .iter()
+                    .filter_map(|target| self.destination(target, &image))
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    }
+
+    /// The path the module of a sheet loads its image with, assets.rules can move it but
+    /// the module is shared by every target
+    fn sheet_image_path(&self, image: &Path) -> color_eyre::Result<String> {
+        let paths: BTreeSet<String> = Target::value_variants()
+            .iter()
+            .filter_map(|target| self.destination(target, image))
+            .collect();
+        let mut iter = paths.iter();
+        match (iter.next(), iter.next()) {
+            (Some(path), None) => Ok(path.clone()),
+            (None, _) => Err(eyre!(
+                "The image {} is outside of the project",
+                image.display()
+            )),
+            (Some(first), Some(second)) => Err(eyre!(
+                "assets.rules moves {} to {} or {} depending on the target, the sheet can only load one of them",
+                love_path(&self.paths.root, image).unwrap_or_default(),
+                first,
+                second
+            ))
+            .suggestion("Change assets.rules so the image goes to the same path in every build"),
+        }
+    }
+
+    fn sheet_module(&self, path: &Path) -> color_eyre::Result<String> {
+        let (sheet, image) = self.read_sheet(path)?;
+        let image_path = self.sheet_image_path(&image)?;
+        let image_name = love_path(&self.paths.root, &image).unwrap_or_default();
+        let (width, height) = image::image_dimensions(&image)
+            .with_context(|| format!("Opening the image {}", image_name))?;
+        let mut table = sheet_table(&sheet, &image_name, width, height)?;
+        table.insert("image".to_string(), json!(image_path));
+
+        let mut lua = format!(
+            "-- Generated by kaledis from {}, changes are overwritten\nlocal sheet = ",
+            love_path(&self.paths.root, path).unwrap_or_default()
+        );
+        write_lua(&mut lua, &Value::Object(table), 0);
+        lua.push_str(
+            "
+
+-- Quads need love.graphics, without it the module only has the numbers
+if love and love.graphics then
+\tsheet.quads = {}
+\tfor index, frame in ipairs(sheet.frames) do
+\t\tsheet.quads[index] = love.graphics.newQuad(frame.x, frame.y, frame.width, frame.height, sheet.width, sheet.height)
+\tend
+end
+
+return sheet
+",
+        );
+        Ok(lua)
+    }
+
+    /// Turns the sheets of assets.aseprite into Lua modules inside the build folder,
+    /// assets/player.json is loaded with require("assets.player")
+    pub async fn import_sheets(&self) -> color_eyre::Result<()> {
+        let Some(config) = self.aseprite_config() else {
+            return Ok(());
+        };
+        let files = self.sheet_files(config)?;
+
+        let mut p = ProgressBar::new_spinner().with_message("Importing sprite sheets...");
+        p = self.progress_bar.add(p);
+        for file in &files {
+            let name = love_path(&self.paths.root, file).unwrap_or_default();
+            let lua = self
+                .sheet_module(file)
+                .with_context(|| format!("Importing {}", name.yellow()))?;
+            let output = self
+                .paths
+                .build
+                .join(file.strip_prefix(&self.paths.root)?.with_extension("lua"));
+            fs_err::tokio::create_dir_all(output.parent().unwrap()).await?;
+            if fs_err::tokio::read_to_string(&output).await.ok() != Some(lua.clone()) {
+                fs_err::tokio::write(&output, lua).await?;
+            }
+        }
+        p.finish_with_message(format!(
+            "{} Imported {} sprite {}",
+            "[+]".green(),
+            files.len(),
+            if files.len() == 1 { "sheet" } else { "sheets" }
+        ));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tag(from: usize, to: usize, direction: &str) -> Tag {
+        serde_json::from_value(json!({
+            "name": "walk",
+            "from": from,
+            "to": to,
+            "direction": direction,
+        }))
+        .unwrap()
+    }
+
+    fn frame(x: i64, duration: u32) -> Value {
+        json!({
+            "frame": { "x": x, "y": 0, "w": 16, "h": 16 },
+            "rotated": false,
+            "spriteSourceSize": { "x": 0, "y": 0, "w": 16, "h": 16 },
+            "sourceSize": { "w": 16, "h": 16 },
+            "duration": duration,
+        })
+    }
+
+    /// A sheet of three 16x16 frames in a row, exported as a hash or an array. The names
+    /// don't sort in the order of the frames, the hash keeps the order of its keys
+    fn sheet(array: bool, tags: Value) -> Sheet {
+        let frames: Vec<String> = [("run", 0, 100), ("jump", 16, 200), ("attack", 32, 300)]
+            .iter()
+            .map(|(name, x, duration)| {
+                let frame = frame(*x, *duration).to_string();
+                match array {
+                    true => format!("{{\"filename\": {:?}, {}", name, &frame[1..]),
+                    false => format!("{:?}: {}", name, frame),
+                }
+            })
+            .collect();
+        let frames = match array {
+            true => format!("[{}]", frames.join(", ")),
+            false => format!("{{{}}}", frames.join(", ")),
+        };
+        let meta = json!({ "image": "hero.png", "size": { "w": 48, "h": 16 }, "frameTags": tags });
+        serde_json::from_str(&format!("{{\"frames\": {}, \"meta\": {}}}", frames, meta)).unwrap()
+    }
+
+    #[test]
+    fn plays_tags_in_every_direction() {
+        let frames = |from, to, direction| tag_frames(&tag(from, to, direction)).unwrap();
+        assert_eq!(frames(1, 3, "forward"), [2, 3, 4]);
+        assert_eq!(frames(1, 3, "reverse"), [4, 3, 2]);
+        // The ends aren't played twice in a row when the animation loops
+        assert_eq!(frames(1, 3, "pingpong"), [2, 3, 4, 3]);
+        assert_eq!(frames(1, 3, "pingpong_reverse"), [4, 3, 2, 3]);
+        assert_eq!(frames(0, 1, "pingpong"), [1, 2]);
+        assert_eq!(frames(2, 2, "pingpong_reverse"), [3]);
+        assert!(tag_frames(&tag(0, 1, "sideways")).is_err());
+    }
+
+    #[test]
+    fn reads_hash_and_array_sheets() {
+        let tags =
+            json!([{ "name": "walk", "from": 0, "to": 2, "direction": "pingpong", "repeat": "2" }]);
+        let hash = sheet_table(&sheet(false, tags.clone()), "hero.png", 48, 16).unwrap();
+        let array = sheet_table(&sheet(true, tags), "hero.png", 48, 16).unwrap();
+        assert_eq!(hash, array);
+
+        let names: Vec<&Value> = hash["frames"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|x| &x["name"])
+            .collect();
+        assert_eq!(names, ["run", "jump", "attack"]);
+        assert_eq!(
+            hash["tags"]["walk"],
+            json!({
+                "direction": "pingpong",
+                "frames": [1, 2, 3, 2],
+                "durations": [0.1, 0.2, 0.3, 0.2],
+                "duration": 0.8,
+                "repeat": 2,
+            })
+        );
+    }
+
+    #[test]
+    fn rejects_tags_outside_of_the_sheet() {
+        for (from, to) in [(1, 3), (2, 1)] {
+            let tags = json!([{ "name": "walk", "from": from, "to": to }]);
+            let error = sheet_table(&sheet(false, tags), "hero.png", 48, 16).unwrap_err();
+            assert_eq!(
+                error.to_string(),
+                format!(
+                    "The tag walk goes from frame {} to {} but the sheet has 3",
+                    from, to
+                )
+            );
+        }
+    }
+
+    #[test]
+    fn checks_the_size_of_the_image() {
+        let error = sheet_table(&sheet(false, json!([])), "hero.png", 32, 16).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "hero.png is 32x16 but the sheet was exported for 48x16"
+        );
+
+        let mut wide = sheet(false, json!([]));
+        wide.meta.size.w = 40;
+        let error = sheet_table(&wide, "hero.png", 40, 16).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "The frame attack goes outside of hero.png, it is at 32,0 sized 16x16"
+        );
+    }
+}
@@ -0,0 +1,663 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    path::{Path, PathBuf},
+};
+
+use color_eyre::{Section, eyre::eyre};
+use colored::Colorize;
+use full_moon::{
+    ast::{
+        Call, Expression, FunctionArgs, FunctionCall, Index, LocalAssignment, Prefix, Suffix, Var,
+        VarExpression,
+    },
+    tokenizer::TokenType,
+    visitors::Visitor,
+};
+use walkdir::WalkDir;
+
+use crate::{
+    commands::build::{Builder, atlas::ATLAS_FOLDER, fonts::FONT_FOLDER, layout::dev_target},
+    dalbit::{
+        transpile::{CollectAssetLiterals, FILESYSTEM_READERS},
+        utils::parse_file,
+    },
+    home_manager::Target,
+    toml_conf::AssetsConfig,
+};
+
+/// Where the asset module is generated, relative to the root of the project
+pub const ASSETS_FOLDER: &str = ".kaledis/assets";
+pub const ASSETS_DEFINITIONS: &str = ".kaledis/assets.d.luau";
+
+pub const KEYWORDS: [&str; 21] = [
+    "and", "break", "do", "else", "elseif", "end", "false", "for", "function", "if", "in", "local",
+    "nil", "not", "or", "repeat", "return", "then", "true", "until", "while",
+];
+
+pub enum AssetNode {
+    /// The path LÖVE opens the asset with
+    File(String),
+    Folder(BTreeMap<String, AssetNode>),
+}
+
+impl AssetNode {
+    fn get(&self, keys: &[String]) -> Option<&AssetNode> {
+        let mut node = self;
+        for key in keys {
+            match node {
+                AssetNode::Folder(children) => node = children.get(key)?,
+                AssetNode::File(_) => return None,
+            }
+        }
+        Some(node)
+    }
+
+    fn files(&self, paths: &mut Vec<String>) {
+        match self {
+            AssetNode::File(path) => paths.push(path.clone()),
+            AssetNode::Folder(children) => children.values().for_each(|x| x.files(paths)),
+        }
+    }
+}
+
+/// Turns a file or folder name into something that can be indexed with a dot
+fn identifier(name: &str) -> String {
+    let mut result: String = name
+        .chars()
+        .map(|x| if x.is_ascii_alphanumeric() { x } else { '_' })
+        .collect();
+    if result.is_empty() || result.starts_with(|x: char| x.is_ascii_digit()) {
+        result.insert(0, '_');
+    }
+    if KEYWORDS.contains(&result.as_str()) {
+        result.push('_');
+    }
+    result
+}
+
+/// The folder before the first wildcard of a glob, the keys of the module start from it
+pub fn glob_base(pattern: &str) -> PathBuf {
+    let mut base = PathBuf::new();
+    for component in Path::new(pattern).components() {
+        if component
+            .as_os_str()
+            .to_string_lossy()
+            .contains(['*', '?', '[', '{'])
+        {
+            return base;
+        }
+        base.push(component);
+    }
+    // Without wildcards the glob is a single file
+    base.pop();
+    base
+}
+
+fn insert(folder: &mut BTreeMap<String, AssetNode>, parts: &[String], path: String) {
+    match parts {
+        [] => {}
+        [name] => {
+            if folder.contains_key(name) {
+                tracing::warn!(
+                    "{} is matched by more than one layout glob, the asset module only keeps the first",
+                    path.yellow()
+                );
+            }
+            folder.entry(name.clone()).or_insert(AssetNode::File(path));
+        }
+        [name, rest @ ..] => {
+            if let AssetNode::Folder(children) = folder
+                .entry(name.clone())
+                .or_insert_with(|| AssetNode::Folder(BTreeMap::new()))
+            {
+                insert(children, rest, path);
+            }
+        }
+    }
+}
+
+/// Replaces the file names with keys, the extension is only kept when two files would share one
+fn with_keys(folder: BTreeMap<String, AssetNode>) -> BTreeMap<String, AssetNode> {
+    let short = |name: &str, node: &AssetNode| match node {
+        AssetNode::File(_) => identifier(
+            &Path::new(name)
+                .file_stem()
+                .map(|x| x.to_string_lossy().to_string())
+                .unwrap_or(name.to_string()),
+        ),
+        AssetNode::Folder(_) => identifier(name),
+    };
+    let mut count: HashMap<String, usize> = HashMap::new();
+    for (name, node) in &folder {
+        *count.entry(short(name, node)).or_default() += 1;
+    }
+
+    let mut result = BTreeMap::new();
+    for (name, node) in folder {
+        let mut key = short(&name, &node);
+        let node = match node {
+            AssetNode::Folder(children) => AssetNode::Folder(with_keys(children)),
+            file => {
+                if count[&key] > 1 {
+                    key = identifier(&name);
+                }
+                file
+            }
+        };
+        result.entry(key).or_insert(node);
+    }
+    result
+}
+
+/// The files matched by a glob of a layout list, without the ones the `!pattern` entries
+/// of the list take out and the ones the build packs or converts
+fn glob_files(builder: &Builder, globs: &[String], glb: &str) -> Vec<PathBuf> {
+    let mut list: Vec<String> = globs
+        .iter()
+        .filter(|x| x.starts_with('!'))
+        .cloned()
+        .collect();
+    list.push(glb.to_string());
+    builder
+        .layout_files(&list)
+        .into_iter()
+        .filter(|x| !builder.is_build_source(x))
+        .collect()
+}
+
+/// The path LÖVE opens a file of the project with, it always uses forward slashes
+pub fn love_path(root: &Path, path: &Path) -> Option<String> {
+    Some(
+        path.strip_prefix(root)
+            .ok()?
+            .iter()
+            .map(|x| x.to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/"),
+    )
+}
+
+/// The LÖVE paths of the files matched by some layout globs
+pub fn asset_paths(builder: &Builder, globs: &[String]) -> BTreeSet<String> {
+    builder
+        .layout_files(globs)
+        .iter()
+        .filter(|x| !builder.is_build_source(x))
+        .filter_map(|x| love_path(&builder.paths.root, x))
+        .collect()
+}
+
+/// Every file matched by layout.bundle and layout.external, nested by folder. The ones
+/// assets.rules moves are nested by the folder they go to
+pub fn asset_tree(builder: &Builder) -> AssetNode {
+    let root = &builder.paths.root;
+    let layout = &builder.config.layout;
+    let mut folder = BTreeMap::new();
+    for globs in [&layout.bundle, &layout.external] {
+        for glb in globs.iter().filter(|x| !x.starts_with('!')) {
+            let base = root.join(glob_base(glb));
+            for path in glob_files(builder, globs, glb) {
+                // The files assets.rules moves are added where they go
+                if globs == &layout.bundle && builder.has_rule(&path) {
+                    continue;
+                }
+                let (Some(relative), Ok(keys)) = (love_path(root, &path), path.strip_prefix(&base))
+                else {
+                    continue;
+                };
+                let parts: Vec<String> = keys
+                    .iter()
+                    .map(|x| x.to_string_lossy().to_string())
+                    .collect();
+                insert(&mut folder, &parts, relative);
+            }
+        }
+    }
+    for (destination, base) in builder.moved_files() {
+        let keys = match base.as_str() {
+            "" => destination.as_str(),
+            base => destination
+                .strip_prefix(base)
+                .map_or(destination.as_str(), |x| x.trim_start_matches('/')),
+        };
+        let parts: Vec<String> = keys.split('/').map(str::to_string).collect();
+        insert(&mut folder, &parts, destination.clone());
+    }
+    AssetNode::Folder(with_keys(folder))
+}
+
+fn write_table(out: &mut String, folder: &BTreeMap<String, AssetNode>, depth: usize, types: bool) {
+    let indent = "\t".repeat(depth);
+    for (key, node) in folder {
+        match (node, types) {
+            (AssetNode::File(_), true) => out.push_str(&format!("{}{}: string,\n", indent, key)),
+            (AssetNode::File(path), false) => {
+                out.push_str(&format!("{}{} = {:?},\n", indent, key, path))
+            }
+            (AssetNode::Folder(children), _) => {
+                out.push_str(&format!(
+                    "{}{}{}\n",
+                    indent,
+                    key,
+                    if types { ": {" } else { " = {" }
+                ));
+                write_table(out, children, depth + 1, types);
+                out.push_str(&format!("{}}},\n", indent));
+            }
+        }
+    }
+}
+
+/// Whether a .luaurc alias points to the generated module
+fn is_module_alias(path: &str) -> bool {
+    path.trim_start_matches("./").trim_end_matches('/') == ASSETS_FOLDER
+}
+
+/// Adds the alias of the asset module when .luaurc doesn't have it
+pub fn add_alias(aliases: &mut Vec<(String, String)>, config: &AssetsConfig) {
+    if !config.generate {
+        return;
+    }
+    match aliases.iter().find(|(name, _)| name == &config.alias) {
+        Some((_, path)) if !is_module_alias(path) => tracing::warn!(
+            "The @{} alias of .luaurc points to {}, so the asset module can't be required, set assets.alias to another name",
+            config.alias,
+            path
+        ),
+        Some(_) => {}
+        None => {
+            tracing::warn!(
+                "The LSP won't find the asset module, add \"{}\": \"{}\" to the aliases of .luaurc",
+                config.alias,
+                ASSETS_FOLDER
+            );
+            aliases.push((config.alias.clone(), ASSETS_FOLDER.to_string()));
+        }
+    }
+}
+
+impl Builder {
+    fn assets_config(&self) -> Option<AssetsConfig> {
+        let default = AssetsConfig::default();
+        let config = self.config.assets.as_ref().unwrap_or(&default);
+        (config.generate
+            && self
+                .aliases
+                .iter()
+                .any(|(name, path)| name == &config.alias && is_module_alias(path)))
+        .then(|| config.clone())
+    }
+
+    /// Writes the asset module and its LSP definitions into .kaledis
+    pub async fn generate_assets(&self) -> color_eyre::Result<()> {
+        if self.assets_config().is_none() {
+            return Ok(());
+        }
+        let AssetNode::Folder(tree) = asset_tree(self) else {
+            return Ok(());
+        };
+
+        let mut module = String::from(
+            "--!strict\n-- Generated by kaledis from layout.bundle and layout.external, changes are overwritten\n\nexport type Assets = {\n",
+        );
+        write_table(&mut module, &tree, 1, true);
+        module.push_str("}\n\nlocal assets: Assets = {\n");
+        write_table(&mut module, &tree, 1, false);
+        module.push_str("}\n\nreturn assets\n");
+
+        let mut paths = vec![];
+        AssetNode::Folder(tree).files(&mut paths);
+        let definitions = format!(
+            "-- Generated by kaledis, the path of every asset of the project\ntype AssetPath = {}\n",
+            if paths.is_empty() {
+                "never".to_string()
+            } else {
+                paths
+                    .iter()
+                    .map(|x| format!("{:?}", x))
+                    .collect::<Vec<_>>()
+                    .join("\n\t| ")
+            }
+        );
+
+        let folder = self.paths.root.join(ASSETS_FOLDER);
+        fs_err::tokio::create_dir_all(&folder).await?;
+        // Only written when they change, so editors don't reload them on every build
+        for (path, contents) in [
+            (folder.join("init.luau"), module),
+            (self.paths.root.join(ASSETS_DEFINITIONS), definitions),
+        ] {
+            if fs_err::tokio::read_to_string(&path).await.ok() != Some(contents.clone()) {
+                fs_err::tokio::write(&path, contents).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Fails when the code uses an asset that doesn't exist, through the asset module or
+    /// a path given to LÖVE. Release builds also report the assets nothing references
+    pub async fn check_assets(&self, targets: &[Target]) -> color_eyre::Result<()> {
+        let module = self.assets_config();
+        let root = &self.paths.root;
+        let tree = asset_tree(self);
+        let bundled = self.bundled_paths();
+        let external = asset_paths(self, &self.config.layout.external);
+        // The pages of the atlases and the fonts only exist once the assets are added
+        let generated: Vec<_> = self
+            .config
+            .assets
+            .iter()
+            .flat_map(|x| {
+                x.atlas
+                    .keys()
+                    .map(|x| format!("{}/{}_", ATLAS_FOLDER, x))
+                    .chain(x.fonts.keys().map(|x| format!("{}/{}.", FONT_FOLDER, x)))
+            })
+            .collect();
+        let android = targets.iter().any(Target::is_android);
+        // assets.rules can leave a file out of some targets, the code may still use it in others
+        let mut routed = vec![];
+        if self.has_rules() {
+            let dev = [dev_target()];
+            for target in if targets.is_empty() {
+                &dev[..]
+            } else {
+                targets
+            } {
+                let paths: BTreeSet<String> = self
+                    .bundle_files(target)?
+                    .into_iter()
+                    .map(|(_, destination)| destination)
+                    .collect();
+                routed.push((target.clone(), paths));
+                for from in self.empty_rules(target) {
+                    tracing::warn!(
+                        "The rule of assets.rules for {} gives the {} build no files, an earlier rule may take them",
+                        from.yellow(),
+                        target.name()
+                    );
+                }
+            }
+        }
+
+        let mut missing = vec![];
+        let mut used = HashSet::new();
+        for entry in WalkDir::new(&self.paths.src)
+            .into_iter()
+            .filter_entry(|x| {
+                x.depth() == 0
+                    || !(x.file_name().to_string_lossy().starts_with('.')
+                        || x.path() == self.paths.dist)
+            })
+            .filter_map(Result::ok)
+            .filter(|x| {
+                matches!(
+                    x.path().extension().and_then(|x| x.to_str()),
+                    Some("lua") | Some("luau")
+                )
+            })
+            .filter(|x| !self.is_ignored(x.path()))
+        {
+            // Syntax errors are reported by the transpiler
+            let Ok(ast) = parse_file(&entry.path().to_path_buf(), true) else {
+                continue;
+            };
+            let file = entry.path().strip_prefix(root).unwrap_or(entry.path());
+
+            let mut references = vec![];
+            if let Some(config) = &module {
+                let mut visitor = AssetReferences {
+                    alias: &config.alias,
+                    tree: &tree,
+                    locals: HashMap::new(),
+                    missing: vec![],
+                    used: vec![],
+                };
+                visitor.visit_ast(&ast);
+                for (line, message) in visitor.missing {
+                    missing.push(format!("{}:{}: {}", file.display(), line, message));
+                }
+                references = visitor.used;
+            }
+
+            let mut literals = CollectAssetLiterals::default();
+            literals.visit_ast(&ast);
+            used.extend(literals.literals);
+            for (function, path, line) in literals.loaded {
+                let path = path.trim_start_matches("./").trim_start_matches('/');
+                if generated.iter().any(|x| path.starts_with(x.as_str())) {
+                    continue;
+                }
+                if bundled.contains(path) || external.contains(path) {
+                    references.push((line, path.to_string()));
+                } else if FILESYSTEM_READERS.contains(&function.as_str()) {
+                    tracing::warn!(
+                        "{}:{}: {} reads {}, which isn't an asset, it only works if the game saved it",
+                        file.display(),
+                        line,
+                        function,
+                        path.yellow()
+                    );
+                } else {
+                    missing.push(format!(
+                        "{}:{}: {} loads {}, which isn't matched by the layout",
+                        file.display(),
+                        line,
+                        function,
+                        path.yellow()
+                    ));
+                }
+            }
+
+            for (line, path) in references {
+                // The apk only has the .love, the external assets are left behind
+                if android && !bundled.contains(&path) {
+                    tracing::warn!(
+                        "{}:{}: {} is an external asset, so the android build doesn't include it",
+                        file.display(),
+                        line,
+                        path.yellow()
+                    );
+                }
+                for (target, paths) in &routed {
+                    if bundled.contains(&path) && !paths.contains(&path) {
+                        tracing::warn!(
+                            "{}:{}: assets.rules leaves {} out of the {} build",
+                            file.display(),
+                            line,
+                            path.yellow(),
+                            target.name()
+                        );
+                    }
+                }
+                used.insert(path);
+            }
+        }
+
+        if !targets.is_empty() {
+            used.extend(self.map_references());
+            used.extend(self.sheet_references());
+            used.extend(self.shader_references());
+            // Only the assets of the targets being built
+            let shipped: BTreeSet<&String> = match routed.is_empty() {
+                true => bundled.iter().collect(),
+                false => routed.iter().flat_map(|(_, paths)| paths).collect(),
+            };
+            let unused: Vec<_> = shipped
+                .into_iter()
+                .chain(&external)
+                .filter(|x| !used.contains(*x))
+                .map(String::as_str)
+                .collect();
+            if !unused.is_empty() {
+                tracing::warn!(
+                    "These assets aren't referenced by the code, unless their paths are built at runtime:\n{}",
+                    unused.join("\n")
+                );
+            }
+        }
+
+        if missing.is_empty() {
+            return Ok(());
+        }
+        Err(eyre!(
+            "The code uses assets that don't exist:\n{}",
+            missing.join("\n")
+        ))
+        .suggestion(
+            "Assets are the files matched by layout.bundle and layout.external, check if they were renamed or removed",
+        )
+    }
+}
+
+/// Follows the locals bound to the asset module and checks the keys they are indexed with
+struct AssetReferences<'a> {
+    alias: &'a str,
+    tree: &'a AssetNode,
+    /// Locals that hold a folder of the module, with the keys leading to it
+    locals: HashMap<String, Vec<String>>,
+    missing: Vec<(usize, String)>,
+    /// The files the chains lead to, with their line
+    used: Vec<(usize, String)>,
+}
+
+impl AssetReferences<'_> {
+    fn is_module_require(&self, suffix: &Suffix) -> bool {
+        let Suffix::Call(Call::AnonymousCall(args)) = suffix else {
+            return false;
+        };
+        let token = match args {
+            FunctionArgs::Parentheses { arguments, .. } if arguments.len() == 1 => {
+                match arguments.iter().next() {
+                    Some(Expression::String(token)) => token,
+                    _ => return false,
+                }
+            }
+            FunctionArgs::String(token) => token,
+            _ => return false,
+        };
+        matches!(
+            token.token_type(),
+            TokenType::StringLiteral { literal, .. } if literal.as_str() == format!("@{}", self.alias)
+        )
+    }
+
+    /// The keys leading to the part of the module an expression starts from, with the remaining suffixes
+    fn start<'b>(
+        &self,
+        prefix: &Prefix,
+        suffixes: &'b [&'b Suffix],
+    ) -> Option<(Vec<String>, &'b [&'b Suffix])> {
+        let Prefix::Name(name) = prefix else {
+            return None;
+        };
+        let name = name.token().to_string();
+        if let Some(keys) = self.locals.get(&name) {
+            return Some((keys.clone(), suffixes));
+        }
+        match suffixes {
+            [first, rest @ ..] if name == "require" && self.is_module_require(first) => {
+                Some((vec![], rest))
+            }
+            _ => None,
+        }
+    }
+
+    /// Walks the dot indexes of a chain, reporting the first key that doesn't exist
+    fn walk(&mut self, mut keys: Vec<String>, suffixes: &[&Suffix]) {
+        let Some(mut node) = self.tree.get(&keys) else {
+            return;
+        };
+        let mut line = None;
+        for suffix in suffixes {
+            let Suffix::Index(Index::Dot { name, .. }) = suffix else {
+                break;
+            };
+            let key = name.token().to_string();
+            line = Some(name.token().start_position().line());
+            let shown = std::iter::once(self.alias)
+                .chain(keys.iter().map(String::as_str))
+                .collect::<Vec<_>>()
+                .join(".");
+            match node {
+                AssetNode::Folder(children) => match children.get(&key) {
+                    Some(child) => node = child,
+                    None => {
+                        self.missing.push((
+                            line.unwrap_or_default(),
+                            format!("{} has no {}", shown, key.yellow()),
+                        ));
+                        return;
+                    }
+                },
+                AssetNode::File(path) => {
+                    self.missing.push((
+                        line.unwrap_or_default(),
+                        format!("{} is the file {}, it has no {}", shown, path, key.yellow()),
+                    ));
+                    return;
+                }
+            }
+            keys.push(key);
+        }
+        if let (AssetNode::File(path), Some(line)) = (node, line) {
+            self.used.push((line, path.clone()));
+        }
+    }
+
+    /// The keys of the module folder an expression evaluates to
+    fn folder_of(&self, expression: &Expression) -> Option<Vec<String>> {
+        let keys = match expression {
+            Expression::Var(Var::Name(name)) => self.locals.get(&name.token().to_string())?.clone(),
+            Expression::FunctionCall(call) => {
+                let suffixes: Vec<_> = call.suffixes().collect();
+                match self.start(call.prefix(), &suffixes)? {
+                    (keys, []) => keys,
+                    _ => return None,
+                }
+            }
+            Expression::Var(Var::Expression(var)) => {
+                let suffixes: Vec<_> = var.suffixes().collect();
+                let (mut keys, rest) = self.start(var.prefix(), &suffixes)?;
+                for suffix in rest {
+                    let Suffix::Index(Index::Dot { name, .. }) = suffix else {
+                        return None;
+                    };
+                    keys.push(name.token().to_string());
+                }
+                keys
+            }
+            _ => return None,
+        };
+        matches!(self.tree.get(&keys), Some(AssetNode::Folder(_))).then_some(keys)
+    }
+
+    fn check<'b>(&mut self, prefix: &Prefix, suffixes: impl Iterator<Item = &'b Suffix>) {
+        let suffixes: Vec<_> = suffixes.collect();
+        if let Some((keys, rest)) = self.start(prefix, &suffixes) {
+            self.walk(keys, rest);
+        }
+    }
+}
+
+impl Visitor for AssetReferences<'_> {
+    fn visit_local_assignment(&mut self, local_assignment: &LocalAssignment) {
+        let mut expressions = local_assignment.expressions().iter();
+        for name in local_assignment.names() {
+            let name = name.token().to_string();
+            match expressions.next().and_then(|x| self.folder_of(x)) {
+                Some(keys) => self.locals.insert(name, keys),
+                // Shadowing the module
+                None => self.locals.remove(&name),
+            };
+        }
+    }
+
+    fn visit_var_expression(&mut self, var_expression: &VarExpression) {
+        self.check(var_expression.prefix(), var_expression.suffixes());
+    }
+
+    fn visit_function_call(&mut self, function_call: &FunctionCall) {
+        self.check(function_call.prefix(), function_call.suffixes());
+    }
+}
@@ -0,0 +1,340 @@
+use std::path::Path;
+
+use color_eyre::{
+    Section,
+    eyre::{Context, eyre},
+};
+use colored::Colorize;
+use image::RgbaImage;
+use indicatif::ProgressBar;
+use walkdir::WalkDir;
+
+use crate::{
+    commands::build::{Builder, build_utils::modified},
+    toml_conf::AtlasConfig,
+};
+
+/// Where the pages and the modules of the atlases go inside the game
+pub const ATLAS_FOLDER: &str = "atlas";
+
+pub(crate) struct Sprite {
+    pub name: String,
+    pub image: RgbaImage,
+}
+
+/// Where a sprite ended up, without the extruded border
+pub(crate) struct Placement {
+    pub page: usize,
+    pub x: u32,
+    pub y: u32,
+}
+
+#[derive(Default)]
+pub(crate) struct Page {
+    pub width: u32,
+    pub height: u32,
+    // The row being filled
+    x: u32,
+    y: u32,
+    row_height: u32,
+}
+
+/// Shelf packing, the tallest sprites go first filling rows from left to right.
+/// A new row starts when a sprite doesn't fit and a new page when the rows don't
+pub(crate) fn pack(
+    sprites: &[Sprite],
+    config: &AtlasConfig,
+) -> color_eyre::Result<(Vec<Placement>, Vec<Page>)> {
+    // Rounding up to a power of two would make the pages bigger than max_size
+    if config.power_of_two && !config.max_size.is_power_of_two() {
+        return Err(eyre!(
+            "max_size is {}, which isn't a power of two like power_of_two asks for",
+            config.max_size
+        ))
+        .suggestion("Set max_size to a power of two like 1024 or 2048");
+    }
+    let mut order: Vec<usize> = (0..sprites.len()).collect();
+    order.sort_by(|a, b| {
+        let (a, b) = (&sprites[*a], &sprites[*b]);
+        b.image
+            .height()
+            .cmp(&a.image.height())
+            .then(b.image.width().cmp(&a.image.width()))
+            .then(a.name.cmp(&b.name))
+    });
+
+    let mut placements: Vec<Option<Placement>> = (0..sprites.len()).map(|_| None).collect();
+    let mut pages = vec![Page::default()];
+    for index in order {
+        let sprite = &sprites[index];
+        let width = sprite.image.width() + config.extrude * 2;
+        let height = sprite.image.height() + config.extrude * 2;
+        if width > config.max_size || height > config.max_size {
+            return Err(eyre!(
+                "The sprite {} is {}x{}, bigger than the pages of the atlas",
+                sprite.name,
+                sprite.image.width(),
+                sprite.image.height()
+            ))
+            .suggestion("Raise max_size or take the sprite out of the atlas folder");
+        }
+
+        let page = pages.last_mut().unwrap();
+        if page.x + width > config.max_size {
+            page.y += page.row_height + config.padding;
+            page.x = 0;
+            page.row_height = 0;
+        }
+        if page.y + height > config.max_size {
+            pages.push(Page::default());
+        }
+
+        let current = pages.len() - 1;
+        let page = &mut pages[current];
+        placements[index] = Some(Placement {
+            page: current,
+            x: page.x + config.extrude,
+            y: page.y + config.extrude,
+        });
+        page.width = page.width.max(page.x + width);
+        page.height = page.height.max(page.y + height);
+        page.row_height = page.row_height.max(height);
+        page.x += width + config.padding;
+    }
+
+    if config.power_of_two {
+        for page in &mut pages {
+            page.width = page.width.next_power_of_two();
+            page.height = page.height.next_power_of_two();
+        }
+    }
+    Ok((placements.into_iter().flatten().collect(), pages))
+}
+
+/// Draws the sprite repeating its edges over the extruded border
+pub(crate) fn blit(page: &mut RgbaImage, sprite: &RgbaImage, x: u32, y: u32, extrude: u32) {
+    let extrude = extrude as i64;
+    let (width, height) = (sprite.width() as i64, sprite.height() as i64);
+    for dy in -extrude..height + extrude {
+        for dx in -extrude..width + extrude {
+            let pixel = sprite.get_pixel(
+                dx.clamp(0, width - 1) as u32,
+                dy.clamp(0, height - 1) as u32,
+            );
+            page.put_pixel((x as i64 + dx) as u32, (y as i64 + dy) as u32, *pixel);
+        }
+    }
+}
+
+impl Builder {
+    /// Whether a file is packed into an atlas instead of being added by itself
+    pub fn is_atlas_source(&self, path: &Path) -> bool {
+        self.config.assets.as_ref().is_some_and(|assets| {
+            assets
+                .atlas
+                .values()
+                .any(|x| path.starts_with(self.paths.root.join(&x.folder)))
+        })
+    }
+
+    /// Packs the folders of assets.atlas into the build folder
+    pub async fn pack_atlases(&self) -> color_eyre::Result<()> {
+        let Some(assets) = &self.config.assets else {
+            return Ok(());
+        };
+        let output = self.paths.build.join(ATLAS_FOLDER);
+        for (name, config) in &assets.atlas {
+            let folder = self.paths.root.join(&config.folder);
+            let entries: Vec<_> = WalkDir::new(&folder)
+                .sort_by_file_name()
+                .into_iter()
+                .filter_map(Result::ok)
+                .collect();
+            let files: Vec<_> = entries
+                .iter()
+                .filter(|x| {
+                    x.path()
+                        .extension()
+                        .is_some_and(|x| x.eq_ignore_ascii_case("png"))
+                })
+                .collect();
+            if files.is_empty() {
+                tracing::warn!("The atlas {} has no PNGs in {}", name, config.folder);
+                continue;
+            }
+
+            // Dev rebuilds on every change, the atlas is only packed again when something changed.
+            // The folders are included since removing a file only changes them
+            let module = output.join(format!("{}.lua", name));
+            if let Some(packed) = modified(&module)
+                && entries
+                    .iter()
+                    .map(|x| x.path().to_path_buf())
+                    .chain([self.paths.root.join("kaledis.toml")])
+                    .all(|x| modified(&x).is_some_and(|x| x <= packed))
+            {
+                continue;
+            }
+
+            let mut p = ProgressBar::new_spinner().with_message(format!("Packing {}...", name));
+            p = self.progress_bar.add(p);
+            let mut sprites = vec![];
+            for file in &files {
+                let image = image::open(file.path())
+                    .with_context(|| format!("Opening {}", file.path().display()))?
+                    .to_rgba8();
+                let name = file
+                    .path()
+                    .strip_prefix(&folder)?
+                    .with_extension("")
+                    .iter()
+                    .map(|x| x.to_string_lossy())
+                    .collect::<Vec<_>>()
+                    .join("/");
+                sprites.push(Sprite { name, image });
+            }
+
+            let (placements, pages) =
+                pack(&sprites, config).with_context(|| format!("Packing the atlas {}", name))?;
+            fs_err::tokio::create_dir_all(&output).await?;
+            // Pages of a previous pack that has more of them
+            for entry in fs_err::read_dir(&output)?.filter_map(Result::ok) {
+                let file = entry.file_name().to_string_lossy().to_string();
+                if file.starts_with(&format!("{}_", name)) && file.ends_with(".png") {
+                    fs_err::tokio::remove_file(entry.path()).await?;
+                }
+            }
+
+            let mut lua = format!(
+                "-- Generated by kaledis from {}, changes are overwritten\nreturn {{\n\tpages = {{\n",
+                config.folder
+            );
+            for (index, page) in pages.iter().enumerate() {
+                let path = format!("{}/{}_{}.png", ATLAS_FOLDER, name, index + 1);
+                let mut image = RgbaImage::new(page.width, page.height);
+                for (sprite, placement) in sprites.iter().zip(&placements) {
+                    if placement.page == index {
+                        blit(
+                            &mut image,
+                            &sprite.image,
+                            placement.x,
+                            placement.y,
+                            config.extrude,
+                        );
+                    }
+                }
+                image
+                    .save(self.paths.build.join(&path))
+                    .with_context(|| format!("Saving {}", path))?;
+                lua.push_str(&format!(
+                    "\t\t{{ path = {:?}, width = {}, height = {} }},\n",
+                    path, page.width, page.height
+                ));
+            }
+            lua.push_str("\t},\n\tsprites = {\n");
+            for (sprite, placement) in sprites.iter().zip(&placements) {
+                lua.push_str(&format!(
+                    "\t\t[{:?}] = {{ page = {}, x = {}, y = {}, width = {}, height = {} }},\n",
+                    sprite.name,
+                    placement.page + 1,
+                    placement.x,
+                    placement.y,
+                    sprite.image.width(),
+                    sprite.image.height()
+                ));
+            }
+            lua.push_str("\t},\n}\n");
+            fs_err::tokio::write(&module, lua).await?;
+
+            p.finish_with_message(format!(
+                "{} Packed {} sprites of {} into {} {}",
+                "[+]".green(),
+                sprites.len(),
+                name,
+                pages.len(),
+                if pages.len() == 1 { "page" } else { "pages" }
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sprites(sizes: &[(&str, u32, u32)]) -> Vec<Sprite> {
+        sizes
+            .iter()
+            .map(|(name, width, height)| Sprite {
+                name: name.to_string(),
+                image: RgbaImage::new(*width, *height),
+            })
+            .collect()
+    }
+
+    fn config(padding: u32, extrude: u32, max_size: u32, power_of_two: bool) -> AtlasConfig {
+        AtlasConfig {
+            folder: String::new(),
+            padding,
+            extrude,
+            max_size,
+            power_of_two,
+        }
+    }
+
+    fn positions(placements: &[Placement]) -> Vec<(usize, u32, u32)> {
+        placements.iter().map(|x| (x.page, x.x, x.y)).collect()
+    }
+
+    #[test]
+    fn fills_rows_tallest_first() {
+        let sprites = sprites(&[("c", 10, 5), ("a", 10, 10), ("d", 10, 10), ("b", 10, 10)]);
+        let (placements, pages) = pack(&sprites, &config(0, 0, 32, false)).unwrap();
+        // c is the shortest, so it starts the second row
+        assert_eq!(
+            positions(&placements),
+            vec![(0, 0, 10), (0, 0, 0), (0, 20, 0), (0, 10, 0)]
+        );
+        assert_eq!((pages.len(), pages[0].width, pages[0].height), (1, 30, 15));
+    }
+
+    #[test]
+    fn leaves_padding_and_extrusion_around_sprites() {
+        let sprites = sprites(&[("a", 4, 4), ("b", 4, 4), ("c", 4, 4)]);
+        let (placements, pages) = pack(&sprites, &config(2, 1, 16, false)).unwrap();
+        // Each sprite takes 6 pixels with its border, plus 2 of padding before the next one
+        assert_eq!(
+            positions(&placements),
+            vec![(0, 1, 1), (0, 9, 1), (0, 1, 9)]
+        );
+        assert_eq!((pages[0].width, pages[0].height), (14, 14));
+    }
+
+    #[test]
+    fn overflows_into_new_pages() {
+        let sprites = sprites(&[("a", 10, 10), ("b", 10, 10), ("c", 10, 10)]);
+        let (placements, pages) = pack(&sprites, &config(0, 0, 16, false)).unwrap();
+        assert_eq!(
+            positions(&placements),
+            vec![(0, 0, 0), (1, 0, 0), (2, 0, 0)]
+        );
+        assert_eq!(pages.len(), 3);
+
+        let big = self::sprites(&[("big", 20, 4)]);
+        assert!(pack(&big, &config(0, 0, 16, false)).is_err());
+        // The extruded border counts towards the size
+        let edge = self::sprites(&[("edge", 16, 4)]);
+        assert!(pack(&edge, &config(0, 0, 16, false)).is_ok());
+        assert!(pack(&edge, &config(0, 1, 16, false)).is_err());
+    }
+
+    #[test]
+    fn rounds_pages_to_powers_of_two() {
+        let sprites = sprites(&[("a", 30, 10), ("b", 20, 5)]);
+        let (_, pages) = pack(&sprites, &config(0, 0, 64, true)).unwrap();
+        assert_eq!((pages[0].width, pages[0].height), (64, 16));
+        // Rounding up could go past max_size
+        assert!(pack(&sprites, &config(0, 0, 100, true)).is_err());
+    }
+}
@@ -1,9 +1,14 @@
-use std::path::PathBuf;
+use std::{
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
 
 use crate::{
+    commands::build::layout::read_ignore,
     dalbit::manifest::Manifest,
     toml_conf::{CustomPolyfillConfig, KaledisConfig},
 };
+use ignore::gitignore::Gitignore;
 use serde_json::{Value, from_str};
 use tokio::fs::{read_to_string, try_exists};
 
@@ -15,6 +20,8 @@ pub struct Paths {
     pub src: PathBuf,
     // pub assets: Option<PathBuf>,
     pub polyfill_path: Option<PathBuf>,
+    /// What .kaledisignore leaves out of the builds
+    pub ignore: Gitignore,
 }
 
 pub fn normalize_lua_path(path: &PathBuf, root: &PathBuf, alternative: &PathBuf) -> PathBuf {
@@ -99,10 +106,16 @@ impl Paths {
             //     .clone()
             //     .map(|x| root.join(PathBuf::from_str(&x).unwrap())),
             dist: root.join("dist"),
+            ignore: read_ignore(&root),
             root,
         }
     }
 }
+/// When a file was last changed, the generated assets are only made again when their sources changed
+pub fn modified(path: &Path) -> Option<SystemTime> {
+    path.metadata().and_then(|x| x.modified()).ok()
+}
+
 pub fn uppercase_first(s: &str) -> String {
     let mut c = s.chars();
     match c.next() {
@@ -0,0 +1,452 @@
+use std::{fmt::Write, path::Path};
+
+use color_eyre::{
+    Section,
+    eyre::{Context, eyre},
+};
+use colored::Colorize;
+use fontdue::{Font, FontSettings, Metrics};
+use image::{Rgba, RgbaImage};
+use indicatif::ProgressBar;
+use serde_json::json;
+
+use crate::{
+    commands::build::{
+        Builder,
+        atlas::{Sprite, blit, pack},
+        build_utils::modified,
+        maps::write_lua,
+    },
+    toml_conf::{AtlasConfig, FontConfig, FontFormat},
+};
+
+/// Where the images, the .fnt files and the modules of the fonts go inside the game
+pub const FONT_FOLDER: &str = "fonts";
+
+// LÖVE splits an ImageFont at the columns of the color of its first pixel
+const SPACER: Rgba<u8> = Rgba([255, 0, 255, 255]);
+
+struct Glyph {
+    character: char,
+    metrics: Metrics,
+    image: RgbaImage,
+}
+
+fn rasterize(font: &Font, character: char, config: &FontConfig) -> Glyph {
+    let (metrics, coverage) = font.rasterize(character, config.size);
+    let mut image = RgbaImage::new(metrics.width as u32, metrics.height as u32);
+    for (pixel, alpha) in image.pixels_mut().zip(coverage) {
+        let alpha = match config.antialias {
+            true => alpha,
+            false if alpha >= 128 => 255,
+            false => 0,
+        };
+        *pixel = Rgba([255, 255, 255, alpha]);
+    }
+    Glyph {
+        character,
+        metrics,
+        image,
+    }
+}
+
+/// The glyphs of the config in order and without repeats, with the characters the font lacks
+fn rasterize_all(font: &Font, config: &FontConfig) -> (Vec<Glyph>, String) {
+    let characters = config
+        .glyphs
+        .clone()
+        .unwrap_or_else(|| (' '..='~').collect());
+    let mut glyphs: Vec<Glyph> = vec![];
+    let mut missing = String::new();
+    for character in characters.chars() {
+        if glyphs.iter().any(|x| x.character == character) || missing.contains(character) {
+            continue;
+        }
+        if !font.has_glyph(character) {
+            missing.push(character);
+            continue;
+        }
+        glyphs.push(rasterize(font, character, config));
+    }
+    (glyphs, missing)
+}
+
+/// Packs the glyphs into the smallest square page they fit in, the .fnt points into it
+fn bmfont(
+    name: &str,
+    font: &Font,
+    glyphs: &[Glyph],
+    config: &FontConfig,
+    base: i32,
+    line_height: i32,
+) -> color_eyre::Result<(RgbaImage, String)> {
+    // Spaces have nothing to draw, they only move the next glyph
+    let drawn: Vec<&Glyph> = glyphs
+        .iter()
+        .filter(|x| x.image.width() > 0 && x.image.height() > 0)
+        .collect();
+    let sprites: Vec<Sprite> = drawn
+        .iter()
+        .map(|x| Sprite {
+            name: x.character.to_string(),
+            image: x.image.clone(),
+        })
+        .collect();
+    let mut atlas = AtlasConfig {
+        folder: String::new(),
+        padding: config.padding,
+        extrude: 0,
+        max_size: 64,
+        power_of_two: true,
+    };
+    let (placements, page) = loop {
+        let (placements, mut pages) = pack(&sprites, &atlas)?;
+        if pages.len() == 1 {
+            break (placements, pages.remove(0));
+        }
+        if atlas.max_size >= 4096 {
+            return Err(eyre!("The glyphs don't fit in a 4096x4096 image"))
+                .suggestion("Use a smaller size or fewer glyphs");
+        }
+        atlas.max_size *= 2;
+    };
+
+    let mut image = RgbaImage::new(page.width.max(1), page.height.max(1));
+    let mut chars = String::new();
+    for glyph in glyphs {
+        let index = drawn.iter().position(|x| x.character == glyph.character);
+        let (x, y) = match index {
+            Some(index) => {
+                let placement = &placements[index];
+                blit(&mut image, &glyph.image, placement.x, placement.y, 0);
+                (placement.x, placement.y)
+            }
+            None => (0, 0),
+        };
+        let metrics = &glyph.metrics;
+        let (width, height) = match index {
+            Some(_) => (metrics.width, metrics.height),
+            None => (0, 0),
+        };
+        writeln!(
+            chars,
+            "char id={} x={} y={} width={} height={} xoffset={} yoffset={} xadvance={} page=0 chnl=15",
+            glyph.character as u32,
+            x,
+            y,
+            width,
+            height,
+            metrics.xmin,
+            base - (metrics.ymin + metrics.height as i32),
+            metrics.advance_width.round() as i32
+        )?;
+    }
+
+    let mut kernings = vec![];
+    for left in glyphs {
+        for right in glyphs {
+            let amount = font
+                .horizontal_kern(left.character, right.character, config.size)
+                .unwrap_or_default()
+                .round() as i32;
+            if amount != 0 {
+                kernings.push(format!(
+                    "kerning first={} second={} amount={}",
+                    left.character as u32, right.character as u32, amount
+                ));
+            }
+        }
+    }
+
+    let mut fnt = String::new();
+    writeln!(
+        fnt,
+        "info face={:?} size={} bold=0 italic=0 charset=\"\" unicode=1 stretchH=100 smooth={} aa={} padding=0,0,0,0 spacing={},{}",
+        font.name().unwrap_or(name),
+        config.size.round() as i32,
+        config.antialias as u8,
+        config.antialias as u8,
+        config.padding,
+        config.padding
+    )?;
+    writeln!(
+        fnt,
+        "common lineHeight={} base={} scaleW={} scaleH={} pages=1 packed=0",
+        line_height,
+        base,
+        image.width(),
+        image.height()
+    )?;
+    // LÖVE opens the pages next to the .fnt
+    writeln!(fnt, "page id=0 file=\"{}.png\"", name)?;
+    writeln!(fnt, "chars count={}", glyphs.len())?;
+    fnt.push_str(&chars);
+    if !kernings.is_empty() {
+        writeln!(fnt, "kernings count={}", kernings.len())?;
+        for kerning in kernings {
+            writeln!(fnt, "{}", kerning)?;
+        }
+    }
+    Ok((image, fnt))
+}
+
+/// Lays the glyphs in a row, each one as wide as its advance and as tall as a line.
+/// Empty cells would merge two spacers, so every glyph is at least a pixel wide
+fn image_font(glyphs: &[Glyph], config: &FontConfig, base: i32, line_height: i32) -> RgbaImage {
+    let widths: Vec<u32> = glyphs
+        .iter()
+        .map(|x| x.metrics.advance_width.round().max(1.0) as u32)
+        .collect();
+    let spacing = config.padding.max(1);
+    let width = widths.iter().map(|x| x + spacing).sum::<u32>() + spacing;
+    let mut image = RgbaImage::from_pixel(width, line_height.max(1) as u32, SPACER);
+
+    let mut x = spacing;
+    for (glyph, width) in glyphs.iter().zip(widths) {
+        for cell_x in x..x + width {
+            for cell_y in 0..image.height() {
+                image.put_pixel(cell_x, cell_y, Rgba([0, 0, 0, 0]));
+            }
+        }
+        // The parts that go out of the cell are cut, like the ones of glyphs with a negative bearing
+        let top = base - (glyph.metrics.ymin + glyph.metrics.height as i32);
+        for (glyph_x, glyph_y, pixel) in glyph.image.enumerate_pixels() {
+            let cell_x = glyph.metrics.xmin + glyph_x as i32;
+            let cell_y = top + glyph_y as i32;
+            if (0..width as i32).contains(&cell_x) && (0..line_height).contains(&cell_y) {
+                image.put_pixel(x + cell_x as u32, cell_y as u32, *pixel);
+            }
+        }
+        x += width + spacing;
+    }
+    image
+}
+
+impl Builder {
+    /// Whether a file is a font rasterized into a bitmap font, the game doesn't need it
+    pub fn is_font_source(&self, path: &Path) -> bool {
+        self.config.assets.as_ref().is_some_and(|assets| {
+            assets
+                .fonts
+                .values()
+                .any(|x| path == self.paths.root.join(&x.file))
+        })
+    }
+
+    /// Rasterizes the fonts of assets.fonts into the build folder
+    pub async fn generate_fonts(&self) -> color_eyre::Result<()> {
+        let Some(assets) = &self.config.assets else {
+            return Ok(());
+        };
+        let output = self.paths.build.join(FONT_FOLDER);
+        for (name, config) in &assets.fonts {
+            let file = self.paths.root.join(&config.file);
+            // Like the atlases, dev only rasterizes the fonts again when they changed
+            let module = output.join(format!("{}.lua", name));
+            if let Some(generated) = modified(&module)
+                && [file.clone(), self.paths.root.join("kaledis.toml")]
+                    .iter()
+                    .all(|x| modified(x).is_some_and(|x| x <= generated))
+            {
+                continue;
+            }
+
+            let mut p = ProgressBar::new_spinner().with_message(format!("Rasterizing {}...", name));
+            p = self.progress_bar.add(p);
+            let data = fs_err::tokio::read(&file)
+                .await
+                .suggestion("Check the file of the font in assets.fonts")?;
+            let font = Font::from_bytes(
+                data,
+                FontSettings {
+                    scale: config.size,
+                    ..Default::default()
+                },
+            )
+            .map_err(|err| eyre!("Reading {}: {}", config.file, err))?;
+            let line = font
+                .horizontal_line_metrics(config.size)
+                .ok_or_else(|| eyre!("{} has no horizontal metrics", config.file))?;
+            let base = line.ascent.ceil() as i32;
+            let line_height = line.new_line_size.ceil() as i32;
+
+            let (glyphs, missing) = rasterize_all(&font, config);
+            if !missing.is_empty() {
+                tracing::warn!(
+                    "{} doesn't have these glyphs, the font {} leaves them out: {}",
+                    config.file,
+                    name,
+                    missing.yellow()
+                );
+            }
+
+            fs_err::tokio::create_dir_all(&output).await?;
+            let image_path = format!("{}/{}.png", FONT_FOLDER, name);
+            let characters: String = glyphs.iter().map(|x| x.character).collect();
+            let (image, load) = match config.format {
+                FontFormat::BMFont => {
+                    let (image, fnt) = bmfont(name, &font, &glyphs, config, base, line_height)
+                        .with_context(|| format!("Rasterizing the font {}", name))?;
+                    fs_err::tokio::write(output.join(format!("{}.fnt", name)), fnt).await?;
+                    (image, "love.graphics.newFont(font.path)")
+                }
+                FontFormat::ImageFont => (
+                    image_font(&glyphs, config, base, line_height),
+                    "love.graphics.newImageFont(font.image, font.glyphs)",
+                ),
+            };
+            image
+                .save(self.paths.build.join(&image_path))
+                .with_context(|| format!("Saving {}", image_path))?;
+
+            let mut table = String::new();
+            write_lua(
+                &mut table,
+                &json!({
+                    "format": format!("{:?}", config.format),
+                    "path": match config.format {
+                        FontFormat::BMFont => format!("{}/{}.fnt", FONT_FOLDER, name),
+                        FontFormat::ImageFont => image_path.clone(),
+                    },
+                    "image": image_path,
+                    "size": if config.size.fract() == 0.0 {
+                        json!(config.size as i64)
+                    } else {
+                        json!(config.size)
+                    },
+                    "lineHeight": line_height,
+                    "glyphs": characters,
+                }),
+                0,
+            );
+            // The table goes last, the glyphs can be anything
+            let lua = include_str!("../../../static/font.lua")
+                .replace("{{SOURCE}}", &config.file)
+                .replace("{{LOAD}}", load)
+                .replace("{{FONT}}", &table);
+            fs_err::tokio::write(&module, lua).await?;
+
+            p.finish_with_message(format!(
+                "{} Rasterized {} glyphs of {}",
+                "[+]".green(),
+                glyphs.len(),
+                name
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TINY5: &[u8] = include_bytes!("../../../examples/game_example/assets/Tiny5-Regular.ttf");
+
+    fn config(glyphs: &str, format: FontFormat) -> FontConfig {
+        FontConfig {
+            file: "Tiny5-Regular.ttf".to_string(),
+            size: 8.0,
+            glyphs: Some(glyphs.to_string()),
+            format,
+            antialias: false,
+            padding: 1,
+        }
+    }
+
+    /// The font with its base and line height, like generate_fonts measures them
+    fn tiny5() -> (Font, i32, i32) {
+        let font = Font::from_bytes(
+            TINY5,
+            FontSettings {
+                scale: 8.0,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let line = font.horizontal_line_metrics(8.0).unwrap();
+        (
+            font,
+            line.ascent.ceil() as i32,
+            line.new_line_size.ceil() as i32,
+        )
+    }
+
+    /// The image with | for the spacer, # for drawn pixels and . for transparent ones
+    fn draw(image: &RgbaImage) -> Vec<String> {
+        (0..image.height())
+            .map(|y| {
+                (0..image.width())
+                    .map(|x| match image.get_pixel(x, y) {
+                        pixel if *pixel == SPACER => '|',
+                        Rgba([255, 255, 255, 255]) => '#',
+                        Rgba([_, _, _, 0]) => '.',
+                        _ => '?',
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn skips_repeated_and_missing_glyphs() {
+        let (font, _, _) = tiny5();
+        let (glyphs, missing) = rasterize_all(&font, &config("AB A漢漢", FontFormat::BMFont));
+        let characters: String = glyphs.iter().map(|x| x.character).collect();
+        assert_eq!(characters, "AB ");
+        assert_eq!(missing, "漢");
+    }
+
+    #[test]
+    fn writes_the_fnt() {
+        let (font, base, line_height) = tiny5();
+        let config = config("AB ", FontFormat::BMFont);
+        let (glyphs, _) = rasterize_all(&font, &config);
+        let (image, fnt) = bmfont("ui", &font, &glyphs, &config, base, line_height).unwrap();
+        assert_eq!(
+            fnt,
+            r#"info face="Tiny5 Regular" size=8 bold=0 italic=0 charset="" unicode=1 stretchH=100 smooth=0 aa=0 padding=0,0,0,0 spacing=1,1
+common lineHeight=9 base=7 scaleW=16 scaleH=8 pages=1 packed=0
+page id=0 file="ui.png"
+chars count=3
+char id=65 x=0 y=0 width=4 height=5 xoffset=0 yoffset=2 xadvance=5 page=0 chnl=15
+char id=66 x=5 y=0 width=4 height=5 xoffset=0 yoffset=2 xadvance=5 page=0 chnl=15
+char id=32 x=0 y=0 width=0 height=0 xoffset=0 yoffset=7 xadvance=2 page=0 chnl=15
+"#
+        );
+        // Each char points to its glyph in the page
+        assert_eq!(
+            draw(&image)[..5],
+            [
+                ".##..###.",
+                "#..#.#..#",
+                "####.###.",
+                "#..#.#..#",
+                "#..#.###."
+            ]
+            .map(|x| format!("{}.......", x))
+        );
+    }
+
+    #[test]
+    fn lays_image_fonts_between_spacers() {
+        let (font, base, line_height) = tiny5();
+        let config = config("AB ", FontFormat::ImageFont);
+        let (glyphs, _) = rasterize_all(&font, &config);
+        // Every cell is as wide as the advance and as tall as a line, the space included
+        assert_eq!(
+            draw(&image_font(&glyphs, &config, base, line_height)),
+            [
+                "|.....|.....|..|",
+                "|.....|.....|..|",
+                "|.##..|###..|..|",
+                "|#..#.|#..#.|..|",
+                "|####.|###..|..|",
+                "|#..#.|#..#.|..|",
+                "|#..#.|###..|..|",
+                "|.....|.....|..|",
+                "|.....|.....|..|",
+            ]
+        );
+    }
+}
@@ -0,0 +1,501 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::{Path, PathBuf},
+};
+
+use clap::ValueEnum;
+use color_eyre::{Section, eyre::eyre};
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+
+use crate::{
+    commands::build::{
+        Builder,
+        assets::{glob_base, love_path},
+    },
+    home_manager::Target,
+    toml_conf::AssetRule,
+};
+
+/// Gitignore-style file at the root of the project, what it matches is never built nor watched
+pub const IGNORE_FILE: &str = ".kaledisignore";
+
+/// Reads the .kaledisignore of a project, a broken line is reported and skipped
+pub fn read_ignore(root: &Path) -> Gitignore {
+    let mut builder = GitignoreBuilder::new(root);
+    if root.join(IGNORE_FILE).is_file()
+        && let Some(err) = builder.add(root.join(IGNORE_FILE))
+    {
+        tracing::warn!("Reading {}: {}", IGNORE_FILE, err);
+    }
+    builder.build().unwrap_or_else(|err| {
+        tracing::warn!("Reading {}: {}", IGNORE_FILE, err);
+        Gitignore::empty()
+    })
+}
+
+/// Whether a matcher built from the root of the project matches a path or one of its folders
+pub fn is_ignored(ignore: &Gitignore, path: &Path) -> bool {
+    // The matcher panics with paths outside of its root
+    path.starts_with(ignore.path())
+        && ignore
+            .matched_path_or_any_parents(path, path.is_dir())
+            .is_ignore()
+}
+
+/// Bundled files with the path LÖVE opens them with
+pub type BundleFiles = Vec<(PathBuf, String)>;
+
+/// The target dev builds take the assets of, the desktop they run on
+pub fn dev_target() -> Target {
+    if cfg!(windows) {
+        Target::Windows
+    } else if cfg!(target_os = "macos") {
+        Target::Macos
+    } else if cfg!(target_arch = "aarch64") {
+        Target::LinuxAarch64
+    } else {
+        Target::LinuxAppImage
+    }
+}
+
+/// Where a bundled file goes in the builds of a target
+enum Route<'a> {
+    /// No rule matches it, it keeps its path
+    Kept,
+    Moved(&'a AssetRule, String),
+    /// Only rules of other targets match it
+    LeftOut,
+}
+
+/// Where a rule puts a file, nothing when its glob doesn't match the file
+fn rule_destination(root: &Path, rule: &AssetRule, path: &Path) -> Option<String> {
+    let pattern = glob::Pattern::new(&root.join(&rule.from).to_string_lossy()).ok()?;
+    if !pattern.matches_path(path) {
+        return None;
+    }
+    let to = rule.to.trim_start_matches("./").trim_start_matches('/');
+    if !to.is_empty() && !to.ends_with('/') {
+        return Some(to.to_string());
+    }
+    let relative = love_path(&root.join(glob_base(&rule.from)), path)?;
+    Some(format!("{}{}", to, relative))
+}
+
+/// Whether a rule applies to the builds of a target
+fn lists(rule: &AssetRule, target: &Target) -> bool {
+    rule.targets
+        .as_ref()
+        .is_none_or(|x| x.iter().any(|x| x.covers(target)))
+}
+
+/// The folder the keys of the asset module start from for the files a rule moves
+fn rule_base(rule: &AssetRule) -> String {
+    let to = rule.to.trim_start_matches("./").trim_start_matches('/');
+    if to.is_empty() || to.ends_with('/') {
+        return to.trim_end_matches('/').to_string();
+    }
+    to.rsplit_once('/')
+        .map(|(folder, _)| folder.to_string())
+        .unwrap_or_default()
+}
+
+/// Where a bundled file goes in the builds of a target, the first rule listing the target wins
+fn route<'a>(root: &Path, rules: &'a [AssetRule], target: &Target, path: &Path) -> Route<'a> {
+    let mut route = Route::Kept;
+    for rule in rules {
+        let Some(destination) = rule_destination(root, rule, path) else {
+            continue;
+        };
+        if lists(rule, target) {
+            return Route::Moved(rule, destination);
+        }
+        route = Route::LeftOut;
+    }
+    route
+}
+
+/// The path LÖVE opens a file with in the builds of a target, nothing when the rules leave it out
+fn destination(root: &Path, rules: &[AssetRule], target: &Target, path: &Path) -> Option<String> {
+    match route(root, rules, target, path) {
+        Route::Kept => love_path(root, path),
+        Route::Moved(_, destination) => Some(destination),
+        Route::LeftOut => None,
+    }
+}
+
+/// The files of a target with where they go, two files can't go to the same path
+fn routed_files(
+    root: &Path,
+    rules: &[AssetRule],
+    sources: Vec<PathBuf>,
+    target: &Target,
+) -> color_eyre::Result<BundleFiles> {
+    let mut destinations: BTreeMap<String, PathBuf> = BTreeMap::new();
+    for path in sources {
+        let Some(destination) = destination(root, rules, target, &path) else {
+            continue;
+        };
+        if let Some(other) = destinations.insert(destination.clone(), path.clone()) {
+            return Err(eyre!(
+                "{} and {} both go to {} in the {} builds",
+                other.display(),
+                path.display(),
+                destination,
+                target.name()
+            ))
+            .suggestion("Change assets.rules so only one of them goes there for each target");
+        }
+    }
+    Ok(destinations
+        .into_iter()
+        .map(|(destination, path)| (path, destination))
+        .collect())
+}
+
+/// The `from` globs of the rules that list a target but give none of the files to its builds
+fn unused_rules<'a>(
+    root: &Path,
+    rules: &'a [AssetRule],
+    sources: &[PathBuf],
+    target: &Target,
+) -> Vec<&'a str> {
+    rules
+        .iter()
+        .filter(|rule| lists(rule, target))
+        .filter(|rule| {
+            !sources.iter().any(|path| {
+                matches!(route(root, rules, target, path), Route::Moved(moved, _) if std::ptr::eq(moved, *rule))
+            })
+        })
+        .map(|rule| rule.from.as_str())
+        .collect()
+}
+
+impl Builder {
+    fn asset_rules(&self) -> &[AssetRule] {
+        self.config
+            .assets
+            .as_ref()
+            .map(|x| x.rules.as_slice())
+            .unwrap_or_default()
+    }
+
+    pub fn has_rules(&self) -> bool {
+        !self.asset_rules().is_empty()
+    }
+
+    /// Whether a rule of assets.rules matches a file, for any target
+    pub fn has_rule(&self, path: &Path) -> bool {
+        self.asset_rules()
+            .iter()
+            .any(|rule| rule_destination(&self.paths.root, rule, path).is_some())
+    }
+
+    /// The path LÖVE opens a bundled file with in the builds of a target,
+    /// nothing when the rules leave it out of them
+    pub fn destination(&self, target: &Target, path: &Path) -> Option<String> {
+        destination(&self.paths.root, self.asset_rules(), target, path)
+    }
+
+    fn bundle_sources(&self) -> Vec<PathBuf> {
+        let mut sources: Vec<PathBuf> = self
+            .layout_files(&self.config.layout.bundle)
+            .into_iter()
+            .filter(|x| !self.is_build_source(x))
+            .collect();
+        // The images of the sheets are bundled even when the layout misses them
+        for image in self.sheet_images() {
+            if !sources.contains(&image) {
+                sources.push(image);
+            }
+        }
+        sources
+    }
+
+    /// The paths of the bundled assets in the builds of any target
+    pub fn bundled_paths(&self) -> BTreeSet<String> {
+        let sources = self.bundle_sources();
+        Target::value_variants()
+            .iter()
+            .flat_map(|target| sources.iter().filter_map(|x| self.destination(target, x)))
+            .collect()
+    }
+
+    /// Where assets.rules moves the bundled files in the builds of any target, with the
+    /// folder the keys of the asset module start from
+    pub fn moved_files(&self) -> BTreeMap<String, String> {
+        let sources = self.bundle_sources();
+        let mut moved = BTreeMap::new();
+        for target in Target::value_variants() {
+            for path in &sources {
+                if let Route::Moved(rule, destination) =
+                    route(&self.paths.root, self.asset_rules(), target, path)
+                {
+                    moved.insert(destination, rule_base(rule));
+                }
+            }
+        }
+        moved
+    }
+
+    /// The `from` globs of the rules that list a target but give its builds no file
+    pub fn empty_rules(&self, target: &Target) -> Vec<&str> {
+        unused_rules(
+            &self.paths.root,
+            self.asset_rules(),
+            &self.bundle_sources(),
+            target,
+        )
+    }
+
+    /// The files of layout.bundle the builds of a target get, with the path LÖVE opens them with.
+    /// The shaders are among them, but they are preprocessed instead of added as they are
+    pub fn bundle_files(&self, target: &Target) -> color_eyre::Result<BundleFiles> {
+        routed_files(
+            &self.paths.root,
+            self.asset_rules(),
+            self.bundle_sources(),
+            target,
+        )
+    }
+
+    /// Whether .kaledisignore leaves a file of the project out
+    pub fn is_ignored(&self, path: &Path) -> bool {
+        is_ignored(&self.paths.ignore, path)
+    }
+
+    /// The `!pattern` entries of a list of globs, they take out what the other entries match
+    fn exclusions(&self, globs: &[String]) -> Gitignore {
+        let mut builder = GitignoreBuilder::new(&self.paths.root);
+        for pattern in globs.iter().filter_map(|x| x.strip_prefix('!')) {
+            if let Err(err) = builder.add_line(None, pattern) {
+                tracing::warn!("Invalid exclusion !{}: {}", pattern, err);
+            }
+        }
+        builder.build().unwrap_or_else(|_| Gitignore::empty())
+    }
+
+    /// The files matched by a list of globs, like layout.bundle, without the `!pattern`
+    /// entries of the list and the ones of .kaledisignore
+    pub fn layout_files(&self, globs: &[String]) -> Vec<PathBuf> {
+        let exclusions = self.exclusions(globs);
+        let mut files: Vec<PathBuf> = globs
+            .iter()
+            .filter(|x| !x.starts_with('!'))
+            .filter_map(
+                |glb| match glob::glob(&self.paths.root.join(glb).to_string_lossy()) {
+                    Ok(paths) => Some(paths),
+                    Err(err) => {
+                        tracing::warn!("Invalid glob {}: {}", glb, err);
+                        None
+                    }
+                },
+            )
+            .flatten()
+            .filter_map(Result::ok)
+            .filter(|x| x.is_file() && !self.is_ignored(x) && !is_ignored(&exclusions, x))
+            .collect();
+        files.sort();
+        files.dedup();
+        files
+    }
+
+    /// Whether a list of globs, like layout.bundle, matches a file
+    pub fn in_layout(&self, globs: &[String], path: &Path) -> bool {
+        globs.iter().filter(|x| !x.starts_with('!')).any(|glb| {
+            glob::Pattern::new(&self.paths.root.join(glb).to_string_lossy())
+                .is_ok_and(|x| x.matches_path(path))
+        }) && !self.is_ignored(path)
+            && !is_ignored(&self.exclusions(globs), path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ROOT: &str = "/game";
+
+    fn rule(from: &str, to: &str, targets: Option<Vec<Target>>) -> AssetRule {
+        AssetRule {
+            from: from.to_string(),
+            to: to.to_string(),
+            targets,
+        }
+    }
+
+    fn file(path: &str) -> PathBuf {
+        Path::new(ROOT).join(path)
+    }
+
+    fn destination_of(rules: &[AssetRule], target: Target, path: &str) -> Option<String> {
+        destination(Path::new(ROOT), rules, &target, &file(path))
+    }
+
+    #[test]
+    fn moves_into_folders_and_onto_files() {
+        let rules = [
+            rule("art/export/**/*.png", "./sprites/", None),
+            rule("docs/credits.txt", "/CREDITS", None),
+            rule("music/*.ogg", "", None),
+        ];
+        // A folder keeps the path after the base of the glob
+        assert_eq!(
+            destination_of(&rules, Target::Windows, "art/export/ui/button.png").as_deref(),
+            Some("sprites/ui/button.png")
+        );
+        // Anything else is the new path of the file
+        assert_eq!(
+            destination_of(&rules, Target::Windows, "docs/credits.txt").as_deref(),
+            Some("CREDITS")
+        );
+        // An empty folder is the root of the game
+        assert_eq!(
+            destination_of(&rules, Target::Windows, "music/theme.ogg").as_deref(),
+            Some("theme.ogg")
+        );
+        // Files no rule matches keep their path
+        assert_eq!(
+            destination_of(&rules, Target::Windows, "art/title.png").as_deref(),
+            Some("art/title.png")
+        );
+    }
+
+    #[test]
+    fn filters_the_rules_by_target() {
+        let rules = [
+            rule("art/sd/**", "art/", Some(vec![Target::Android])),
+            rule(
+                "art/hd/**",
+                "art/",
+                Some(vec![Target::Windows, Target::Macos]),
+            ),
+        ];
+        assert_eq!(
+            destination_of(&rules, Target::Android, "art/sd/hero.png").as_deref(),
+            Some("art/hero.png")
+        );
+        // Android also stands for the bundle, and Windows for its 32 bit build
+        assert_eq!(
+            destination_of(&rules, Target::AndroidBundle, "art/sd/hero.png").as_deref(),
+            Some("art/hero.png")
+        );
+        assert_eq!(
+            destination_of(&rules, Target::WindowsX86, "art/hd/hero.png").as_deref(),
+            Some("art/hero.png")
+        );
+        // Only the rules of other targets match them, so they are left out
+        assert_eq!(
+            destination_of(&rules, Target::Android, "art/hd/hero.png"),
+            None
+        );
+        assert_eq!(
+            destination_of(&rules, Target::Macos, "art/sd/hero.png"),
+            None
+        );
+        assert_eq!(destination_of(&rules, Target::Web, "art/hd/hero.png"), None);
+    }
+
+    #[test]
+    fn takes_the_first_rule_of_the_target() {
+        let rules = [
+            rule("art/**", "mobile/", Some(vec![Target::Android])),
+            rule("art/hero.png", "hero.png", None),
+            rule("art/**", "desktop/", None),
+        ];
+        assert_eq!(
+            destination_of(&rules, Target::Android, "art/hero.png").as_deref(),
+            Some("mobile/hero.png")
+        );
+        assert_eq!(
+            destination_of(&rules, Target::Windows, "art/hero.png").as_deref(),
+            Some("hero.png")
+        );
+        assert_eq!(
+            destination_of(&rules, Target::Windows, "art/enemy.png").as_deref(),
+            Some("desktop/enemy.png")
+        );
+        // The last rule never gets hero.png, but it still gives the other files
+        let sources = vec![file("art/hero.png")];
+        assert_eq!(
+            unused_rules(Path::new(ROOT), &rules, &sources, &Target::Windows),
+            vec!["art/**"]
+        );
+        // On android the first rule takes it instead
+        assert_eq!(
+            unused_rules(Path::new(ROOT), &rules, &sources, &Target::Android),
+            vec!["art/hero.png", "art/**"]
+        );
+    }
+
+    #[test]
+    fn reports_files_going_to_the_same_path() {
+        let rules = [
+            rule("art/sd/**", "art/", Some(vec![Target::Android])),
+            rule("art/hd/**", "art/", Some(vec![Target::Windows])),
+        ];
+        let sources = vec![
+            file("art/hd/hero.png"),
+            file("art/sd/hero.png"),
+            file("main.png"),
+        ];
+        let files =
+            routed_files(Path::new(ROOT), &rules, sources.clone(), &Target::Android).unwrap();
+        assert_eq!(
+            files,
+            vec![
+                (file("art/sd/hero.png"), "art/hero.png".to_string()),
+                (file("main.png"), "main.png".to_string()),
+            ]
+        );
+        let files =
+            routed_files(Path::new(ROOT), &rules, sources.clone(), &Target::Windows).unwrap();
+        assert_eq!(
+            files[0],
+            (file("art/hd/hero.png"), "art/hero.png".to_string())
+        );
+
+        // Without the target the sd art goes to the same path as the hd one
+        let rules = [
+            rule("art/sd/**", "art/", None),
+            rule("art/hd/**", "art/", None),
+        ];
+        let err = routed_files(Path::new(ROOT), &rules, sources, &Target::Windows).unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("both go to art/hero.png in the windows builds")
+        );
+    }
+
+    #[test]
+    fn finds_the_rules_that_give_nothing() {
+        let rules = [
+            rule("art/**", "art/", None),
+            rule("art/hero.png", "hero.png", None),
+            rule("sounds/**", "sfx/", Some(vec![Target::Web])),
+        ];
+        let sources = vec![file("art/hero.png"), file("sounds/jump.ogg")];
+        // art/** takes hero.png before the second rule can
+        assert_eq!(
+            unused_rules(Path::new(ROOT), &rules, &sources, &Target::Web),
+            vec!["art/hero.png"]
+        );
+        // The rules of other targets aren't reported
+        assert_eq!(
+            unused_rules(Path::new(ROOT), &rules, &sources, &Target::Windows),
+            vec!["art/hero.png"]
+        );
+        assert_eq!(
+            unused_rules(Path::new(ROOT), &rules, &[], &Target::Web),
+            vec!["art/**", "art/hero.png", "sounds/**"]
+        );
+    }
+
+    #[test]
+    fn starts_the_keys_from_the_destination_folder() {
+        assert_eq!(rule_base(&rule("art/**", "./sprites/", None)), "sprites");
+        assert_eq!(rule_base(&rule("art/**", "", None)), "");
+        assert_eq!(rule_base(&rule("a.txt", "/docs/a/b.txt", None)), "docs/a");
+        assert_eq!(rule_base(&rule("a.txt", "b.txt", None)), "");
+    }
+}
@@ -1,17 +1,33 @@
 use std::{
-    io::{Cursor, Read, Write},
+    io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write},
     path::{Path, PathBuf},
+    process::Stdio,
 };
 
 use backhand::{
     FilesystemCompressor, FilesystemReader, FilesystemWriter, InnerNode, NodeHeader,
-    compression::Compressor, kind::Kind,
+    compression::{CompressionOptions, Compressor, Gzip, Lz4, Zstd},
+    kind::Kind,
+};
+use color_eyre::{
+    Section,
+    eyre::{Context, ContextCompat, eyre},
 };
-use color_eyre::{Section, eyre::Context};
 use fs_err::tokio::{File, create_dir_all};
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
-
-use crate::{commands::build::Builder, home_manager::Target};
+use sha2::Digest;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    process::Command,
+};
+use tracing::warn;
+
+use crate::{
+    commands::build::Builder,
+    elf_utils,
+    home_manager::Target,
+    toml_conf::{AppImageCompression, LinuxConfig},
+    zsync_utils,
+};
 
 fn is_valid_superblock(data: &[u8]) -> bool {
     if data.len() < 96 {
@@ -132,37 +148,107 @@ fn skip_file_from_squashfs<'a>(
     Ok(writer)
 }
 
-// Returns both the AppImage and the squashfs
-fn extract_squashfs_from_appimage(
-    mut data: Vec<u8>,
-) -> Result<(Vec<u8>, Vec<u8>), Box<dyn std::error::Error>> {
-    let offset = find_valid_squashfs_offset(&data).ok_or("SquashFS magic not found in AppImage")?;
+// Returns the AppImage runtime and the offset where the squashfs starts
+fn read_appimage_runtime(file: &mut std::fs::File) -> color_eyre::Result<(Vec<u8>, u64)> {
+    let mut header = vec![0; 64];
+    file.read_exact(&mut header)?;
+    let mut offset = elf_utils::elf_size(&header)?;
 
-    let squashfs = data.split_off(offset);
+    let mut superblock = vec![0; 96];
+    file.seek(SeekFrom::Start(offset))?;
+    if file.read_exact(&mut superblock).is_err() || !is_valid_superblock(&superblock) {
+        // Some runtimes put data after the section headers, so we scan for it instead
+        let mut data = vec![];
+        file.seek(SeekFrom::Start(0))?;
+        file.read_to_end(&mut data)?;
+        offset = find_valid_squashfs_offset(&data).wrap_err("SquashFS magic not found in AppImage")?
+            as u64;
+    }
 
-    Ok((data, squashfs))
+    let mut runtime = vec![0; offset as usize];
+    file.seek(SeekFrom::Start(0))?;
+    file.read_exact(&mut runtime)?;
+    Ok((runtime, offset))
 }
 
-pub async fn build_linux(builder: &Builder, data: &[u8]) -> color_eyre::Result<()> {
-    let dists = builder
-        .paths
-        .dist
-        .join(Target::LinuxAppImage.as_ref().to_string());
-    let original = builder
-        .home
-        .get_path(&builder.config.love, Target::LinuxAppImage)
-        .await
-        .join("love2d.AppImage");
-    let mut file = fs_err::tokio::File::open(original).await?;
+/// Only opens the file when the squashfs writer gets to it, so big asset
+/// folders don't keep thousands of files open or loaded in memory
+struct LazyFile {
+    path: PathBuf,
+    file: Option<std::fs::File>,
+    // backhand reads again after a short last chunk, that must not start the file over
+    eof: bool,
+}
 
-    let mut image_data = vec![];
-    file.read_to_end(&mut image_data).await?;
+impl LazyFile {
+    fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            file: None,
+            eof: false,
+        }
+    }
+}
 
-    let (appimage, squashfs) = extract_squashfs_from_appimage(image_data).unwrap();
+impl Read for LazyFile {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.eof {
+            return Ok(0);
+        }
+        if self.file.is_none() {
+            self.file = Some(std::fs::File::open(&self.path)?);
+        }
+        let read = self.file.as_mut().unwrap().read(buf)?;
+        if read == 0 && !buf.is_empty() {
+            // Closes it so only the files being written are open
+            self.file = None;
+            self.eof = true;
+        }
+        Ok(read)
+    }
+}
+
+fn get_compressor(linux: Option<&LinuxConfig>) -> color_eyre::Result<FilesystemCompressor> {
+    let compression = linux.map(|x| x.compression).unwrap_or_default();
+    let level = linux.and_then(|x| x.compression_level);
+    let options = match compression {
+        AppImageCompression::Zstd => level.map(|compression_level| {
+            CompressionOptions::Zstd(Zstd { compression_level })
+        }),
+        AppImageCompression::Gzip => level.map(|compression_level| {
+            CompressionOptions::Gzip(Gzip {
+                compression_level,
+                window_size: 15,
+                strategies: 0,
+            })
+        }),
+        AppImageCompression::Lz4 => {
+            if level.is_some() {
+                warn!("lz4 doesn't have compression levels, ignoring compression_level");
+            }
+            Some(CompressionOptions::Lz4(Lz4 {
+                version: 1,
+                flags: 0,
+            }))
+        }
+    };
+    let compressor = match compression {
+        AppImageCompression::Zstd => Compressor::Zstd,
+        AppImageCompression::Gzip => Compressor::Gzip,
+        AppImageCompression::Lz4 => Compressor::Lz4,
+    };
+    Ok(FilesystemCompressor::new(compressor, options)?)
+}
+
+pub async fn build_linux(builder: &Builder, data: &[u8], target: Target) -> color_eyre::Result<()> {
+    let dists = builder.paths.dist.join(target.as_ref());
+    let original = builder.runtime_path(target).await?.join("love2d.AppImage");
+    let mut file = fs_err::File::open(original)?.into_parts().0;
+    let (mut appimage, offset) = read_appimage_runtime(&mut file)?;
 
     let reader = FilesystemReader::from_reader_with_offset_and_kind(
-        Cursor::new(squashfs),
-        0,
+        BufReader::new(file),
+        offset,
         Kind::from_target("le_v4_0").unwrap(),
     )?;
 
@@ -187,8 +273,18 @@ pub async fn build_linux(builder: &Builder, data: &[u8]) -> color_eyre::Result<(
     // let icon_path = builder.paths.root.join(icon);
     let mut writer =
         skip_file_from_squashfs(&reader, to_skip, builder.config.icon.is_some()).unwrap();
+    let linux = builder.config.linux.as_ref();
     // The AppImage of love2d doesn't support xz
-    writer.set_compressor(FilesystemCompressor::new(Compressor::Zstd, None).unwrap());
+    writer.set_compressor(get_compressor(linux).context("Creating the squashfs compressor")?);
+    if let Some(block_size) = linux.and_then(|x| x.block_size) {
+        if !block_size.is_power_of_two() || !(4096..=1048576).contains(&block_size) {
+            return Err(eyre!(
+                "Invalid block_size {}, it must be a power of two between 4096 and 1048576",
+                block_size
+            ));
+        }
+        writer.set_block_size(block_size);
+    }
 
     bts.extend_from_slice(&data);
     writer.push_file(
@@ -242,35 +338,140 @@ NoDisplay=true"#,
         )?;
     }
 
-    for pattern in &builder.config.layout.external {
-        for path in glob::glob(&builder.paths.root.join(pattern).to_string_lossy())
-            .context("Building for windows")
-            .expect("Failed to parse glob")
-            .filter_map(Result::ok)
-        {
-            let output = PathBuf::new().join("bin").join(
-                path.strip_prefix(&builder.paths.root)
-                    .context("Building for windows")
-                    .suggestion("Don't use assets outside the root of your project")
-                    .expect("Failed to strip root"),
-            );
-            let data = fs_err::tokio::read(&path).await?;
-            writer.push_dir_all(output.parent().unwrap(), NodeHeader::default())?;
-            writer.push_file(std::io::Cursor::new(data), output, NodeHeader::default())?;
-        }
+    for path in builder.layout_files(&builder.config.layout.external) {
+        let output = PathBuf::new().join("bin").join(
+            path.strip_prefix(&builder.paths.root)
+                .context("Building for windows")
+                .suggestion("Don't use assets outside the root of your project")
+                .expect("Failed to strip root"),
+        );
+        writer.push_dir_all(output.parent().unwrap(), NodeHeader::default())?;
+        writer.push_file(LazyFile::new(path), output, NodeHeader::default())?;
     }
 
     create_dir_all(&dists).await?;
 
+    if let Some(info) = linux.and_then(|x| x.update_information.as_ref()) {
+        elf_utils::write_section(&mut appimage, ".upd_info", info.as_bytes())
+            .context("Writing the update information")?;
+    }
+
     fs_err::tokio::remove_file(dists.join("love2d.AppImage")).await?;
-    let mut output_file = fs_err::tokio::File::create(
-        dists.join(format!("{}.AppImage", builder.config.project_name)),
-    )
-    .await?;
-
-    output_file.write_all(&appimage).await?;
-    let mut data = Cursor::new(vec![]);
-    writer.write(&mut data)?;
-    output_file.write_all(data.get_ref()).await?;
+    let file_name = format!("{}.AppImage", builder.config.project_name);
+    let output_path = dists.join(&file_name);
+    {
+        let mut output_file = BufWriter::new(fs_err::File::create(&output_path)?);
+        output_file.write_all(&appimage)?;
+        writer.write_with_offset(&mut output_file, appimage.len() as u64)?;
+        output_file.flush()?;
+    }
+
+    let Some(linux) = linux else {
+        return Ok(());
+    };
+    if linux.sign {
+        sign_appimage(&output_path, &mut appimage, linux.sign_key.as_deref())
+            .await
+            .context("Signing the AppImage")
+            .suggestion("Make sure gpg is installed and has a secret key available")?;
+    }
+    let uses_zsync = linux
+        .update_information
+        .as_ref()
+        .is_some_and(|x| x.starts_with("zsync|") || x.starts_with("gh-releases-zsync|"));
+    if linux.zsync.unwrap_or(uses_zsync) {
+        let zsync_path = dists.join(format!("{}.zsync", file_name));
+        tokio::task::spawn_blocking(move || {
+            zsync_utils::make_zsync(&output_path, &file_name, &zsync_path)
+        })
+        .await?
+        .context("Generating the zsync file")?;
+    }
     Ok(())
 }
+
+async fn run_gpg(args: &[&str], input: Option<&[u8]>) -> color_eyre::Result<Vec<u8>> {
+    let mut child = Command::new("gpg")
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .context("Failed to spawn gpg")?;
+    let mut stdin = child.stdin.take().expect("gpg stdin is piped");
+    if let Some(input) = input {
+        stdin.write_all(input).await?;
+    }
+    drop(stdin);
+    let output = child.wait_with_output().await?;
+    if !output.status.success() || output.stdout.is_empty() {
+        return Err(eyre!("gpg {} failed", args.join(" ")));
+    }
+    Ok(output.stdout)
+}
+
+/// Signs the AppImage the same way appimagetool does, the signature covers the
+/// hex encoded sha256 of the whole file with the signature sections zeroed.
+async fn sign_appimage(
+    path: &Path,
+    runtime: &mut [u8],
+    key: Option<&str>,
+) -> color_eyre::Result<()> {
+    let mut unsigned = runtime.to_vec();
+    elf_utils::write_section(&mut unsigned, ".sha256_sig", &[])?;
+    elf_utils::write_section(&mut unsigned, ".sig_key", &[])?;
+
+    let digest = {
+        let path = path.to_path_buf();
+        let runtime_size = runtime.len() as u64;
+        tokio::task::spawn_blocking(move || -> color_eyre::Result<String> {
+            let mut hasher = sha2::Sha256::new();
+            hasher.update(&unsigned);
+            let mut file = std::fs::File::open(path)?;
+            file.seek(SeekFrom::Start(runtime_size))?;
+            std::io::copy(&mut file, &mut hasher)?;
+            Ok(hex::encode(hasher.finalize()))
+        })
+        .await??
+    };
+
+    let mut sign_args = vec!["--batch", "--yes", "--armor", "--detach-sign"];
+    let mut export_args = vec!["--batch", "--armor", "--export"];
+    if let Some(key) = key {
+        sign_args.extend(["--local-user", key]);
+        export_args.push(key);
+    }
+    let signature = run_gpg(&sign_args, Some(digest.as_bytes())).await?;
+    let public_key = run_gpg(&export_args, None).await?;
+
+    elf_utils::write_section(runtime, ".sha256_sig", &signature)?;
+    elf_utils::write_section(runtime, ".sig_key", &public_key)?;
+
+    let mut file = fs_err::tokio::OpenOptions::new()
+        .write(true)
+        .open(path)
+        .await?;
+    file.write_all(runtime).await?;
+    file.flush().await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lazy_file_stays_at_the_end() {
+        let path = std::env::temp_dir().join(format!("kaledis-lazy-{}", std::process::id()));
+        std::fs::write(&path, [7; 10]).unwrap();
+        let mut file = LazyFile::new(path.clone());
+        let mut buf = [0; 4];
+        let mut total = 0;
+        for _ in 0..3 {
+            total += file.read(&mut buf).unwrap();
+        }
+        assert_eq!(total, 10);
+        assert_eq!(file.read(&mut buf).unwrap(), 0);
+        assert_eq!(file.read(&mut buf).unwrap(), 0);
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -1,15 +1,17 @@
 use color_eyre::{
     Section,
-    eyre::{Context, ContextCompat},
+    eyre::{Context, ContextCompat, eyre},
 };
 use colored::Colorize;
-use fs_err::tokio::{File, create_dir_all, hard_link};
+use fs_err::tokio::{File, create_dir_all, hard_link, remove_dir_all, rename};
 use icns::{IconFamily, IconType, PixelFormat};
 use image::{DynamicImage, ImageReader, imageops::FilterType};
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use tracing::warn;
+use plist::XmlWriteOptions;
+use std::path::Path;
+use tokio::io::AsyncWriteExt;
+use tracing::{info, warn};
 
-use crate::{commands::build::Builder, toml_conf::KaledisConfig};
+use crate::{commands::build::Builder, toml_conf::KaledisConfig, zip_utils::Zipper};
 
 fn resize_to_icns(img: &DynamicImage, size: u32) -> icns::Image {
     let resized = img
@@ -20,39 +22,54 @@ fn resize_to_icns(img: &DynamicImage, size: u32) -> icns::Image {
         .expect("failed to convert image")
 }
 
+// Every size the iconset format expects, from 16x16 to 512x512@2x
+const ICON_SIZES: [(u32, IconType); 10] = [
+    (16, IconType::RGBA32_16x16),
+    (32, IconType::RGBA32_16x16_2x),
+    (32, IconType::RGBA32_32x32),
+    (64, IconType::RGBA32_32x32_2x),
+    (128, IconType::RGBA32_128x128),
+    (256, IconType::RGBA32_128x128_2x),
+    (256, IconType::RGBA32_256x256),
+    (512, IconType::RGBA32_256x256_2x),
+    (512, IconType::RGBA32_512x512),
+    (1024, IconType::RGBA32_512x512_2x),
+];
+
 pub async fn build_macos(builder: &Builder, data: &[u8]) -> color_eyre::Result<()> {
-    if builder.config.mac.is_none() {
+    let Some(mac) = &builder.config.mac else {
         warn!("No valid macos config, skipping macos build");
         return Ok(());
-    }
+    };
 
     println!(
         "{}", "WARNING: only unsigned builds are available for now. i don't have an mac. If you want to publish it officially i recommend using https://github.com/love2d/love/actions/".yellow()
     );
 
     let dists = builder.paths.dist.join("Macos");
-    let contents = dists.join("love.app").join("Contents");
+    let app_name = format!("{}.app", builder.config.project_name);
+    let app = dists.join(&app_name);
+    // A project called love already has the app of the runtime under its name, and on
+    // the file systems ignoring case Love.app is love.app
+    if app_name != "love.app" {
+        if app.exists() && !app_name.eq_ignore_ascii_case("love.app") {
+            remove_dir_all(&app).await?;
+        }
+        rename(dists.join("love.app"), &app)
+            .await
+            .context("Renaming love.app")?;
+    }
+
+    let contents = app.join("Contents");
     let resources = contents.join("Resources");
 
     if let Some(icon) = &builder.config.icon {
         let img = ImageReader::open(builder.paths.root.join(icon))?.decode()?;
         let mut family = IconFamily::new();
 
-        // ic04 → 16x16
-        let img16 = resize_to_icns(&img, 16);
-        family.add_icon_with_type(&img16, IconType::RGBA32_16x16)?;
-
-        // ic11 → 32x32 (retina 16)
-        let img32 = resize_to_icns(&img, 32);
-        family.add_icon_with_type(&img32, IconType::RGBA32_16x16_2x)?;
-
-        // ic07 → 128x128
-        let img128 = resize_to_icns(&img, 128);
-        family.add_icon_with_type(&img128, IconType::RGBA32_128x128)?;
-
-        // ic13 → 256x256 (retina 128)
-        let img256 = resize_to_icns(&img, 256);
-        family.add_icon_with_type(&img256, IconType::RGBA32_128x128_2x)?;
+        for (size, icon_type) in ICON_SIZES {
+            family.add_icon_with_type(&resize_to_icns(&img, size), icon_type)?;
+        }
 
         let file = std::io::BufWriter::new(
             std::fs::File::create(resources.join("OSXAppIcon2.icns")).unwrap(),
@@ -60,10 +77,6 @@ pub async fn build_macos(builder: &Builder, data: &[u8]) -> color_eyre::Result<(
         family.write(file).unwrap();
     }
 
-    create_dir_all(&dists)
-        .await
-        .expect("Failed to create macos dist folder");
-
     macro_rules! create {
         ($name: expr, $value :expr) => {{
             let mut f = File::create($name).await.expect("Failed to create file");
@@ -71,20 +84,15 @@ pub async fn build_macos(builder: &Builder, data: &[u8]) -> color_eyre::Result<(
         }};
     }
 
-    for pattern in &builder.config.layout.external {
-        for path in glob::glob(&builder.paths.root.join(pattern).to_string_lossy())
-            .context("Building for macos")?
-            .filter_map(Result::ok)
-        {
-            let output_path = resources.join(
-                path.strip_prefix(&builder.paths.root)
-                    .context("Building for macos")
-                    .suggestion("Don't use assets outside the root of your project")
-                    .expect("Failed to strip root"),
-            );
-            create_dir_all(output_path.parent().unwrap()).await?;
-            hard_link(&path, output_path).await?;
-        }
+    for path in builder.layout_files(&builder.config.layout.external) {
+        let output_path = resources.join(
+            path.strip_prefix(&builder.paths.root)
+                .context("Building for macos")
+                .suggestion("Don't use assets outside the root of your project")
+                .expect("Failed to strip root"),
+        );
+        create_dir_all(output_path.parent().unwrap()).await?;
+        hard_link(&path, output_path).await?;
     }
 
     create!(
@@ -93,56 +101,200 @@ pub async fn build_macos(builder: &Builder, data: &[u8]) -> color_eyre::Result<(
     );
 
     let plist_path = contents.join("Info.plist");
-    let data = {
-        let mut plist_file = File::open(&plist_path).await?;
-        rewrite_app_files(&builder.config, &mut plist_file).await?
-    };
+    let plist = fs_err::tokio::read(&plist_path).await?;
+    create!(
+        &plist_path,
+        rewrite_plist(&builder.config, &plist).context("Rewriting Info.plist")?
+    );
 
-    create!(plist_path, data.as_bytes());
+    if mac.zip {
+        info!("Zipping {}...", app_name);
+        let zip_path = dists.join(format!("{}.zip", builder.config.project_name));
+        let zipped = tokio::task::spawn_blocking(move || -> color_eyre::Result<Vec<u8>> {
+            let mut zip = Zipper::new();
+            zip.put_folder_with_permissions(&app, Path::new(&app_name))?;
+            Ok(zip.finish())
+        })
+        .await??;
+        create!(zip_path, zipped);
+    }
 
     Ok(())
 }
 
-// Credit: https://github.com/camchenry/boon
+fn json_to_plist(value: &serde_json::Value) -> color_eyre::Result<plist::Value> {
+    Ok(match value {
+        serde_json::Value::Null => return Err(eyre!("null is not a valid plist value")),
+        serde_json::Value::Bool(value) => plist::Value::Boolean(*value),
+        serde_json::Value::Number(number) => {
+            if let Some(integer) = number.as_i64() {
+                plist::Value::Integer(integer.into())
+            } else {
+                plist::Value::Real(number.as_f64().unwrap_or_default())
+            }
+        }
+        serde_json::Value::String(value) => plist::Value::String(value.clone()),
+        serde_json::Value::Array(values) => plist::Value::Array(
+            values
+                .iter()
+                .map(json_to_plist)
+                .collect::<color_eyre::Result<_>>()?,
+        ),
+        serde_json::Value::Object(map) => plist::Value::Dictionary(
+            map.iter()
+                .map(|(key, value)| Ok((key.clone(), json_to_plist(value)?)))
+                .collect::<color_eyre::Result<_>>()?,
+        ),
+    })
+}
 
-/// Rewrites the macOS application files to contain the project's info
-async fn rewrite_app_files(config: &KaledisConfig, file: &mut File) -> color_eyre::Result<String> {
+/// Rewrites the macOS Info.plist to contain the project's info
+pub fn rewrite_plist(config: &KaledisConfig, contents: &[u8]) -> color_eyre::Result<Vec<u8>> {
     let mac = config
         .mac
         .as_ref()
         .wrap_err("No Mac manifest in kaledis.toml")
         .suggestion("Try adding the mac field on the manifest")?;
-    let mut buffer = String::new();
-    file.read_to_string(&mut buffer).await?;
-    let re = regex::Regex::new("(CFBundleIdentifier.*\n\t<string>)(.*)(</string>)")
-        .context("Failed to create regex")?;
-
-    buffer = re
-        .replace(buffer.as_str(), |caps: &regex::Captures| {
-            [
-                caps.get(1).expect("Could not get capture").as_str(),
-                mac.id.as_str(),
-                caps.get(3).expect("Could not get capture").as_str(),
-            ]
-            .join("")
-        })
-        .to_string();
-    let re = regex::Regex::new("(CFBundleName.*\n\t<string>)(.*)(</string>)")
-        .context("Could not create regex")?;
-    buffer = re
-        .replace(buffer.as_str(), |caps: &regex::Captures| {
-            [
-                caps.get(1).expect("Could not get capture").as_str(),
-                config.project_name.as_str(),
-                caps.get(3).expect("Could not get capture").as_str(),
-            ]
-            .join("")
-        })
-        .to_string();
-    let re = regex::RegexBuilder::new("^\t<key>UTExportedTypeDeclarations.*(\n.*)+\t</array>\n")
-        .multi_line(true)
-        .build()
-        .context("Could not build regex")?;
-    buffer = re.replace(buffer.as_str(), "").to_string();
-    Ok(buffer)
+
+    let mut plist = plist::Value::from_reader_xml(contents)?;
+    let dict = plist
+        .as_dictionary_mut()
+        .wrap_err("The root of Info.plist is not a dictionary")?;
+
+    macro_rules! set {
+        ($key: expr, $value: expr) => {
+            dict.insert($key.to_string(), plist::Value::from($value.clone()))
+        };
+    }
+
+    set!("CFBundleIdentifier", mac.id);
+    set!("CFBundleName", config.project_name);
+    if let Some(version) = &mac.version {
+        set!("CFBundleShortVersionString", version);
+    }
+    if let Some(build) = mac.build_version.as_ref().or(mac.version.as_ref()) {
+        set!("CFBundleVersion", build);
+    }
+    if let Some(copyright) = &mac.copyright {
+        set!("NSHumanReadableCopyright", copyright);
+    }
+    set!("LSApplicationCategoryType", mac.category);
+    if let Some(version) = &mac.minimum_system_version {
+        set!("LSMinimumSystemVersion", version);
+    }
+    set!("NSHighResolutionCapable", mac.high_resolution);
+    if config.icon.is_some() {
+        // The icon name points to the Assets.car of love, which has priority over the icns
+        dict.remove("CFBundleIconName");
+        set!("CFBundleIconFile", "OSXAppIcon2");
+    }
+    // Love registers itself as the owner of .love files, which games shouldn't do
+    dict.remove("UTExportedTypeDeclarations");
+
+    for key in &mac.plist_remove {
+        dict.remove(key);
+    }
+    for (key, value) in &mac.plist {
+        dict.insert(
+            key.clone(),
+            json_to_plist(value).with_context(|| format!("Converting mac.plist.{}", key))?,
+        );
+    }
+
+    let mut output = vec![];
+    plist.to_writer_xml_with_options(&mut output, &XmlWriteOptions::default().indent(b'\t', 1))?;
+    output.push(b'\n');
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LOVE_PLIST: &[u8] = include_bytes!("../../../tests/fixtures/love-11.5-Info.plist");
+
+    fn config(mac: &str) -> KaledisConfig {
+        toml::from_str(&format!(
+            "project_name = \"Game\"\nlove = \"11.5\"\nicon = \"icon.png\"\n[layout]\n[mac]\n{}",
+            mac
+        ))
+        .unwrap()
+    }
+
+    fn rewrite(mac: &str) -> plist::Dictionary {
+        let output = rewrite_plist(&config(mac), LOVE_PLIST).unwrap();
+        plist::Value::from_reader_xml(output.as_slice())
+            .unwrap()
+            .into_dictionary()
+            .unwrap()
+    }
+
+    #[test]
+    fn sets_the_project_info() {
+        let dict = rewrite(
+            r#"id = "com.example.game"
+version = "1.2"
+copyright = "Example"
+minimum_system_version = "10.15""#,
+        );
+        let string = |key: &str| dict.get(key).and_then(|x| x.as_string()).unwrap();
+        assert_eq!(string("CFBundleIdentifier"), "com.example.game");
+        assert_eq!(string("CFBundleName"), "Game");
+        assert_eq!(string("CFBundleShortVersionString"), "1.2");
+        assert_eq!(string("CFBundleVersion"), "1.2");
+        assert_eq!(string("NSHumanReadableCopyright"), "Example");
+        assert_eq!(string("LSMinimumSystemVersion"), "10.15");
+        assert_eq!(string("CFBundleIconFile"), "OSXAppIcon2");
+        assert!(!dict.contains_key("CFBundleIconName"));
+        assert!(!dict.contains_key("UTExportedTypeDeclarations"));
+        // The keys kaledis doesn't know about are kept
+        assert_eq!(string("CFBundleExecutable"), "love");
+        assert!(dict.contains_key("CFBundleDocumentTypes"));
+    }
+
+    #[test]
+    fn applies_the_overrides() {
+        let dict = rewrite(
+            r#"id = "com.example.game"
+plist_remove = ["CFBundleDocumentTypes", "NSSupportsAutomaticGraphicsSwitching"]
+[mac.plist]
+CFBundleName = "Overridden"
+LSUIElement = true
+GCSupportedGameControllers = [{ ProfileName = "ExtendedGamepad" }]
+NSCameraUsageDescription = "Scans codes""#,
+        );
+        assert!(!dict.contains_key("CFBundleDocumentTypes"));
+        assert!(!dict.contains_key("NSSupportsAutomaticGraphicsSwitching"));
+        assert_eq!(
+            dict.get("CFBundleName").and_then(|x| x.as_string()),
+            Some("Overridden")
+        );
+        assert_eq!(
+            dict.get("LSUIElement").and_then(|x| x.as_boolean()),
+            Some(true)
+        );
+        let controllers = dict
+            .get("GCSupportedGameControllers")
+            .and_then(|x| x.as_array())
+            .unwrap();
+        assert_eq!(
+            controllers[0]
+                .as_dictionary()
+                .and_then(|x| x.get("ProfileName"))
+                .and_then(|x| x.as_string()),
+            Some("ExtendedGamepad")
+        );
+    }
+
+    #[test]
+    fn rejects_null_overrides() {
+        let mut config = config("id = \"com.example.game\"");
+        config
+            .mac
+            .as_mut()
+            .unwrap()
+            .plist
+            .insert("Broken".to_string(), serde_json::Value::Null);
+        assert!(rewrite_plist(&config, LOVE_PLIST).is_err());
+    }
 }
@@ -0,0 +1,1131 @@
+use std::{
+    cell::RefCell,
+    collections::BTreeSet,
+    io::Read,
+    path::{Component, Path, PathBuf},
+};
+
+use base64::{Engine, engine::general_purpose::STANDARD};
+use color_eyre::eyre::{Context, eyre};
+use colored::Colorize;
+use flate2::read::{GzDecoder, ZlibDecoder};
+use indicatif::ProgressBar;
+use serde_json::{Map, Value, json};
+
+use crate::{
+    commands::build::{
+        Builder,
+        assets::{KEYWORDS, asset_paths, love_path},
+    },
+    toml_conf::MapsConfig,
+    xml_utils::XmlElement,
+};
+
+/// The maps that are converted, Tiled ones in XML and JSON and LDtk projects
+pub const MAP_EXTENSIONS: [&str; 3] = ["tmx", "tmj", "ldtk"];
+/// Files the maps load, tilesets, object templates and LDtk levels saved separately
+pub const MAP_DEPENDENCIES: [&str; 5] = ["tsx", "tsj", "tx", "tj", "ldtkl"];
+pub const MAPS_DEFINITIONS: &str = ".kaledis/maps.d.luau";
+
+// Attributes that look like numbers but are names or colors
+const STRING_ATTRIBUTES: [&str; 26] = [
+    "name",
+    "class",
+    "type",
+    "version",
+    "tiledversion",
+    "orientation",
+    "renderorder",
+    "staggeraxis",
+    "staggerindex",
+    "draworder",
+    "tintcolor",
+    "backgroundcolor",
+    "color",
+    "trans",
+    "encoding",
+    "compression",
+    "halign",
+    "valign",
+    "fontfamily",
+    "objectalignment",
+    "tilerendersize",
+    "fillmode",
+    "propertytype",
+    "image",
+    "source",
+    "template",
+];
+const BOOLEAN_ATTRIBUTES: [&str; 11] = [
+    "visible",
+    "locked",
+    "infinite",
+    "repeatx",
+    "repeaty",
+    "wrap",
+    "bold",
+    "italic",
+    "underline",
+    "strikeout",
+    "kerning",
+];
+
+/// Joins a path written inside a file with the folder of the file, without touching the disk
+pub fn resolve(base: &Path, relative: &str) -> PathBuf {
+    let mut result = PathBuf::new();
+    for component in base.join(relative.replace('\\', "/")).components() {
+        match component {
+            Component::ParentDir => {
+                result.pop();
+            }
+            Component::CurDir => {}
+            other => result.push(other),
+        }
+    }
+    result
+}
+
+fn number(value: &str) -> Option<Value> {
+    if let Ok(integer) = value.parse::<i64>() {
+        return Some(json!(integer));
+    }
+    value
+        .parse::<f64>()
+        .ok()
+        .filter(|x| x.is_finite())
+        .map(|x| json!(x))
+}
+
+fn attribute(key: &str, value: &str) -> Value {
+    if BOOLEAN_ATTRIBUTES.contains(&key) {
+        return Value::Bool(value == "1" || value == "true");
+    }
+    if STRING_ATTRIBUTES.contains(&key) {
+        return Value::String(value.to_string());
+    }
+    number(value).unwrap_or_else(|| Value::String(value.to_string()))
+}
+
+fn attributes(element: &XmlElement) -> Map<String, Value> {
+    element
+        .attributes
+        .iter()
+        .map(|(key, value)| (key.clone(), attribute(key, value)))
+        .collect()
+}
+
+/// Fills the attributes Tiled leaves out when they have their default value
+fn defaults(object: &mut Map<String, Value>, values: &[(&str, Value)]) {
+    for (key, value) in values {
+        object
+            .entry(key.to_string())
+            .or_insert_with(|| value.clone());
+    }
+}
+
+fn child<'a>(element: &'a XmlElement, name: &str) -> Option<&'a XmlElement> {
+    element.elements().find(|x| x.name == name)
+}
+
+fn points(text: &str) -> Value {
+    Value::Array(
+        text.split_whitespace()
+            .filter_map(|point| point.split_once(','))
+            .map(|(x, y)| json!({ "x": number(x), "y": number(y) }))
+            .collect(),
+    )
+}
+
+/// The global tile ids of a tile layer or chunk, in any of the encodings Tiled saves
+fn tile_data(
+    data: &XmlElement,
+    encoding: Option<&str>,
+    compression: Option<&str>,
+) -> color_eyre::Result<Value> {
+    let gids: Vec<u32> = match encoding {
+        Some("csv") => data
+            .text
+            .split(',')
+            .map(str::trim)
+            .filter(|x| !x.is_empty())
+            .map(str::parse)
+            .collect::<Result<_, _>>()?,
+        Some("base64") => decode_tiles(&data.text, compression)?,
+        None => data
+            .elements()
+            .filter(|x| x.name == "tile")
+            .map(|x| x.get_attr("gid").and_then(|x| x.parse().ok()).unwrap_or(0))
+            .collect(),
+        Some(other) => return Err(eyre!("Unknown tile encoding {}", other)),
+    };
+    Ok(json!(gids))
+}
+
+fn decode_tiles(text: &str, compression: Option<&str>) -> color_eyre::Result<Vec<u32>> {
+    let bytes = STANDARD.decode(text.split_whitespace().collect::<String>())?;
+    let bytes = match compression.unwrap_or("") {
+        "" => bytes,
+        "zlib" => {
+            let mut output = vec![];
+            ZlibDecoder::new(bytes.as_slice()).read_to_end(&mut output)?;
+            output
+        }
+        "gzip" => {
+            let mut output = vec![];
+            GzDecoder::new(bytes.as_slice()).read_to_end(&mut output)?;
+            output
+        }
+        "zstd" => zstd::decode_all(bytes.as_slice())?,
+        other => return Err(eyre!("Unknown tile compression {}", other)),
+    };
+    Ok(bytes
+        .chunks_exact(4)
+        .map(|x| u32::from_le_bytes([x[0], x[1], x[2], x[3]]))
+        .collect())
+}
+
+/// Objects of the JSON format mark their shape with flags, the Lua export names it
+fn object_shape(object: Value) -> Value {
+    let Value::Object(mut object) = object else {
+        return object;
+    };
+    let shape = if object.remove("ellipse") == Some(Value::Bool(true)) {
+        "ellipse"
+    } else if object.remove("point") == Some(Value::Bool(true)) {
+        "point"
+    } else if object.contains_key("polygon") {
+        "polygon"
+    } else if object.contains_key("polyline") {
+        "polyline"
+    } else if let Some(Value::Object(text)) = object.remove("text") {
+        for (key, value) in text {
+            object.insert(key, value);
+        }
+        "text"
+    } else {
+        "rectangle"
+    };
+    object.insert("shape".to_string(), json!(shape));
+    Value::Object(object)
+}
+
+fn lua_string(text: &str) -> String {
+    let mut result = String::from("\"");
+    for character in text.chars() {
+        match character {
+            '\\' => result.push_str("\\\\"),
+            '"' => result.push_str("\\\""),
+            '\n' => result.push_str("\\n"),
+            '\r' => result.push_str("\\r"),
+            '\t' => result.push_str("\\t"),
+            // Lua 5.1 only escapes bytes
+            x if x.is_control() => {
+                let mut bytes = [0; 4];
+                for byte in x.encode_utf8(&mut bytes).bytes() {
+                    result.push_str(&format!("\\{:03}", byte));
+                }
+            }
+            x => result.push(x),
+        }
+    }
+    result.push('"');
+    result
+}
+
+fn is_identifier(key: &str) -> bool {
+    key.starts_with(|x: char| x.is_ascii_alphabetic() || x == '_')
+        && key.chars().all(|x| x.is_ascii_alphanumeric() || x == '_')
+        && !KEYWORDS.contains(&key)
+}
+
+fn write_key(out: &mut String, key: &str) {
+    if is_identifier(key) {
+        out.push_str(key);
+    } else {
+        out.push_str(&format!("[{}]", lua_string(key)));
+    }
+    out.push_str(" = ");
+}
+
+fn is_scalar(value: &Value) -> bool {
+    !matches!(value, Value::Array(_) | Value::Object(_))
+}
+
+/// Writes a value as a Lua table constructor, null fields are left out
+pub fn write_lua(out: &mut String, value: &Value, depth: usize) {
+    let indent = "\t".repeat(depth + 1);
+    let closing = "\t".repeat(depth);
+    match value {
+        Value::Null => out.push_str("nil"),
+        Value::Bool(value) => out.push_str(&value.to_string()),
+        Value::Number(value) => out.push_str(&value.to_string()),
+        Value::String(value) => out.push_str(&lua_string(value)),
+        Value::Array(items) if items.is_empty() => out.push_str("{}"),
+        // Tile data is kept in rows instead of one value per line
+        Value::Array(items) if items.iter().all(is_scalar) => {
+            if items.len() <= 16 {
+                out.push_str("{ ");
+                for (index, item) in items.iter().enumerate() {
+                    if index > 0 {
+                        out.push_str(", ");
+                    }
+                    write_lua(out, item, depth);
+                }
+                out.push_str(" }");
+                return;
+            }
+            out.push_str("{\n");
+            for row in items.chunks(32) {
+                out.push_str(&indent);
+                for item in row {
+                    write_lua(out, item, depth);
+                    out.push_str(", ");
+                }
+                out.truncate(out.trim_end().len());
+                out.push('\n');
+            }
+            out.push_str(&closing);
+            out.push('}');
+        }
+        Value::Array(items) => {
+            out.push_str("{\n");
+            for item in items {
+                out.push_str(&indent);
+                write_lua(out, item, depth + 1);
+                out.push_str(",\n");
+            }
+            out.push_str(&closing);
+            out.push('}');
+        }
+        Value::Object(fields) => {
+            if fields.values().all(Value::is_null) {
+                out.push_str("{}");
+                return;
+            }
+            // Points and offsets fit in one line
+            if fields.len() <= 4 && fields.values().all(is_scalar) {
+                out.push_str("{ ");
+                for (index, (key, value)) in fields.iter().filter(|(_, x)| !x.is_null()).enumerate()
+                {
+                    if index > 0 {
+                        out.push_str(", ");
+                    }
+                    write_key(out, key);
+                    write_lua(out, value, depth);
+                }
+                out.push_str(" }");
+                return;
+            }
+            out.push_str("{\n");
+            for (key, value) in fields.iter().filter(|(_, value)| !value.is_null()) {
+                out.push_str(&indent);
+                write_key(out, key);
+                write_lua(out, value, depth + 1);
+                out.push_str(",\n");
+            }
+            out.push_str(&closing);
+            out.push('}');
+        }
+    }
+}
+
+fn inline_levels(levels: &mut Value, base: &Path) -> color_eyre::Result<()> {
+    for level in levels.as_array_mut().into_iter().flatten() {
+        if let Some(Value::String(relative)) = level.get("externalRelPath") {
+            let file = resolve(base, relative);
+            *level = serde_json::from_str(&fs_err::read_to_string(&file)?)
+                .with_context(|| format!("Reading the level {}", file.display()))?;
+        }
+    }
+    Ok(())
+}
+
+struct Converter<'a> {
+    root: &'a Path,
+    /// The LÖVE paths of the files matched by the layout, to warn about images left out
+    assets: BTreeSet<String>,
+    /// The map being converted, for the warnings
+    map: String,
+    /// Leaves the warnings to the conversion when only looking for the files the maps use
+    quiet: bool,
+    used: RefCell<BTreeSet<String>>,
+}
+
+impl Converter<'_> {
+    /// A path written inside a map turned into the one LÖVE opens, relative to the root of the project
+    fn love_path(&self, base: &Path, path: &str) -> String {
+        let resolved = resolve(base, path);
+        let result = love_path(self.root, &resolved);
+        if let Some(result) = &result {
+            self.used.borrow_mut().insert(result.clone());
+        }
+        if self.quiet {
+            return result.unwrap_or(path.to_string());
+        }
+        let Some(result) = result else {
+            tracing::warn!(
+                "{} points to {} outside of the project, it is kept as it is",
+                self.map.yellow(),
+                path
+            );
+            return path.to_string();
+        };
+        if !resolved.exists() {
+            tracing::warn!(
+                "{} points to {} which doesn't exist",
+                self.map.yellow(),
+                result
+            );
+        } else if !self.assets.contains(&result) {
+            tracing::warn!(
+                "{} uses {} which isn't matched by layout.bundle or layout.external",
+                self.map.yellow(),
+                result
+            );
+        }
+        result
+    }
+
+    fn properties(&self, element: &XmlElement, base: &Path) -> Value {
+        let mut result = Map::new();
+        for property in child(element, "properties")
+            .into_iter()
+            .flat_map(XmlElement::elements)
+        {
+            let Some(name) = property.get_attr("name") else {
+                continue;
+            };
+            // Multiline strings are saved as the text of the property
+            let value = property.get_attr("value").unwrap_or(&property.text);
+            let value = match property.get_attr("type").unwrap_or("string") {
+                "int" | "float" | "object" => number(value).unwrap_or(json!(0)),
+                "bool" => json!(value == "true"),
+                "file" if !value.is_empty() => json!(self.love_path(base, value)),
+                "class" => self.properties(property, base),
+                _ => json!(value),
+            };
+            result.insert(name.to_string(), value);
+        }
+        Value::Object(result)
+    }
+
+    fn object(&self, element: &XmlElement, base: &Path) -> Value {
+        let mut object = attributes(element);
+        // Tiled 1.9 saved the type as class
+        if let Some(class) = object.remove("class") {
+            object.insert("type".to_string(), class);
+        }
+        if let Some(Value::String(template)) = object.get_mut("template") {
+            *template = self.love_path(base, template);
+        }
+        defaults(
+            &mut object,
+            &[
+                ("name", json!("")),
+                ("type", json!("")),
+                ("x", json!(0)),
+                ("y", json!(0)),
+                ("width", json!(0)),
+                ("height", json!(0)),
+                ("rotation", json!(0)),
+                ("visible", json!(true)),
+            ],
+        );
+        let mut shape = "rectangle";
+        for element in element.elements() {
+            match element.name.as_str() {
+                "ellipse" => shape = "ellipse",
+                "point" => shape = "point",
+                "polygon" | "polyline" => {
+                    shape = if element.name == "polygon" {
+                        "polygon"
+                    } else {
+                        "polyline"
+                    };
+                    object.insert(
+                        element.name.clone(),
+                        points(element.get_attr("points").unwrap_or("")),
+                    );
+                }
+                "text" => {
+                    shape = "text";
+                    object.extend(attributes(element));
+                    object.insert("text".to_string(), json!(element.text));
+                }
+                _ => {}
+            }
+        }
+        object.insert("shape".to_string(), json!(shape));
+        object.insert("properties".to_string(), self.properties(element, base));
+        Value::Object(object)
+    }
+
+    fn layer(&self, element: &XmlElement, base: &Path) -> color_eyre::Result<Option<Value>> {
+        let kind = match element.name.as_str() {
+            "layer" => "tilelayer",
+            "objectgroup" => "objectgroup",
+            "imagelayer" => "imagelayer",
+            "group" => "group",
+            _ => return Ok(None),
+        };
+        let mut layer = attributes(element);
+        layer.insert("type".to_string(), json!(kind));
+        defaults(
+            &mut layer,
+            &[
+                ("name", json!("")),
+                ("class", json!("")),
+                ("visible", json!(true)),
+                ("opacity", json!(1)),
+                ("offsetx", json!(0)),
+                ("offsety", json!(0)),
+                ("parallaxx", json!(1)),
+                ("parallaxy", json!(1)),
+            ],
+        );
+        layer.insert("properties".to_string(), self.properties(element, base));
+
+        match kind {
+            "tilelayer" => {
+                if let Some(data) = child(element, "data") {
+                    let encoding = data.get_attr("encoding");
+                    let compression = data.get_attr("compression");
+                    let chunks: Vec<_> = data.elements().filter(|x| x.name == "chunk").collect();
+                    // Infinite maps split the tiles in chunks
+                    if chunks.is_empty() {
+                        layer.insert("data".to_string(), tile_data(data, encoding, compression)?);
+                    } else {
+                        let mut result = vec![];
+                        for chunk in chunks {
+                            let mut value = attributes(chunk);
+                            value.insert(
+                                "data".to_string(),
+                                tile_data(chunk, encoding, compression)?,
+                            );
+                            result.push(Value::Object(value));
+                        }
+                        layer.insert("chunks".to_string(), Value::Array(result));
+                    }
+                }
+                layer.insert("encoding".to_string(), json!("lua"));
+            }
+            "objectgroup" => {
+                defaults(&mut layer, &[("draworder", json!("topdown"))]);
+                let objects = element
+                    .elements()
+                    .filter(|x| x.name == "object")
+                    .map(|x| self.object(x, base))
+                    .collect();
+                layer.insert("objects".to_string(), Value::Array(objects));
+            }
+            "imagelayer" => {
+                if let Some(image) = child(element, "image")
+                    && let Some(source) = image.get_attr("source")
+                {
+                    layer.insert("image".to_string(), json!(self.love_path(base, source)));
+                }
+            }
+            _ => {
+                layer.insert(
+                    "layers".to_string(),
+                    Value::Array(self.layers(element, base)?),
+                );
+            }
+        }
+        Ok(Some(Value::Object(layer)))
+    }
+
+    fn layers(&self, element: &XmlElement, base: &Path) -> color_eyre::Result<Vec<Value>> {
+        let mut layers = vec![];
+        for element in element.elements() {
+            if let Some(layer) = self.layer(element, base)? {
+                layers.push(layer);
+            }
+        }
+        Ok(layers)
+    }
+
+    fn tileset(&self, element: &XmlElement, base: &Path) -> color_eyre::Result<Value> {
+        if let Some(source) = element.get_attr("source") {
+            let mut tileset = self.external_tileset(&resolve(base, source))?;
+            if let Some(firstgid) = element.get_attr("firstgid") {
+                tileset["firstgid"] = attribute("firstgid", firstgid);
+            }
+            return Ok(tileset);
+        }
+
+        let mut tileset = attributes(element);
+        defaults(
+            &mut tileset,
+            &[
+                ("class", json!("")),
+                ("spacing", json!(0)),
+                ("margin", json!(0)),
+                ("columns", json!(0)),
+                ("tilecount", json!(0)),
+            ],
+        );
+        if let Some(image) = child(element, "image") {
+            if let Some(source) = image.get_attr("source") {
+                tileset.insert("image".to_string(), json!(self.love_path(base, source)));
+            }
+            for (key, name) in [
+                ("width", "imagewidth"),
+                ("height", "imageheight"),
+                ("trans", "transparentcolor"),
+            ] {
+                if let Some(value) = image.get_attr(key) {
+                    tileset.insert(name.to_string(), attribute(key, value));
+                }
+            }
+        }
+        let offset = child(element, "tileoffset").map(attributes);
+        tileset.insert(
+            "tileoffset".to_string(),
+            offset.map_or(json!({ "x": 0, "y": 0 }), Value::Object),
+        );
+        if let Some(grid) = child(element, "grid") {
+            tileset.insert("grid".to_string(), Value::Object(attributes(grid)));
+        }
+        tileset.insert("properties".to_string(), self.properties(element, base));
+
+        let mut tiles = vec![];
+        for tile in element.elements().filter(|x| x.name == "tile") {
+            let mut value = attributes(tile);
+            if let Some(class) = value.remove("class") {
+                value.insert("type".to_string(), class);
+            }
+            if let Some(properties) = child(tile, "properties").map(|_| self.properties(tile, base))
+            {
+                value.insert("properties".to_string(), properties);
+            }
+            // Tilesets made of separate images
+            if let Some(image) = child(tile, "image") {
+                if let Some(source) = image.get_attr("source") {
+                    value.insert("image".to_string(), json!(self.love_path(base, source)));
+                }
+                for key in ["width", "height"] {
+                    if let Some(size) = image.get_attr(key) {
+                        value.insert(key.to_string(), attribute(key, size));
+                    }
+                }
+            }
+            if let Some(animation) = child(tile, "animation") {
+                let frames = animation
+                    .elements()
+                    .map(|x| Value::Object(attributes(x)))
+                    .collect();
+                value.insert("animation".to_string(), Value::Array(frames));
+            }
+            if let Some(group) = child(tile, "objectgroup") {
+                value.insert("objectGroup".to_string(), json!(self.layer(group, base)?));
+            }
+            tiles.push(Value::Object(value));
+        }
+        tileset.insert("tiles".to_string(), Value::Array(tiles));
+        Ok(Value::Object(tileset))
+    }
+
+    /// Tilesets saved in their own file are inlined, in either format
+    fn external_tileset(&self, path: &Path) -> color_eyre::Result<Value> {
+        let text = fs_err::read_to_string(path)?;
+        let base = path.parent().unwrap();
+        let tileset = if path.extension().is_some_and(|x| x == "tsx") {
+            self.tileset(&XmlElement::parse(&text)?, base)
+        } else {
+            self.tiled_json(serde_json::from_str(&text)?, base)
+        };
+        tileset.with_context(|| format!("Reading the tileset {}", path.display()))
+    }
+
+    fn tmx(&self, path: &Path) -> color_eyre::Result<Value> {
+        let root = XmlElement::parse(&fs_err::read_to_string(path)?)?;
+        if root.name != "map" {
+            return Err(eyre!("Expected a <map>, found <{}>", root.name));
+        }
+        let base = path.parent().unwrap();
+        let mut map = attributes(&root);
+        defaults(
+            &mut map,
+            &[
+                ("class", json!("")),
+                ("infinite", json!(false)),
+                ("renderorder", json!("right-down")),
+            ],
+        );
+        map.insert("luaversion".to_string(), json!("5.1"));
+        map.insert("properties".to_string(), self.properties(&root, base));
+        let mut tilesets = vec![];
+        for tileset in root.elements().filter(|x| x.name == "tileset") {
+            tilesets.push(self.tileset(tileset, base)?);
+        }
+        map.insert("tilesets".to_string(), Value::Array(tilesets));
+        map.insert(
+            "layers".to_string(),
+            Value::Array(self.layers(&root, base)?),
+        );
+        Ok(Value::Object(map))
+    }
+
+    /// Brings a map or tileset saved as JSON to the shape of the Lua export
+    fn tiled_json(&self, value: Value, base: &Path) -> color_eyre::Result<Value> {
+        let mut object = match value {
+            Value::Array(items) => {
+                return Ok(Value::Array(
+                    items
+                        .into_iter()
+                        .map(|x| self.tiled_json(x, base))
+                        .collect::<color_eyre::Result<_>>()?,
+                ));
+            }
+            Value::Object(object) => object,
+            value => return Ok(value),
+        };
+        if let (Some(Value::String(source)), Some(firstgid)) =
+            (object.get("source"), object.get("firstgid"))
+        {
+            let mut tileset = self.external_tileset(&resolve(base, source))?;
+            tileset["firstgid"] = firstgid.clone();
+            return Ok(tileset);
+        }
+        if object.get("encoding").and_then(Value::as_str) == Some("base64") {
+            let compression = object
+                .remove("compression")
+                .and_then(|x| x.as_str().map(str::to_string));
+            if let Some(Value::String(data)) = object.get("data") {
+                let data = decode_tiles(data, compression.as_deref())?;
+                object.insert("data".to_string(), json!(data));
+            }
+            for chunk in object
+                .get_mut("chunks")
+                .and_then(Value::as_array_mut)
+                .into_iter()
+                .flatten()
+            {
+                if let Some(Value::String(data)) = chunk.get("data") {
+                    let data = decode_tiles(data, compression.as_deref())?;
+                    chunk["data"] = json!(data);
+                }
+            }
+        }
+        if object.contains_key("data") || object.contains_key("chunks") {
+            object.insert("encoding".to_string(), json!("lua"));
+        }
+
+        let mut result = Map::new();
+        for (key, value) in object {
+            let value = match (key.as_str(), value) {
+                ("properties", Value::Array(properties)) => self.json_properties(properties, base),
+                ("image" | "template", Value::String(path)) if !path.is_empty() => {
+                    json!(self.love_path(base, &path))
+                }
+                ("objects", Value::Array(objects)) => self.tiled_json(
+                    Value::Array(objects.into_iter().map(object_shape).collect()),
+                    base,
+                )?,
+                (_, value) => self.tiled_json(value, base)?,
+            };
+            result.insert(key, value);
+        }
+        Ok(Value::Object(result))
+    }
+
+    fn json_properties(&self, properties: Vec<Value>, base: &Path) -> Value {
+        let mut result = Map::new();
+        for property in properties {
+            let Some(name) = property["name"].as_str() else {
+                continue;
+            };
+            let value = match (property["type"].as_str(), &property["value"]) {
+                (Some("file"), Value::String(path)) if !path.is_empty() => {
+                    json!(self.love_path(base, path))
+                }
+                (_, value) => value.clone(),
+            };
+            result.insert(name.to_string(), value);
+        }
+        Value::Object(result)
+    }
+
+    fn tmj(&self, path: &Path) -> color_eyre::Result<Value> {
+        let map = serde_json::from_str(&fs_err::read_to_string(path)?)?;
+        let mut map = self.tiled_json(map, path.parent().unwrap())?;
+        map["luaversion"] = json!("5.1");
+        Ok(map)
+    }
+
+    /// LDtk projects keep their fields, the levels saved separately are inlined
+    fn ldtk(&self, path: &Path) -> color_eyre::Result<Value> {
+        let base = path.parent().unwrap();
+        let mut project: Value = serde_json::from_str(&fs_err::read_to_string(path)?)?;
+        inline_levels(&mut project["levels"], base)?;
+        // Projects with more than one world keep the levels inside them
+        for world in project["worlds"].as_array_mut().into_iter().flatten() {
+            inline_levels(&mut world["levels"], base)?;
+        }
+        if project.get("externalLevels").is_some() {
+            project["externalLevels"] = json!(false);
+        }
+        Ok(self.ldtk_paths(project, base))
+    }
+
+    fn convert(&mut self, path: &Path) -> color_eyre::Result<Value> {
+        self.map = love_path(self.root, path).unwrap_or_default();
+        match path
+            .extension()
+            .unwrap()
+            .to_string_lossy()
+            .to_lowercase()
+            .as_str()
+        {
+            "tmx" => self.tmx(path),
+            "tmj" => self.tmj(path),
+            _ => self.ldtk(path),
+        }
+        .with_context(|| format!("Converting {}", self.map))
+    }
+
+    fn ldtk_paths(&self, value: Value, base: &Path) -> Value {
+        match value {
+            Value::Array(items) => Value::Array(
+                items
+                    .into_iter()
+                    .map(|x| self.ldtk_paths(x, base))
+                    .collect(),
+            ),
+            Value::Object(object) => Value::Object(
+                object
+                    .into_iter()
+                    .map(|(key, value)| {
+                        let value = match value {
+                            Value::String(path) if key == "relPath" || key.ends_with("RelPath") => {
+                                json!(self.love_path(base, &path))
+                            }
+                            value => self.ldtk_paths(value, base),
+                        };
+                        (key, value)
+                    })
+                    .collect(),
+            ),
+            value => value,
+        }
+    }
+}
+
+impl Builder {
+    fn maps_config(&self) -> Option<&MapsConfig> {
+        self.config.assets.as_ref()?.maps.as_ref()
+    }
+
+    fn is_map(&self, config: &MapsConfig, path: &Path) -> bool {
+        self.in_layout(&config.files, path)
+    }
+
+    /// Whether a file is converted into a module instead of being added by itself,
+    /// the tilesets and levels the maps load included
+    pub fn is_map_source(&self, path: &Path) -> bool {
+        let Some(config) = self.maps_config() else {
+            return false;
+        };
+        let extension = path
+            .extension()
+            .map(|x| x.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        MAP_DEPENDENCIES.contains(&extension.as_str())
+            || MAP_EXTENSIONS.contains(&extension.as_str()) && self.is_map(config, path)
+    }
+
+    /// The module a map is converted into, relative to the build folder
+    pub fn map_module(&self, path: &Path) -> Option<PathBuf> {
+        let config = self.maps_config()?;
+        let extension = path.extension()?.to_string_lossy().to_lowercase();
+        if !MAP_EXTENSIONS.contains(&extension.as_str()) || !self.is_map(config, path) {
+            return None;
+        }
+        Some(
+            path.strip_prefix(&self.paths.root)
+                .ok()?
+                .with_extension("lua"),
+        )
+    }
+
+    fn map_files(&self, config: &MapsConfig) -> color_eyre::Result<BTreeSet<PathBuf>> {
+        Ok(self
+            .layout_files(&config.files)
+            .into_iter()
+            .filter(|x| self.map_module(x).is_some())
+            .collect())
+    }
+
+    fn converter(&self, quiet: bool) -> Converter<'_> {
+        let layout = &self.config.layout;
+        Converter {
+            root: &self.paths.root,
+            assets: asset_paths(
+                self,
+                &[layout.bundle.as_slice(), layout.external.as_slice()].concat(),
+            ),
+            map: String::new(),
+            quiet,
+            used: RefCell::new(BTreeSet::new()),
+        }
+    }
+
+    /// The LÖVE paths of the files the maps use, like the images of their tilesets
+    pub fn map_references(&self) -> BTreeSet<String> {
+        let Some(config) = self.maps_config() else {
+            return BTreeSet::new();
+        };
+        let mut converter = self.converter(true);
+        // Broken maps are reported when they are converted
+        for file in self.map_files(config).unwrap_or_default() {
+            let _ = converter.convert(&file);
+        }
+        converter.used.into_inner()
+    }
+
+    /// Converts the maps of assets.maps into Lua modules inside the build folder
+    pub async fn convert_maps(&self) -> color_eyre::Result<()> {
+        let Some(config) = self.maps_config() else {
+            return Ok(());
+        };
+        let files = self.map_files(config)?;
+
+        let mut p = ProgressBar::new_spinner().with_message("Converting maps...");
+        p = self.progress_bar.add(p);
+        let mut converter = self.converter(false);
+        let mut modules = vec![];
+        for file in &files {
+            let module = self.map_module(file).unwrap();
+            let map = converter.convert(file)?;
+
+            let mut lua = format!(
+                "-- Generated by kaledis from {}, changes are overwritten\nreturn ",
+                converter.map
+            );
+            write_lua(&mut lua, &map, 0);
+            lua.push('\n');
+            let output = self.paths.build.join(&module);
+            fs_err::tokio::create_dir_all(output.parent().unwrap()).await?;
+            if fs_err::tokio::read_to_string(&output).await.ok() != Some(lua.clone()) {
+                fs_err::tokio::write(&output, lua).await?;
+            }
+            modules.push(
+                module
+                    .with_extension("")
+                    .iter()
+                    .map(|x| x.to_string_lossy())
+                    .collect::<Vec<_>>()
+                    .join("."),
+            );
+        }
+
+        let definitions = format!(
+            "{}\n-- What each map is required with\ntype MapModule = {}\n",
+            include_str!("../../../static/maps.d.luau"),
+            if modules.is_empty() {
+                "never".to_string()
+            } else {
+                modules
+                    .iter()
+                    .map(|x| format!("{:?}", x))
+                    .collect::<Vec<_>>()
+                    .join("\n\t| ")
+            }
+        );
+        let path = self.paths.root.join(MAPS_DEFINITIONS);
+        fs_err::tokio::create_dir_all(path.parent().unwrap()).await?;
+        if fs_err::tokio::read_to_string(&path).await.ok() != Some(definitions.clone()) {
+            fs_err::tokio::write(&path, definitions).await?;
+        }
+
+        p.finish_with_message(format!(
+            "{} Converted {} {}",
+            "[+]".green(),
+            files.len(),
+            if files.len() == 1 { "map" } else { "maps" }
+        ));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    const GIDS: [u32; 4] = [1, 2, 0, 3];
+
+    fn encoded(compress: impl Fn(&[u8]) -> Vec<u8>) -> String {
+        let bytes: Vec<u8> = GIDS.iter().flat_map(|x| x.to_le_bytes()).collect();
+        STANDARD.encode(compress(&bytes))
+    }
+
+    /// A converter for a project in a temporary folder with the given files
+    fn project(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let root =
+            std::env::temp_dir().join(format!("kaledis-maps-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        for (path, contents) in files {
+            let path = root.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, contents).unwrap();
+        }
+        root
+    }
+
+    fn converter(root: &Path) -> Converter<'_> {
+        Converter {
+            root,
+            assets: BTreeSet::new(),
+            map: String::new(),
+            quiet: true,
+            used: RefCell::new(BTreeSet::new()),
+        }
+    }
+
+    #[test]
+    fn decodes_tiles_in_every_encoding() {
+        let zlib = encoded(|bytes| {
+            let mut encoder = flate2::write::ZlibEncoder::new(vec![], Default::default());
+            encoder.write_all(bytes).unwrap();
+            encoder.finish().unwrap()
+        });
+        let gzip = encoded(|bytes| {
+            let mut encoder = flate2::write::GzEncoder::new(vec![], Default::default());
+            encoder.write_all(bytes).unwrap();
+            encoder.finish().unwrap()
+        });
+        let zstd = encoded(|bytes| zstd::encode_all(bytes, 0).unwrap());
+        for (text, compression) in [
+            (encoded(<[u8]>::to_vec), None),
+            (zlib, Some("zlib")),
+            (gzip, Some("gzip")),
+            (zstd, Some("zstd")),
+        ] {
+            // Tiled wraps the data in new lines and indentation
+            let text = format!("\n   {}\n  ", text);
+            assert_eq!(decode_tiles(&text, compression).unwrap(), GIDS);
+        }
+        assert!(decode_tiles(&encoded(<[u8]>::to_vec), Some("lz4")).is_err());
+
+        let csv = XmlElement::parse("<data encoding=\"csv\">\n1,2,\n0,3\n</data>").unwrap();
+        assert_eq!(tile_data(&csv, Some("csv"), None).unwrap(), json!(GIDS));
+        let xml = XmlElement::parse(
+            "<data><tile gid=\"1\"/><tile gid=\"2\"/><tile/><tile gid=\"3\"/></data>",
+        )
+        .unwrap();
+        assert_eq!(tile_data(&xml, None, None).unwrap(), json!(GIDS));
+    }
+
+    #[test]
+    fn writes_lua_tables() {
+        let value = json!({
+            "name": "say \"hi\"\n\u{1}",
+            "end": true,
+            "two words": 1.5,
+            "missing": null,
+            "offset": { "x": 0, "y": -2 },
+            "layers": [{ "id": 1 }, []],
+        });
+        let mut out = String::new();
+        write_lua(&mut out, &value, 0);
+        assert_eq!(
+            out,
+            "{\n\t[\"end\"] = true,\n\tlayers = {\n\t\t{ id = 1 },\n\t\t{},\n\t},\n\
+             \tname = \"say \\\"hi\\\"\\n\\001\",\n\toffset = { x = 0, y = -2 },\n\
+             \t[\"two words\"] = 1.5,\n}"
+        );
+    }
+
+    #[test]
+    fn keeps_tile_data_in_rows() {
+        let mut out = String::new();
+        write_lua(&mut out, &json!(vec![7; 40]), 1);
+        let rows: Vec<_> = out.lines().collect();
+        assert_eq!(rows.len(), 4);
+        assert_eq!(rows[1].matches("7").count(), 32);
+        // The rows end with a comma so they join into one list
+        assert_eq!(rows[2], "\t\t7, 7, 7, 7, 7, 7, 7, 7,");
+        assert_eq!(rows[3], "\t}");
+    }
+
+    #[test]
+    fn rewrites_tileset_paths() {
+        let root = project(
+            "tilesets",
+            &[
+                (
+                    "maps/level.tmx",
+                    r#"<map version="1.10" orientation="orthogonal" width="2" height="2">
+                        <tileset firstgid="1" source="../tilesets/ground.tsx"/>
+                        <tileset firstgid="5" name="props" tilewidth="8" tileheight="8">
+                            <image source="props.png" width="16" height="16"/>
+                        </tileset>
+                        <layer id="1" name="ground" width="2" height="2">
+                            <data encoding="csv">1,2,0,5</data>
+                        </layer>
+                    </map>"#,
+                ),
+                (
+                    "tilesets/ground.tsx",
+                    r#"<tileset name="ground" tilewidth="8" tileheight="8" tilecount="4" columns="2">
+                        <image source="../images/ground.png" width="16" height="16"/>
+                    </tileset>"#,
+                ),
+            ],
+        );
+        let converter = converter(&root);
+        let map = converter.tmx(&root.join("maps/level.tmx")).unwrap();
+        assert_eq!(map["tilesets"][0]["name"], "ground");
+        assert_eq!(map["tilesets"][0]["firstgid"], 1);
+        assert_eq!(map["tilesets"][0]["image"], "images/ground.png");
+        assert_eq!(map["tilesets"][1]["firstgid"], 5);
+        assert_eq!(map["tilesets"][1]["image"], "maps/props.png");
+        assert_eq!(map["layers"][0]["data"], json!([1, 2, 0, 5]));
+        assert_eq!(
+            converter.used.into_inner(),
+            BTreeSet::from([
+                "images/ground.png".to_string(),
+                "maps/props.png".to_string()
+            ])
+        );
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn inlines_ldtk_external_levels() {
+        let root = project(
+            "ldtk",
+            &[
+                (
+                    "maps/world.ldtk",
+                    r#"{
+                        "externalLevels": true,
+                        "defs": { "tilesets": [{ "relPath": "../art/tiles.png" }] },
+                        "levels": [{ "identifier": "Start", "externalRelPath": "world/Start.ldtkl" }]
+                    }"#,
+                ),
+                (
+                    "maps/world/Start.ldtkl",
+                    r#"{
+                        "identifier": "Start",
+                        "layerInstances": [{ "__tilesetRelPath": "../art/tiles.png" }]
+                    }"#,
+                ),
+            ],
+        );
+        let converter = converter(&root);
+        let project = converter.ldtk(&root.join("maps/world.ldtk")).unwrap();
+        assert_eq!(project["externalLevels"], false);
+        assert_eq!(project["defs"]["tilesets"][0]["relPath"], "art/tiles.png");
+        let level = &project["levels"][0];
+        assert_eq!(level["identifier"], "Start");
+        assert!(level.get("externalRelPath").is_none());
+        assert_eq!(
+            level["layerInstances"][0]["__tilesetRelPath"],
+            "art/tiles.png"
+        );
+        std::fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -1,18 +1,32 @@
 pub mod android;
+pub mod android_icons;
+pub mod aseprite;
+pub mod assets;
+pub mod atlas;
 pub mod build_utils;
+pub mod fonts;
+pub mod layout;
 pub mod linux;
 pub mod macos;
+pub mod maps;
+pub mod optimize;
+pub mod runtime;
+pub mod shaders;
+pub mod web;
 pub mod windows;
 
 use std::{
     io::{BufRead, BufReader, Cursor, Read, Write},
-    path::PathBuf,
+    path::{Path, PathBuf},
     process::exit,
     str::FromStr,
 };
 
 use backhand::{FilesystemReader, FilesystemWriter, InnerNode, kind::Kind};
-use color_eyre::Section;
+use color_eyre::{
+    Section,
+    eyre::{Context, ContextCompat, eyre},
+};
 use colored::Colorize;
 use fs_err::tokio::{
     File, canonicalize, copy, create_dir, create_dir_all, hard_link, remove_dir_all, remove_file,
@@ -24,19 +38,30 @@ use tokio::io::{self, AsyncReadExt, AsyncWriteExt};
 use walkdir::WalkDir;
 
 use crate::{
-    commands::build::{
-        android::build_android, linux::build_linux, macos::build_macos, windows::build_windows,
+    commands::{
+        build::{
+            android::{build_android, build_android_bundle},
+            linux::build_linux,
+            macos::build_macos,
+            web::{build_web, warn_unsupported},
+            windows::build_windows,
+        },
+        toolchain::update_lock,
     },
     dalbit::{
         manifest::Manifest,
         transpile::{clean_polyfill, process_files},
     },
     home_manager::{HomeManager, Target},
-    toml_conf::{KaledisConfig, LoveConfig, Modules},
+    toml_conf::{AssetsConfig, KaledisConfig, LoveConfig, Modules, WebConfig},
     utils::relative,
-    zip_utils::Zipper,
 };
+use assets::{add_alias, love_path};
 use build_utils::{Paths, get_transpiler, read_aliases};
+use layout::{BundleFiles, dev_target};
+use optimize::report;
+use runtime::{ensure_dev_runtime, ensure_runtime, runtime_path};
+use shaders::platform_define;
 
 #[derive(PartialEq, Eq, Clone)]
 pub enum Strategy {
@@ -66,6 +91,11 @@ pub struct Builder {
 }
 
 impl Builder {
+    /// Folder with the LÖVE runtime of a target
+    pub async fn runtime_path(&self, target: Target) -> color_eyre::Result<PathBuf> {
+        runtime_path(&self.home, &self.paths.root, &self.config.love, target).await
+    }
+
     pub async fn get_love_config(root: &PathBuf) -> LoveCfg {
         if root.join("conf.luau").exists() {
             return LoveCfg::File(root.join("conf.luau"));
@@ -95,33 +125,37 @@ impl Builder {
     ) -> Self {
         clean_polyfill();
         let manager = HomeManager::new().await.unwrap();
+        // Prune keeps the versions of the projects it knows
+        if let Err(err) = manager.register_project(&root).await {
+            tracing::warn!("{:#}", err);
+        }
         match &strategy {
             Strategy::Build(targets) => {
                 for target in targets {
-                    if *target == Target::LoveFile {
-                        continue;
-                    }
-                    manager.ensure_version(&config.love, target.clone()).await;
+                    ensure_runtime(&manager, &config.love, target.clone())
+                        .await
+                        .expect("Failed to download LÖVE");
                 }
             }
             Strategy::BuildDev => {
-                #[cfg(windows)]
-                manager.ensure_version(&config.love, Target::Windows).await;
-                #[cfg(target_os = "linux")]
-                manager
-                    .ensure_version(&config.love, Target::LinuxAppImage)
-                    .await;
-                #[cfg(target_os = "macos")]
-                manager.ensure_version(&config.love, Target::Macos).await;
+                ensure_dev_runtime(&manager, &config.love)
+                    .await
+                    .expect("Failed to download LÖVE");
             }
         }
 
+        let mut aliases = read_aliases(&root).await.expect("Failed to read aliases");
+        add_alias(
+            &mut aliases,
+            config.assets.as_ref().unwrap_or(&AssetsConfig::default()),
+        );
+
         Self {
             manifest: get_transpiler(bundle, config.polyfill.as_ref())
                 .await
                 .expect("Failed to build manifest"),
             love_config: love_config,
-            aliases: read_aliases(&root).await.expect("Failed to read aliases"),
+            aliases,
             paths: Paths::from_root(root, &config),
             config: config,
             home: manager,
@@ -144,51 +178,78 @@ impl Builder {
         create_dir(&self.paths.build).await?;
         Ok(())
     }
-    pub async fn add_assets(&self, zipper: Option<&mut Zipper>, finishing_love: bool) {
-        let mut to_link = self.config.layout.external.clone();
+    /// Whether an asset is packed or converted by the build instead of being added by itself
+    pub fn is_build_source(&self, path: &Path) -> bool {
+        self.is_atlas_source(path)
+            || self.is_map_source(path)
+            || self.is_sheet_source(path)
+            || self.is_font_source(path)
+    }
+
+    /// Generates the assets and, for dev or next to a .love, links the ones LÖVE reads from
+    /// the build folder. The release builds pack the bundled ones with love_file
+    pub async fn add_assets(&self, finishing_love: bool) -> color_eyre::Result<()> {
+        if !finishing_love {
+            self.pack_atlases()
+                .await
+                .wrap_err("Failed to pack the atlases")?;
+            self.generate_fonts()
+                .await
+                .wrap_err("Failed to rasterize the fonts")?;
+            self.convert_maps()
+                .await
+                .wrap_err("Failed to convert the maps")?;
+            self.import_sheets()
+                .await
+                .wrap_err("Failed to import the sprite sheets")?;
+        }
 
         let mut p = ProgressBar::new_spinner().with_message("Adding assets...");
         p = self.progress_bar.add(p);
 
         if self.strategy == Strategy::BuildDev || finishing_love {
+            let named = |path: PathBuf| {
+                let name = love_path(&self.paths.root, &path)
+                    .ok_or_else(|| eyre!("{} is outside of the project", path.display()))
+                    .suggestion("Don't use assets outside the root of your project")?;
+                Ok((path, name))
+            };
+            let mut to_link: Vec<(PathBuf, String)> = self
+                .layout_files(&self.config.layout.external)
+                .into_iter()
+                .filter(|x| !self.is_build_source(x) && !self.is_shader_source(x))
+                .map(named)
+                .collect::<color_eyre::Result<_>>()?;
             if !finishing_love {
-                to_link.extend_from_slice(&self.config.layout.bundle);
+                // Dev gets the assets of the desktop it runs on, write_shaders adds the shaders
+                to_link.extend(
+                    self.bundle_files(&dev_target())?
+                        .into_iter()
+                        .filter(|(path, _)| !self.is_shader_source(path)),
+                );
             }
-            for glb in &to_link {
-                for path in glob::glob(&self.paths.root.join(glb).to_string_lossy())
-                    .unwrap()
-                    .filter_map(Result::ok)
-                {
-                    let pth_b = &self.paths.build.join(
-                        &path
-                            .strip_prefix(&self.paths.root)
-                            .suggestion("Don't use assets outside the root of your project")
-                            .expect("Failed to remove root"),
-                    );
-                    create_dir_all(&pth_b.parent().expect("Invalid path"))
-                        .await
-                        .expect("Failed to create file structure");
-                    if pth_b.exists() {
-                        remove_file(&pth_b)
-                            .await
-                            .expect("Failed to clean previous asset");
-                    }
-                    hard_link(&path, &pth_b)
+            for (path, name) in to_link {
+                let pth_b = &self.paths.build.join(name);
+                create_dir_all(&pth_b.parent().wrap_err("Invalid path")?)
+                    .await
+                    .wrap_err("Failed to create file structure")?;
+                if pth_b.exists() {
+                    remove_file(&pth_b)
                         .await
-                        .expect("Failed to link the file");
+                        .wrap_err("Failed to clean previous asset")?;
                 }
+                hard_link(&path, &pth_b)
+                    .await
+                    .wrap_err("Failed to link the file")?;
             }
-        } else if let Some(zipper) = zipper {
-            for glb in &self.config.layout.bundle {
-                for path in glob::glob(&self.paths.root.join(glb).to_string_lossy())
-                    .unwrap()
-                    .filter_map(Result::ok)
-                {
-                    zipper.add_rootless(&path, &self.paths.root).unwrap();
-                }
+            if !finishing_love {
+                self.write_shaders()
+                    .await
+                    .wrap_err("Failed to write the shaders")?;
             }
         }
         p.finish_with_message(format!("{} Assets Added", "[+]".green()));
+        Ok(())
     }
 
     pub async fn handle_conf_file(&self, used_modules: Vec<Modules>) {
@@ -289,6 +350,10 @@ impl Builder {
     pub async fn transpile(&self) -> Vec<Modules> {
         let mut result = vec![];
 
+        self.generate_assets()
+            .await
+            .expect("Failed to generate the asset module");
+
         for (_, path) in &self.aliases {
             let bas;
             if path.starts_with(".") {
@@ -331,6 +396,12 @@ pub async fn build(path: Option<PathBuf>, run: Strategy, bundle: bool) -> color_
     }
 
     let builder = Builder::new(root.clone(), run.clone(), bundle).await;
+    builder
+        .check_assets(match &run {
+            Strategy::Build(targets) => targets,
+            Strategy::BuildDev => &[],
+        })
+        .await?;
     builder
         .clean_build_folder()
         .await
@@ -339,16 +410,58 @@ pub async fn build(path: Option<PathBuf>, run: Strategy, bundle: bool) -> color_
 
     match run {
         Strategy::BuildDev => {
-            builder.add_assets(None, false).await;
+            builder.add_assets(false).await?;
             builder.transpile().await;
         }
         Strategy::Build(platforms) => {
-            let mut zip = Zipper::new();
-            builder.add_assets(Some(&mut zip), false).await;
-            zip.put_folder_recursively(&builder.paths.build)
-                .expect("Failed to create zip");
-            let data = zip.finish();
+            builder.add_assets(false).await?;
+            // The targets that get the same assets share their .love, unless the shaders
+            // tell their platforms apart
+            let mut groups: Vec<(Vec<Target>, BundleFiles)> = vec![];
+            for platform in &platforms {
+                let files = builder.bundle_files(platform)?;
+                let shaders = files.iter().any(|(path, _)| builder.is_shader_source(path));
+                match groups.iter_mut().find(|(targets, other)| {
+                    *other == files
+                        && targets[0].is_android() == platform.is_android()
+                        && (!shaders || platform_define(&targets[0]) == platform_define(platform))
+                }) {
+                    Some((targets, _)) => targets.push(platform.clone()),
+                    None => groups.push((vec![platform.clone()], files)),
+                }
+            }
+            let mut loves: Vec<(Vec<Target>, Vec<u8>)> = vec![];
+            for (targets, _) in groups {
+                let mut optimized = vec![];
+                let data = builder.love_file(&targets[0], &mut optimized).await?;
+                if loves.is_empty() && targets.len() == platforms.len() {
+                    report("Optimized assets", optimized);
+                } else {
+                    let names: Vec<String> = targets.iter().map(Target::name).collect();
+                    report(
+                        &format!("Optimized assets of {}", names.join(", ")),
+                        optimized,
+                    );
+                }
+                loves.push((targets, data));
+            }
+            let love = |platform: &Target| {
+                loves
+                    .iter()
+                    .find(|(targets, _)| targets.contains(platform))
+                    .map(|(_, data)| data.as_slice())
+                    .unwrap_or_default()
+            };
+
+            if platforms.contains(&Target::Web) {
+                let default = WebConfig::default();
+                warn_unsupported(
+                    &builder.paths.build,
+                    builder.config.web.as_ref().unwrap_or(&default),
+                );
+            }
 
+            let mut artifacts = vec![];
             for platform in platforms {
                 // We skip when we use love file because it basically is done at this state
                 if let Target::LoveFile = platform {
@@ -359,20 +472,25 @@ pub async fn build(path: Option<PathBuf>, run: Strategy, bundle: bool) -> color_
                         .await
                         .expect("Failed to create build folder");
                     let mut file = File::create(builder.paths.build.join("final.love")).await?;
-                    file.write_all(&data).await?;
+                    file.write_all(love(&platform)).await?;
 
-                    builder.add_assets(None, true).await;
+                    builder.add_assets(true).await?;
 
                     continue;
                 }
 
-                let home = &builder.home;
-                home.ensure_version(&builder.config.love, platform.clone())
-                    .await;
+                if let Some(artifact) =
+                    ensure_runtime(&builder.home, &builder.config.love, platform.clone()).await?
+                {
+                    artifacts.push(artifact);
+                }
 
-                let platform_path = home.get_path(&builder.config.love, platform.clone()).await;
+                let platform_path = builder.runtime_path(platform.clone()).await?;
 
-                if platform != Target::Android {
+                if !matches!(
+                    platform,
+                    Target::Android | Target::AndroidBundle | Target::Web
+                ) {
                     let dists = builder.paths.dist.join(platform.as_ref().to_string());
                     if dists.exists() {
                         remove_dir_all(&dists)
@@ -391,23 +509,31 @@ pub async fn build(path: Option<PathBuf>, run: Strategy, bundle: bool) -> color_
                 match platform {
                     Target::LoveFile => {}
                     Target::Android => {
-                        build_android(&builder, &data)
+                        build_android(&builder, love(&platform))
                             .await
                             .expect("Failed to start android server");
                     }
-                    Target::LinuxAppImage => {
-                        build_linux(&builder, &data).await?;
+                    Target::AndroidBundle => {
+                        build_android_bundle(&builder, love(&platform)).await?;
+                    }
+                    Target::LinuxAppImage | Target::LinuxAarch64 => {
+                        build_linux(&builder, love(&platform), platform.clone()).await?;
                     }
                     Target::Macos => {
-                        build_macos(&builder, &data).await?;
+                        build_macos(&builder, love(&platform)).await?;
+                    }
+                    Target::Web => {
+                        build_web(&builder, love(&platform)).await?;
                     }
-                    Target::Windows => {
-                        build_windows(&builder, &data)
+                    Target::Windows | Target::WindowsX86 => {
+                        build_windows(&builder, love(&platform), platform.clone())
                             .await
                             .expect("Failed to build to windows");
                     }
                 }
             }
+
+            update_lock(&builder.paths.root, artifacts).await?;
         }
     }
 
@@ -432,6 +558,11 @@ pub async fn recursive_copy(input: &PathBuf, output: &PathBuf) -> color_eyre::Re
         else if entry.file_type().is_file() {
             copy(from, to).await?;
         }
+        // keep symlinks, the macos frameworks depend on them
+        else if entry.file_type().is_symlink() {
+            #[cfg(unix)]
+            fs_err::tokio::symlink(fs_err::tokio::read_link(from).await?, to).await?;
+        }
     }
     Ok(())
 }
@@ -0,0 +1,168 @@
+use std::path::{Path, PathBuf};
+
+use color_eyre::eyre::Context;
+use colored::Colorize;
+use image::{
+    DynamicImage, ImageEncoder,
+    codecs::png::{CompressionType, FilterType, PngEncoder},
+    imageops,
+};
+use indicatif::HumanBytes;
+
+use crate::{
+    commands::build::Builder, home_manager::Target, toml_conf::OptimizeConfig, zip_utils::Zipper,
+};
+
+/// Where the optimized PNGs are cached, relative to the root of the project
+pub const OPTIMIZED_FOLDER: &str = ".kaledis/optimized";
+
+/// The size of an asset before and after it was optimized
+pub struct Optimized {
+    pub path: String,
+    pub before: u64,
+    pub after: u64,
+}
+
+impl Optimized {
+    fn saved(&self) -> u64 {
+        self.before.saturating_sub(self.after)
+    }
+}
+
+/// Encodes at the highest compression, only the pixels are written so the metadata is dropped
+fn encode_png(image: &DynamicImage) -> color_eyre::Result<Vec<u8>> {
+    let mut bytes = vec![];
+    PngEncoder::new_with_quality(&mut bytes, CompressionType::Best, FilterType::Adaptive)
+        .write_image(
+            image.as_bytes(),
+            image.width(),
+            image.height(),
+            image.color().into(),
+        )?;
+    Ok(bytes)
+}
+
+/// Prints how much each asset shrunk, the biggest savings first
+pub fn report(title: &str, mut optimized: Vec<Optimized>) {
+    if optimized.is_empty() {
+        return;
+    }
+    optimized.sort_by(|a, b| b.saved().cmp(&a.saved()).then(a.path.cmp(&b.path)));
+    println!("{}", title.bold());
+    for item in &optimized {
+        println!(
+            "{:>10} -> {:>10}  {:>10}  {}",
+            HumanBytes(item.before).to_string(),
+            HumanBytes(item.after).to_string(),
+            format!("-{}", HumanBytes(item.saved())).green(),
+            item.path
+        );
+    }
+    let before: u64 = optimized.iter().map(|x| x.before).sum();
+    let saved: u64 = optimized.iter().map(Optimized::saved).sum();
+    println!(
+        "Saved {} of {} ({:.1}%)",
+        HumanBytes(saved),
+        HumanBytes(before),
+        saved as f64 * 100.0 / before.max(1) as f64
+    );
+}
+
+impl Builder {
+    pub fn optimize_config(&self) -> Option<&OptimizeConfig> {
+        self.config.assets.as_ref()?.optimize.as_ref()
+    }
+
+    /// The PNG as it goes into a release build, cached by the hash of the original
+    async fn optimize_png(&self, path: &Path, scale: Option<f32>) -> color_eyre::Result<Vec<u8>> {
+        let original = fs_err::tokio::read(path).await?;
+        let hash = blake3::hash(&original).to_hex();
+        let cached = self.paths.root.join(OPTIMIZED_FOLDER).join(match scale {
+            Some(scale) => format!("{}_{}.png", hash, scale),
+            None => format!("{}.png", hash),
+        });
+        if let Ok(bytes) = fs_err::tokio::read(&cached).await {
+            return Ok(bytes);
+        }
+
+        let mut image = image::load_from_memory(&original)
+            .with_context(|| format!("Decoding {}", path.display()))?;
+        if let Some(scale) = scale {
+            image = image.resize_exact(
+                ((image.width() as f32 * scale).round() as u32).max(1),
+                ((image.height() as f32 * scale).round() as u32).max(1),
+                imageops::FilterType::Lanczos3,
+            );
+        }
+        let mut bytes = encode_png(&image)?;
+        // Palette PNGs are decoded into full colors, those can come out bigger
+        if scale.is_none() && bytes.len() >= original.len() {
+            bytes = original;
+        }
+
+        fs_err::tokio::create_dir_all(cached.parent().unwrap()).await?;
+        fs_err::tokio::write(&cached, &bytes).await?;
+        Ok(bytes)
+    }
+
+    /// The .love of a target with the assets that assets.rules gives it, the android
+    /// builds get the PNGs scaled by assets.optimize.mobile_scale
+    pub async fn love_file(
+        &self,
+        target: &Target,
+        optimized: &mut Vec<Optimized>,
+    ) -> color_eyre::Result<Vec<u8>> {
+        let mut zipper = Zipper::new();
+        let files = self
+            .bundle_files(target)?
+            .into_iter()
+            .filter(|(path, _)| !self.is_shader_source(path));
+        for (path, name) in files {
+            self.add_optimized(&mut zipper, &path, &name, target.is_android(), optimized)
+                .await?;
+        }
+        self.add_shaders(&mut zipper, target)?;
+        zipper.put_folder_recursively(&self.paths.build)?;
+        Ok(zipper.finish())
+    }
+
+    /// Adds an asset to the .love under a name, optimizing it when it is a PNG
+    pub async fn add_optimized(
+        &self,
+        zipper: &mut Zipper,
+        path: &Path,
+        name: &str,
+        mobile: bool,
+        optimized: &mut Vec<Optimized>,
+    ) -> color_eyre::Result<()> {
+        let config = self.optimize_config();
+        let scale = config.and_then(|x| x.mobile_scale).filter(|_| mobile);
+        let is_png = path
+            .extension()
+            .is_some_and(|x| x.eq_ignore_ascii_case("png"));
+        if !is_png || !config.is_some_and(|x| x.png || scale.is_some()) {
+            zipper.copy_from_path(path, PathBuf::from(name))?;
+            return Ok(());
+        }
+
+        let bytes = match self.optimize_png(path, scale).await {
+            Ok(bytes) => bytes,
+            Err(err) => {
+                tracing::warn!(
+                    "{} is added as it is, it couldn't be optimized: {:#}",
+                    path.display(),
+                    err
+                );
+                zipper.copy_from_path(path, PathBuf::from(name))?;
+                return Ok(());
+            }
+        };
+        zipper.add_buffer(name, &bytes)?;
+        optimized.push(Optimized {
+            before: fs_err::metadata(path)?.len(),
+            after: bytes.len() as u64,
+            path: name.to_string(),
+        });
+        Ok(())
+    }
+}
@@ -0,0 +1,127 @@
+use std::path::{Path, PathBuf};
+
+use color_eyre::{
+    Section,
+    eyre::{Context, ContextCompat, eyre},
+};
+
+use crate::{
+    home_manager::{Artifact, CURRENT_PLATFORM, HomeManager, Target},
+    toml_conf::{CustomLoveConfig, LoveRuntime},
+};
+
+// Where the executable is inside a runtime folder, the first ones are how kaledis downloads them
+const EXECUTABLES: [&str; 5] = [
+    "love.exe",
+    "love2d.AppImage",
+    "love.app/Contents/MacOS/love",
+    "love",
+    "bin/love",
+];
+
+/// Finds love on PATH
+pub fn find_system_love() -> Option<PathBuf> {
+    let name = if cfg!(windows) { "love.exe" } else { "love" };
+    std::env::var_os("PATH")
+        .iter()
+        .flat_map(std::env::split_paths)
+        .map(|folder| folder.join(name))
+        .find(|path| path.is_file())
+}
+
+fn system_love() -> color_eyre::Result<PathBuf> {
+    find_system_love()
+        .wrap_err("love was not found on PATH")
+        .suggestion("Install LÖVE or set love to a version like \"11.5\" in kaledis.toml")
+}
+
+/// Reads the version of a love executable, it prints "LOVE 11.5 (Mysterious Mysteries)"
+fn executable_version(love: &Path) -> color_eyre::Result<String> {
+    let output = std::process::Command::new(love)
+        .arg("--version")
+        .output()
+        .with_context(|| format!("Running {} --version", love.display()))?;
+    let text = String::from_utf8_lossy(&output.stdout);
+    text.split_whitespace()
+        .nth(1)
+        .map(|x| x.to_string())
+        .wrap_err_with(|| format!("Couldn't read the version of {}", love.display()))
+        .suggestion("Use love = { path = \"...\", version = \"11.5\" } instead")
+}
+
+/// Version of the releases downloaded for targets without a custom runtime
+pub fn download_version(love: &LoveRuntime) -> color_eyre::Result<String> {
+    match love.pinned_version() {
+        Some(version) => Ok(version.to_string()),
+        None => executable_version(&system_love()?),
+    }
+}
+
+/// Makes sure the runtime of a target exists, returning the artifact if it was downloaded
+pub async fn ensure_runtime(
+    home: &HomeManager,
+    love: &LoveRuntime,
+    target: Target,
+) -> color_eyre::Result<Option<Artifact>> {
+    if target == Target::LoveFile || love.custom_folder(&target).is_some() {
+        return Ok(None);
+    }
+    let version = download_version(love)?;
+    home.ensure_version(&version, target.clone()).await?;
+    Ok(home.get_artifact(&version, &target.download_target()).await)
+}
+
+/// Folder with the runtime of a target
+pub async fn runtime_path(
+    home: &HomeManager,
+    root: &Path,
+    love: &LoveRuntime,
+    target: Target,
+) -> color_eyre::Result<PathBuf> {
+    if let Some(folder) = love.custom_folder(&target) {
+        let path = root.join(folder);
+        if !path.is_dir() {
+            return Err(eyre!(
+                "The custom LÖVE folder {} for {} doesn't exist",
+                path.display(),
+                target.as_ref()
+            ));
+        }
+        return Ok(path);
+    }
+    Ok(home.get_path(&download_version(love)?, target).await)
+}
+
+/// Makes sure the love Dev runs exists
+pub async fn ensure_dev_runtime(home: &HomeManager, love: &LoveRuntime) -> color_eyre::Result<()> {
+    match love {
+        LoveRuntime::Version(_) if love.is_system() => system_love().map(|_| ()),
+        LoveRuntime::Custom(CustomLoveConfig { path: Some(_), .. }) => Ok(()),
+        _ => ensure_runtime(home, love, CURRENT_PLATFORM.clone())
+            .await
+            .map(|_| ()),
+    }
+}
+
+/// The love executable Dev runs
+pub async fn dev_executable(
+    home: &HomeManager,
+    root: &Path,
+    love: &LoveRuntime,
+) -> color_eyre::Result<PathBuf> {
+    let path = match love {
+        LoveRuntime::Version(_) if love.is_system() => return system_love(),
+        LoveRuntime::Custom(CustomLoveConfig {
+            path: Some(path), ..
+        }) => root.join(path),
+        _ => runtime_path(home, root, love, CURRENT_PLATFORM.clone()).await?,
+    };
+    if path.is_file() {
+        return Ok(path);
+    }
+    EXECUTABLES
+        .iter()
+        .map(|name| path.join(name))
+        .find(|x| x.is_file())
+        .wrap_err_with(|| format!("No love executable found in {}", path.display()))
+}
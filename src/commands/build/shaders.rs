@@ -0,0 +1,858 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+};
+
+use color_eyre::{
+    Section,
+    eyre::{ContextCompat, eyre},
+};
+use colored::Colorize;
+use glsl::{
+    parser::Parse,
+    syntax::{ExternalDeclaration, TranslationUnit},
+};
+
+use crate::{
+    commands::build::{Builder, Strategy, assets::love_path, layout::dev_target, maps::resolve},
+    home_manager::Target,
+    zip_utils::Zipper,
+};
+
+/// The shaders of the bundle that are preprocessed instead of copied
+pub const SHADER_EXTENSIONS: [&str; 3] = ["glsl", "frag", "vert"];
+
+// Defined to 1 for the targets of their platform and to 0 for the others
+const PLATFORM_DEFINES: [&str; 5] = [
+    "__WINDOWS__",
+    "__LINUX__",
+    "__MACOS__",
+    "__ANDROID__",
+    "__WEB__",
+];
+
+/// The define of the platform a target runs on, the .love runs anywhere so it has none
+pub fn platform_define(target: &Target) -> Option<&'static str> {
+    match target {
+        Target::Windows | Target::WindowsX86 => Some("__WINDOWS__"),
+        Target::LinuxAppImage | Target::LinuxAarch64 => Some("__LINUX__"),
+        Target::Macos => Some("__MACOS__"),
+        Target::Android | Target::AndroidBundle => Some("__ANDROID__"),
+        Target::Web => Some("__WEB__"),
+        Target::LoveFile => None,
+    }
+}
+
+// What LÖVE defines before compiling, so the parser sees plain GLSL
+const LOVE_ALIASES: [(&str, &str); 9] = [
+    ("number", "float"),
+    ("Image", "sampler2D"),
+    ("ArrayImage", "sampler2DArray"),
+    ("CubeImage", "samplerCube"),
+    ("VolumeImage", "sampler3D"),
+    ("DepthImage", "sampler2DShadow"),
+    ("extern", "uniform"),
+    ("Texel", "texture2D"),
+    ("love_PixelColor", "gl_FragColor"),
+];
+
+/// A line of the preprocessed shader and where it came from, the injected defines have no origin
+#[derive(Debug)]
+struct Line {
+    text: String,
+    origin: Option<(String, usize)>,
+}
+
+impl Line {
+    fn location(&self) -> String {
+        match &self.origin {
+            Some((file, line)) => format!("{}:{}", file, line),
+            None => "<defines>".to_string(),
+        }
+    }
+}
+
+fn directive(text: &str) -> Option<(&str, &str)> {
+    let text = text.trim_start().strip_prefix('#')?.trim_start();
+    let end = text
+        .find(|x: char| !x.is_ascii_alphanumeric() && x != '_')
+        .unwrap_or(text.len());
+    Some((&text[..end], text[end..].trim()))
+}
+
+fn is_identifier_char(character: char) -> bool {
+    character.is_ascii_alphanumeric() || character == '_'
+}
+
+/// Replaces the object-like macros in a line, nested ones included
+fn expand(text: &str, macros: &HashMap<String, String>) -> String {
+    let mut text = text.to_string();
+    for _ in 0..8 {
+        let mut result = String::new();
+        let mut changed = false;
+        let mut chars = text.char_indices().peekable();
+        while let Some((start, character)) = chars.next() {
+            if !(character.is_ascii_alphabetic() || character == '_') {
+                result.push(character);
+                continue;
+            }
+            let mut end = start + character.len_utf8();
+            while let Some((index, next)) = chars.peek()
+                && is_identifier_char(*next)
+            {
+                end = index + next.len_utf8();
+                chars.next();
+            }
+            match macros.get(&text[start..end]) {
+                Some(value) => {
+                    result.push_str(value);
+                    changed = true;
+                }
+                None => result.push_str(&text[start..end]),
+            }
+        }
+        text = result;
+        if !changed {
+            break;
+        }
+    }
+    text
+}
+
+/// Evaluates the expression of an #if or #elif, undefined names are 0 like in C
+fn evaluate(expression: &str, macros: &HashMap<String, String>) -> Option<i64> {
+    let mut tokens = vec![];
+    let mut chars = expression.chars().peekable();
+    while let Some(character) = chars.next() {
+        if character.is_whitespace() {
+            continue;
+        }
+        let mut token = character.to_string();
+        if is_identifier_char(character) {
+            while let Some(next) = chars.peek()
+                && is_identifier_char(*next)
+            {
+                token.push(chars.next().unwrap());
+            }
+        } else if let Some(next) = chars.peek()
+            && ["&&", "||", "==", "!=", "<=", ">="]
+                .contains(&format!("{}{}", character, next).as_str())
+        {
+            token.push(chars.next().unwrap());
+        }
+        tokens.push(token);
+    }
+
+    // defined() is resolved before the macros are expanded
+    let mut resolved = vec![];
+    let mut index = 0;
+    while index < tokens.len() {
+        if tokens[index] == "defined" {
+            let (name, skip) = if tokens.get(index + 1).map(String::as_str) == Some("(") {
+                (tokens.get(index + 2)?, 4)
+            } else {
+                (tokens.get(index + 1)?, 2)
+            };
+            resolved.push((macros.contains_key(name) as i64).to_string());
+            index += skip;
+            continue;
+        }
+        let token = &tokens[index];
+        if token.starts_with(|x: char| x.is_ascii_alphabetic() || x == '_') {
+            let value = expand(token, macros);
+            resolved.push(if value == *token {
+                "0".to_string()
+            } else {
+                value
+            });
+        } else {
+            resolved.push(token.clone());
+        }
+        index += 1;
+    }
+    let mut parser = Expression {
+        tokens: resolved
+            .join(" ")
+            .split_whitespace()
+            .map(str::to_string)
+            .collect(),
+        position: 0,
+    };
+    let value = parser.binary(0)?;
+    (parser.position == parser.tokens.len()).then_some(value)
+}
+
+struct Expression {
+    tokens: Vec<String>,
+    position: usize,
+}
+
+impl Expression {
+    fn next(&mut self) -> Option<String> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn unary(&mut self) -> Option<i64> {
+        match self.next()?.as_str() {
+            "!" => Some((self.unary()? == 0) as i64),
+            "-" => Some(-self.unary()?),
+            "+" => self.unary(),
+            "(" => {
+                let value = self.binary(0)?;
+                (self.next()? == ")").then_some(value)
+            }
+            number => number.trim_end_matches(['u', 'U']).parse().ok(),
+        }
+    }
+
+    fn binary(&mut self, precedence: u8) -> Option<i64> {
+        let mut left = self.unary()?;
+        loop {
+            let Some(operator) = self.tokens.get(self.position).cloned() else {
+                return Some(left);
+            };
+            let current = match operator.as_str() {
+                "||" => 1,
+                "&&" => 2,
+                "==" | "!=" => 3,
+                "<" | ">" | "<=" | ">=" => 4,
+                "+" | "-" => 5,
+                "*" | "/" | "%" => 6,
+                _ => return Some(left),
+            };
+            if current <= precedence {
+                return Some(left);
+            }
+            self.position += 1;
+            let right = self.binary(current)?;
+            left = match operator.as_str() {
+                "||" => (left != 0 || right != 0) as i64,
+                "&&" => (left != 0 && right != 0) as i64,
+                "==" => (left == right) as i64,
+                "!=" => (left != right) as i64,
+                "<" => (left < right) as i64,
+                ">" => (left > right) as i64,
+                "<=" => (left <= right) as i64,
+                ">=" => (left >= right) as i64,
+                "+" => left + right,
+                "-" => left - right,
+                "*" => left * right,
+                "/" => left.checked_div(right)?,
+                _ => left.checked_rem(right)?,
+            };
+        }
+    }
+}
+
+/// The shader as one stage sees it, with the inactive branches, directives and LÖVE aliases
+/// taken out. It keeps a line for every line of the shader so the errors point to the same place
+fn stage_source(lines: &[Line], stage: &str) -> color_eyre::Result<String> {
+    let mut macros: HashMap<String, String> = LOVE_ALIASES
+        .iter()
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect();
+    macros.insert(stage.to_string(), "1".to_string());
+    // Function-like macros are only known to be defined, the calls parse as function calls
+    let mut functions = HashSet::new();
+    // Whether each nested block is active and whether one of its branches was taken
+    let mut blocks: Vec<(bool, bool)> = vec![];
+
+    let mut source = String::new();
+    for line in lines {
+        let active = blocks.iter().all(|(active, _)| *active);
+        let Some((name, rest)) = directive(&line.text) else {
+            if active {
+                source.push_str(&expand(&line.text, &macros));
+            }
+            source.push('\n');
+            continue;
+        };
+        source.push('\n');
+
+        let mut known = macros.clone();
+        known.extend(
+            functions
+                .iter()
+                .map(|x: &String| (x.clone(), "1".to_string())),
+        );
+        let condition = |expression: &str| {
+            evaluate(expression, &known).map(|x| x != 0).ok_or_else(|| {
+                eyre!(
+                    "{}: can't evaluate #{} {}",
+                    line.location(),
+                    name,
+                    expression
+                )
+            })
+        };
+        match name {
+            "ifdef" | "ifndef" => {
+                let defined = known.contains_key(rest.split_whitespace().next().unwrap_or(""));
+                let taken = defined == (name == "ifdef");
+                blocks.push((active && taken, taken));
+            }
+            "if" => {
+                let taken = !active || condition(rest)?;
+                blocks.push((active && taken, taken));
+            }
+            "elif" | "else" => {
+                let parent = blocks.len() > 1 && !blocks[..blocks.len() - 1].iter().all(|x| x.0);
+                let (_, done) = *blocks
+                    .last()
+                    .with_context(|| format!("{}: #{} without #if", line.location(), name))?;
+                let taken = !done && (name == "else" || parent || condition(rest)?);
+                *blocks.last_mut().unwrap() = (!parent && taken, done || taken);
+            }
+            "endif" => {
+                blocks
+                    .pop()
+                    .with_context(|| format!("{}: #endif without #if", line.location()))?;
+            }
+            "define" if active => {
+                let end = rest
+                    .find(|x: char| !is_identifier_char(x))
+                    .unwrap_or(rest.len());
+                let (macro_name, value) = rest.split_at(end);
+                if value.starts_with('(') {
+                    functions.insert(macro_name.to_string());
+                } else {
+                    macros.insert(macro_name.to_string(), value.trim().to_string());
+                }
+            }
+            "undef" if active => {
+                macros.remove(rest);
+                functions.remove(rest);
+            }
+            "error" if active => {
+                return Err(eyre!("{}: #error {}", line.location(), rest));
+            }
+            "version" | "pragma" | "extension" | "line" | "define" | "undef" | "error" => {}
+            other => {
+                return Err(eyre!("{}: unknown directive #{}", line.location(), other));
+            }
+        }
+    }
+    if !blocks.is_empty() {
+        return Err(eyre!("An #if is missing its #endif"));
+    }
+    Ok(source)
+}
+
+/// Parses the shader as a pixel and as a vertex shader, LÖVE compiles the file for both stages
+fn validate(lines: &[Line]) -> color_eyre::Result<()> {
+    let mut entry_points = vec![];
+    for (stage, entry) in [
+        ("PIXEL", ["effect", "effects"]),
+        ("VERTEX", ["position", "position"]),
+    ] {
+        let source = stage_source(lines, stage)?;
+        if source.trim().is_empty() {
+            continue;
+        }
+        let unit = TranslationUnit::parse(&source).map_err(|error| {
+            // The parser reports the line of the source it was given, which has the same lines
+            let line = error
+                .info
+                .split("at line ")
+                .nth(1)
+                .and_then(|x| x.split(|x: char| !x.is_ascii_digit()).next())
+                .and_then(|x| x.parse::<usize>().ok());
+            let location = line
+                .and_then(|x| lines.get(x.checked_sub(1)?))
+                .map(Line::location)
+                .unwrap_or_default();
+            let reason = error
+                .info
+                .lines()
+                .rfind(|x| !x.trim().is_empty())
+                .unwrap_or("");
+            eyre!(
+                "{}: invalid GLSL in the {} stage, {}",
+                location,
+                stage.to_lowercase(),
+                reason.trim()
+            )
+        })?;
+        for declaration in &unit.0.0 {
+            if let ExternalDeclaration::FunctionDefinition(function) = declaration
+                && entry.contains(&function.prototype.name.0.as_str())
+            {
+                entry_points.push(function.prototype.name.0.clone());
+            }
+        }
+    }
+    if entry_points.is_empty() {
+        return Err(eyre!("It has no effect or position function"))
+            .suggestion("Pixel shaders need vec4 effect(vec4 color, Image tex, vec2 texture_coords, vec2 screen_coords) and vertex shaders vec4 position(mat4 transform_projection, vec4 vertex_position)");
+    }
+    Ok(())
+}
+
+/// Takes out the comments, keeping the shader on the same lines
+fn strip_comments(text: &str) -> String {
+    let mut result = String::new();
+    let mut chars = text.chars().peekable();
+    while let Some(character) = chars.next() {
+        match (character, chars.peek()) {
+            ('/', Some('/')) => {
+                while chars.peek().is_some_and(|x| *x != '\n') {
+                    chars.next();
+                }
+            }
+            ('/', Some('*')) => {
+                chars.next();
+                let mut previous = ' ';
+                for character in chars.by_ref() {
+                    if character == '\n' {
+                        result.push('\n');
+                    }
+                    if previous == '*' && character == '/' {
+                        break;
+                    }
+                    previous = character;
+                }
+                result.push(' ');
+            }
+            _ => result.push(character),
+        }
+    }
+    result
+}
+
+/// The file an #include points to, relative to the shader or starting with an alias of .luaurc
+fn include_path(
+    root: &Path,
+    aliases: &[(String, String)],
+    from: &Path,
+    target: &str,
+) -> Option<PathBuf> {
+    if let Some(rest) = target.strip_prefix('@') {
+        let (alias, rest) = rest.split_once('/')?;
+        let (_, folder) = aliases.iter().find(|(name, _)| name == alias)?;
+        return Some(resolve(&resolve(root, folder), rest));
+    }
+    Some(resolve(from.parent()?, target))
+}
+
+/// The defines added after the #version, __DEV__ is 1 in dev builds, __MOBILE__ in the android
+/// ones and __WINDOWS__, __LINUX__, __MACOS__, __ANDROID__ or __WEB__ in the builds of that platform
+fn defines(dev: bool, target: &Target) -> Vec<Line> {
+    let mobile = target.is_android();
+    let platform = platform_define(target);
+    [
+        ("__DEV__", dev),
+        ("__MOBILE__", mobile),
+        ("__DESKTOP__", !mobile),
+    ]
+    .into_iter()
+    .chain(PLATFORM_DEFINES.map(|x| (x, platform == Some(x))))
+    .map(|(name, value)| Line {
+        text: format!("#define {} {}", name, value as u8),
+        origin: None,
+    })
+    .collect()
+}
+
+/// Copies a shader into the output with its includes, every file is only included once
+fn include(
+    root: &Path,
+    aliases: &[(String, String)],
+    path: &Path,
+    stack: &mut Vec<PathBuf>,
+    included: &mut HashSet<PathBuf>,
+    output: &mut Vec<Line>,
+) -> color_eyre::Result<()> {
+    let name = love_path(root, path).unwrap_or(path.display().to_string());
+    let text = fs_err::read_to_string(path)?;
+    stack.push(path.to_path_buf());
+    included.insert(path.to_path_buf());
+    for (index, text) in text.lines().enumerate() {
+        let location = format!("{}:{}", name, index + 1);
+        if let Some(("include", target)) = directive(text) {
+            let target = target.trim_matches(['"', '<', '>']);
+            let file = include_path(root, aliases, path, target)
+                .filter(|x| x.is_file())
+                .ok_or_else(|| eyre!("{}: the include {} doesn't exist", location, target))?;
+            if stack.contains(&file) {
+                return Err(eyre!("{}: including {} makes a loop", location, target))
+                    .suggestion("Move what both files need into a third one");
+            }
+            if !included.contains(&file) {
+                include(root, aliases, &file, stack, included, output)?;
+            }
+            continue;
+        }
+        output.push(Line {
+            text: text.to_string(),
+            origin: Some((name.clone(), index + 1)),
+        });
+    }
+    stack.pop();
+    Ok(())
+}
+
+fn is_shader(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|x| SHADER_EXTENSIONS.contains(&x.to_string_lossy().to_lowercase().as_str()))
+}
+
+impl Builder {
+    /// Whether a file is a shader of the bundle, those are preprocessed instead of added as they are
+    pub fn is_shader_source(&self, path: &Path) -> bool {
+        is_shader(path) && self.in_layout(&self.config.layout.bundle, path)
+    }
+
+    fn shader_files(&self) -> Vec<PathBuf> {
+        self.layout_files(&self.config.layout.bundle)
+            .into_iter()
+            .filter(|x| is_shader(x))
+            .collect()
+    }
+
+    /// Resolves the includes of a shader, adds the defines and validates it
+    fn preprocess_shader(&self, path: &Path, target: &Target) -> color_eyre::Result<String> {
+        let mut body = vec![];
+        include(
+            &self.paths.root,
+            &self.aliases,
+            path,
+            &mut vec![],
+            &mut HashSet::new(),
+            &mut body,
+        )?;
+        // #version and the language pragma have to stay before anything else
+        let header = body
+            .iter()
+            .take_while(|x| {
+                matches!(directive(&x.text), Some(("version", _)))
+                    || matches!(directive(&x.text), Some(("pragma", rest)) if rest.starts_with("language"))
+                    || x.text.trim().is_empty()
+            })
+            .count();
+        let mut lines: Vec<Line> = body.drain(..header).collect();
+        let dev = self.strategy == Strategy::BuildDev;
+        lines.extend(defines(dev, target));
+        lines.extend(body);
+
+        // Files made to be included can't always be validated alone, the shaders using them validate them
+        if let Err(err) = validate(&lines)
+            && !self.included_files().contains(path)
+        {
+            return Err(err);
+        }
+
+        if dev {
+            // LÖVE reports the lines of the processed shader, #line brings back the ones of the
+            // file wherever an include or the defines moved them
+            let mut source = String::new();
+            let mut previous: Option<&(String, usize)> = None;
+            // Nothing can come before the #version
+            for (index, line) in lines.iter().enumerate() {
+                if let Some(origin) = &line.origin
+                    && index >= header
+                    && previous
+                        .is_none_or(|(file, number)| *file != origin.0 || number + 1 != origin.1)
+                {
+                    source.push_str(&format!("#line {}\n", origin.1));
+                }
+                previous = line.origin.as_ref().or(previous);
+                source.push_str(&line.text);
+                source.push('\n');
+            }
+            return Ok(source);
+        }
+        let source = lines
+            .iter()
+            .map(|x| x.text.as_str())
+            .collect::<Vec<_>>()
+            .join("\n");
+        Ok(strip_comments(&source)
+            .lines()
+            .map(str::trim_end)
+            .filter(|x| !x.is_empty())
+            .collect::<Vec<_>>()
+            .join("\n")
+            + "\n")
+    }
+
+    /// The files the shaders of the bundle include
+    fn included_files(&self) -> HashSet<PathBuf> {
+        self.shader_files()
+            .iter()
+            .flat_map(|file| {
+                let text = fs_err::read_to_string(file).unwrap_or_default();
+                text.lines()
+                    .filter_map(|line| match directive(line) {
+                        Some(("include", target)) => include_path(
+                            &self.paths.root,
+                            &self.aliases,
+                            file,
+                            target.trim_matches(['"', '<', '>']),
+                        ),
+                        _ => None,
+                    })
+                    .filter(|x| x != file)
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    }
+
+    /// The LÖVE paths of the included files, the code never loads them but they are used
+    pub fn shader_references(&self) -> Vec<String> {
+        self.included_files()
+            .iter()
+            .filter_map(|x| love_path(&self.paths.root, x))
+            .collect()
+    }
+
+    /// Writes the preprocessed shaders into the build folder, the broken ones are written
+    /// as they are with a warning so LÖVE shows the error on the screen
+    pub async fn write_shaders(&self) -> color_eyre::Result<()> {
+        for file in self.shader_files() {
+            let Some(name) = self.destination(&dev_target(), &file) else {
+                continue;
+            };
+            let output = self.paths.build.join(name);
+            let source = match self.preprocess_shader(&file, &dev_target()) {
+                Ok(source) => source,
+                Err(err) => {
+                    tracing::warn!("{} {:#}", "The shader is broken:".yellow(), err);
+                    fs_err::tokio::read_to_string(&file).await?
+                }
+            };
+            fs_err::tokio::create_dir_all(output.parent().unwrap()).await?;
+            if fs_err::tokio::read_to_string(&output).await.ok() != Some(source.clone()) {
+                // Dev links the assets, the link is replaced instead of writing through it
+                let _ = fs_err::tokio::remove_file(&output).await;
+                fs_err::tokio::write(&output, source).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Adds the preprocessed shaders of a target to its .love
+    pub fn add_shaders(&self, zipper: &mut Zipper, target: &Target) -> color_eyre::Result<()> {
+        let mut errors = vec![];
+        for file in self.shader_files() {
+            let Some(name) = self.destination(target, &file) else {
+                continue;
+            };
+            match self.preprocess_shader(&file, target) {
+                Ok(source) => {
+                    zipper.add_buffer(&name, source.as_bytes())?;
+                }
+                Err(err) => errors.push(format!("{:#}", err)),
+            }
+        }
+        if errors.is_empty() {
+            return Ok(());
+        }
+        Err(eyre!("Some shaders are broken:\n{}", errors.join("\n")))
+            .suggestion("The errors point to the line of the file that has it, includes included")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EFFECT: &str = "vec4 effect(vec4 color, Image tex, vec2 texture_coords, vec2 screen_coords) { return color; }";
+
+    fn lines(file: &str, text: &str) -> Vec<Line> {
+        text.lines()
+            .enumerate()
+            .map(|(index, text)| Line {
+                text: text.to_string(),
+                origin: Some((file.to_string(), index + 1)),
+            })
+            .collect()
+    }
+
+    /// The lines of a stage that have something left
+    fn kept(lines: &[Line], stage: &str) -> Vec<String> {
+        stage_source(lines, stage)
+            .unwrap()
+            .lines()
+            .map(str::trim)
+            .filter(|x| !x.is_empty())
+            .map(str::to_string)
+            .collect()
+    }
+
+    /// A project in a temporary folder with the given shaders, included into their lines
+    fn included(name: &str, files: &[(&str, &str)]) -> color_eyre::Result<Vec<Line>> {
+        let root =
+            std::env::temp_dir().join(format!("kaledis-shaders-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        for (path, contents) in files {
+            let path = root.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, contents).unwrap();
+        }
+        let aliases = [("shaders".to_string(), "lib".to_string())];
+        let mut output = vec![];
+        include(
+            &root,
+            &aliases,
+            &root.join(files[0].0),
+            &mut vec![],
+            &mut HashSet::new(),
+            &mut output,
+        )?;
+        Ok(output)
+    }
+
+    #[test]
+    fn takes_one_branch_of_nested_ifs() {
+        let shader = lines(
+            "a.glsl",
+            "#define LEVEL 2
+#if LEVEL > 2
+a
+#elif LEVEL == 2
+#ifdef PIXEL
+b
+#elif defined(VERTEX) && LEVEL
+c
+#else
+d
+#endif
+#elif 1
+e
+#else
+f
+#endif
+#if 0
+#if 1
+g
+#elif 1
+h
+#endif
+#else
+i
+#endif",
+        );
+        assert_eq!(kept(&shader, "PIXEL"), ["b", "i"]);
+        assert_eq!(kept(&shader, "VERTEX"), ["c", "i"]);
+        // Every line stays where it was, so the errors point to it
+        assert_eq!(
+            stage_source(&shader, "PIXEL").unwrap().lines().count(),
+            shader.len()
+        );
+    }
+
+    #[test]
+    fn evaluates_conditions_like_c() {
+        let macros = HashMap::from([("TWO".to_string(), "2".to_string())]);
+        assert_eq!(
+            evaluate("1 + TWO * 3 == 7 && !defined FOO", &macros),
+            Some(1)
+        );
+        assert_eq!(
+            evaluate("(1 || 0) && defined(TWO) && TWO < 2", &macros),
+            Some(0)
+        );
+        // Undefined names are 0
+        assert_eq!(evaluate("UNKNOWN", &macros), Some(0));
+        assert_eq!(evaluate("TWO / 0", &macros), None);
+        assert_eq!(evaluate("1 +", &macros), None);
+    }
+
+    #[test]
+    fn points_directive_errors_to_their_line() {
+        let error = |text: &str| {
+            stage_source(&lines("a.glsl", text), "PIXEL")
+                .unwrap_err()
+                .to_string()
+        };
+        assert_eq!(error("x\n#endif"), "a.glsl:2: #endif without #if");
+        assert_eq!(error("#else"), "a.glsl:1: #else without #if");
+        assert_eq!(error("#if 1\nx"), "An #if is missing its #endif");
+        assert_eq!(error("\n#foo"), "a.glsl:2: unknown directive #foo");
+        assert_eq!(error("#if 1 +\n#endif"), "a.glsl:1: can't evaluate #if 1 +");
+        assert_eq!(
+            error("#ifdef VERTEX\n#error no\n#else\n#error yes\n#endif"),
+            "a.glsl:4: #error yes"
+        );
+    }
+
+    #[test]
+    fn defines_the_platform_of_the_target() {
+        let shader = "#if __WEB__
+web
+#elif __ANDROID__ && __MOBILE__
+android
+#elif __WINDOWS__ || __LINUX__ || __MACOS__
+desktop
+#else
+anywhere
+#endif";
+        for (target, kept_line) in [
+            (Target::Web, "web"),
+            (Target::AndroidBundle, "android"),
+            (Target::WindowsX86, "desktop"),
+            (Target::LinuxAarch64, "desktop"),
+            (Target::Macos, "desktop"),
+            (Target::LoveFile, "anywhere"),
+        ] {
+            let mut source = defines(false, &target);
+            source.extend(lines("a.glsl", shader));
+            assert_eq!(kept(&source, "PIXEL"), [kept_line], "{}", target.name());
+        }
+    }
+
+    #[test]
+    fn includes_every_file_once() {
+        let output = included(
+            "once",
+            &[
+                (
+                    "main.glsl",
+                    "#include \"common.glsl\"\n#include <@shaders/lib.glsl>\nmain",
+                ),
+                ("common.glsl", "common"),
+                ("lib/lib.glsl", "#include \"../common.glsl\"\nlib"),
+            ],
+        )
+        .unwrap();
+        let origins: Vec<_> = output.iter().map(Line::location).collect();
+        assert_eq!(origins, ["common.glsl:1", "lib/lib.glsl:2", "main.glsl:3"]);
+    }
+
+    #[test]
+    fn stops_include_loops() {
+        let error = included(
+            "loop",
+            &[
+                ("a.glsl", "#include \"b.glsl\""),
+                ("b.glsl", "// b\n#include \"a.glsl\""),
+            ],
+        )
+        .unwrap_err();
+        assert_eq!(error.to_string(), "b.glsl:2: including a.glsl makes a loop");
+
+        let error = included("missing", &[("a.glsl", "\n#include \"nope.glsl\"")]).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "a.glsl:2: the include nope.glsl doesn't exist"
+        );
+    }
+
+    #[test]
+    fn points_glsl_errors_to_the_included_file() {
+        let main = format!("#include \"lib.glsl\"\n{}", EFFECT);
+        let lib = "float half(float x) { return x / 2.0; }\nfloat broken(float x) { return x +; }";
+        let output = included("glsl", &[("main.glsl", &main), ("lib.glsl", lib)]).unwrap();
+        let error = validate(&output).unwrap_err().to_string();
+        assert!(
+            error.starts_with("lib.glsl:2: invalid GLSL in the pixel stage"),
+            "{}",
+            error
+        );
+
+        let output = included("valid", &[("main.glsl", EFFECT)]).unwrap();
+        validate(&output).unwrap();
+    }
+}
@@ -0,0 +1,139 @@
+use std::path::Path;
+
+use color_eyre::eyre::Context;
+use colored::Colorize;
+use walkdir::WalkDir;
+
+use crate::{
+    commands::build::{Builder, LoveCfg},
+    home_manager::Target,
+    toml_conf::WebConfig,
+};
+
+// APIs love.js can't provide, browsers don't let pages do these things
+const UNSUPPORTED: [(&str, &str); 9] = [
+    (
+        "love.system.getClipboardText",
+        "the clipboard isn't available",
+    ),
+    (
+        "love.system.setClipboardText",
+        "the clipboard isn't available",
+    ),
+    ("love.system.vibrate", "there is no vibration"),
+    ("love.system.getPowerInfo", "there is no battery info"),
+    ("love.graphics.newVideo", "videos can't be decoded"),
+    ("require(\"socket", "there are no sockets"),
+    ("require(\"enet\")", "there are no sockets"),
+    ("os.execute", "processes can't be started"),
+    ("io.popen", "processes can't be started"),
+];
+
+/// Warns about the APIs of the transpiled files that won't work in the browser
+pub fn warn_unsupported(build_folder: &Path, config: &WebConfig) {
+    let mut warned = vec![];
+    for entry in WalkDir::new(build_folder)
+        .into_iter()
+        .filter_map(Result::ok)
+        .filter(|x| x.path().extension().is_some_and(|x| x == "lua"))
+    {
+        let Ok(content) = fs_err::read_to_string(entry.path()) else {
+            continue;
+        };
+        let file = entry
+            .path()
+            .strip_prefix(build_folder)
+            .unwrap_or(entry.path());
+
+        let threads = (!config.threaded).then_some((
+            "love.thread",
+            "threads only work on the threaded build, set web.threaded",
+        ));
+        for (api, reason) in UNSUPPORTED.iter().copied().chain(threads) {
+            if content.contains(api) && !warned.contains(&api) {
+                warned.push(api);
+                tracing::warn!(
+                    "{} is used in {} but won't work on the web, {}",
+                    api.yellow(),
+                    file.display(),
+                    reason
+                );
+            }
+        }
+    }
+}
+
+/// The window title goes into the page as text, so it can't open tags
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// Packages the .love with love.js into a static site in dist/Web
+pub async fn build_web(builder: &Builder, data: &[u8]) -> color_eyre::Result<()> {
+    let config = builder.config.web.as_ref();
+    let default = WebConfig::default();
+    let config = config.unwrap_or(&default);
+
+    let dists = builder.paths.dist.join(Target::Web.as_ref());
+    if dists.exists() {
+        fs_err::tokio::remove_dir_all(&dists).await?;
+    }
+    fs_err::tokio::create_dir_all(&dists).await?;
+
+    // The release build uses threads, which need SharedArrayBuffer
+    let runtime = builder
+        .runtime_path(Target::Web)
+        .await?
+        .join(if config.threaded { "release" } else { "compat" });
+    for file in ["love.js", "love.wasm", "love.worker.js"] {
+        if runtime.join(file).exists() {
+            fs_err::tokio::copy(runtime.join(file), dists.join(file))
+                .await
+                .context("Copying love.js")?;
+        }
+    }
+
+    let (title, width, height) = match &builder.love_config {
+        LoveCfg::Config(love) => (
+            love.window.title.clone(),
+            love.window.width,
+            love.window.height,
+        ),
+        LoveCfg::File(_) => (builder.config.project_name.clone(), 800, 600),
+    };
+    let index = include_str!("../../../static/web_index.html")
+        .replace("{{TITLE}}", &escape_html(&title))
+        .replace("{{WIDTH}}", &config.width.unwrap_or(width).to_string())
+        .replace("{{HEIGHT}}", &config.height.unwrap_or(height).to_string())
+        .replace("{{MEMORY}}", &config.memory.to_string());
+
+    fs_err::tokio::write(dists.join("index.html"), index).await?;
+    fs_err::tokio::write(
+        dists.join("game.js"),
+        include_str!("../../../static/web_loader.js"),
+    )
+    .await?;
+    fs_err::tokio::write(dists.join("game.data"), data).await?;
+
+    if !builder.config.layout.external.is_empty() {
+        tracing::warn!(
+            "External assets aren't included in the web build, the browser can only read the .love"
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escapes_the_title() {
+        assert_eq!(
+            escape_html("Tom & Jerry </title><script>"),
+            "Tom &amp; Jerry &lt;/title&gt;&lt;script&gt;"
+        );
+    }
+}
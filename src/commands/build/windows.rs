@@ -1,11 +1,16 @@
 use crate::commands::build::Builder;
 use crate::editpe;
+use crate::home_manager::Target;
 use color_eyre::{Section, eyre::Context};
 use fs_err::tokio::{File, create_dir_all, hard_link, remove_file};
 use tokio::io::AsyncWriteExt;
 
-pub async fn build_windows(builder: &Builder, data: &[u8]) -> color_eyre::Result<()> {
-    let dists = builder.paths.dist.join("Windows");
+pub async fn build_windows(
+    builder: &Builder,
+    data: &[u8],
+    target: Target,
+) -> color_eyre::Result<()> {
+    let dists = builder.paths.dist.join(target.as_ref());
     let exe = dists.join("love.exe");
     let mut exe_file = File::open(&exe).await.expect("Failed to open love.exe");
 
@@ -31,26 +36,20 @@ pub async fn build_windows(builder: &Builder, data: &[u8]) -> color_eyre::Result
         .await
         .expect("Failed to remove original love.exe");
 
-    for pattern in &builder.config.layout.external {
-        for path in glob::glob(&builder.paths.root.join(pattern).to_string_lossy())
-            .context("Building for windows")
-            .expect("Failed to parse glob")
-            .filter_map(Result::ok)
-        {
-            let output = dists.join(
-                path.strip_prefix(&builder.paths.root)
-                    .context("Building for windows")
-                    .suggestion("Don't use assets outside the root of your project")
-                    .expect("Failed to strip root"),
-            );
-            create_dir_all(&output.parent().unwrap())
-                .await
-                .expect("Failed to create output file");
-            if output.exists() {
-                remove_file(&output).await.expect("Failed to clean folder");
-            }
-            hard_link(&path, output).await.expect("Failed to link file");
+    for path in builder.layout_files(&builder.config.layout.external) {
+        let output = dists.join(
+            path.strip_prefix(&builder.paths.root)
+                .context("Building for windows")
+                .suggestion("Don't use assets outside the root of your project")
+                .expect("Failed to strip root"),
+        );
+        create_dir_all(&output.parent().unwrap())
+            .await
+            .expect("Failed to create output file");
+        if output.exists() {
+            remove_file(&output).await.expect("Failed to clean folder");
         }
+        hard_link(&path, output).await.expect("Failed to link file");
     }
 
     Ok(())
@@ -1,6 +1,8 @@
 pub mod android;
 pub mod build;
 pub mod init;
+pub mod serve;
+pub mod toolchain;
 pub mod update_polyfill;
 pub mod watch;
 
@@ -9,7 +11,11 @@ use std::{default, path::PathBuf};
 use clap::{Parser, Subcommand, ValueEnum};
 use tokio::fs;
 
-use crate::{commands::init::replace_bytes, home_manager::Target, toml_conf::KaledisConfig};
+use crate::{
+    commands::{init::replace_bytes, toolchain::ToolchainCommands},
+    home_manager::Target,
+    toml_conf::KaledisConfig,
+};
 
 // Assets is now more dynamic
 #[derive(ValueEnum, Clone, Debug)]
@@ -44,8 +50,36 @@ pub enum Commands {
     )]
     AndroidDev { ip: String, path: Option<PathBuf> },
 
+    #[clap(about = "Hosts the web build locally, with the headers love.js needs")]
+    Serve {
+        path: Option<PathBuf>,
+        #[arg(short, long, default_value_t = 8000)]
+        port: u16,
+    },
+
     #[clap(about = "Updates the polyfill used")]
     UpdatePolyfill,
+
+    #[clap(about = "Manages the LÖVE versions downloaded to ~/.kaledis")]
+    Toolchain {
+        #[command(subcommand)]
+        command: ToolchainCommands,
+    },
+
+    #[clap(about = "Android utilities")]
+    Android {
+        #[command(subcommand)]
+        command: AndroidCommands,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum AndroidCommands {
+    #[clap(about = "Creates a debug keystore under ~/.kaledis, used when no signing config exists")]
+    Keygen {
+        #[arg(short, long, help = "Replaces the existing debug keystore.")]
+        force: bool,
+    },
 }
 
 #[derive(Parser, Debug)]
@@ -53,6 +87,12 @@ pub enum Commands {
 pub struct CLI {
     #[command(subcommand)]
     pub cli: Commands,
+    #[arg(
+        long,
+        global = true,
+        help = "Never downloads anything, fails if a runtime is missing from the cache."
+    )]
+    pub offline: bool,
     // TODO: make subcommands shortcuts to flags
 }
 
@@ -138,8 +178,19 @@ pub async fn handle_commands(command: Commands) {
         Commands::Dev { path } => {
             watch::watch(path).await;
         }
+        Commands::Serve { path, port } => {
+            serve::serve(path, port).await.unwrap();
+        }
         Commands::UpdatePolyfill => {
             update_polyfill::update_polyfill().await.unwrap();
         }
+        Commands::Toolchain { command } => {
+            toolchain::toolchain(command).await.unwrap();
+        }
+        Commands::Android { command } => match command {
+            AndroidCommands::Keygen { force } => {
+                android::keygen(force).await.unwrap();
+            }
+        },
     }
 }
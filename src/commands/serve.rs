@@ -0,0 +1,124 @@
+use std::path::{Component, Path, PathBuf};
+
+use color_eyre::{Section, eyre::eyre};
+use colored::Colorize;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream},
+};
+
+use crate::{home_manager::Target, utils::relative};
+
+fn content_type(path: &Path) -> &'static str {
+    match path.extension().and_then(|x| x.to_str()) {
+        Some("html") => "text/html; charset=utf-8",
+        Some("js") => "text/javascript",
+        Some("wasm") => "application/wasm",
+        Some("css") => "text/css",
+        Some("png") => "image/png",
+        Some("ico") => "image/x-icon",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Maps the request path into the served folder, refusing anything that escapes it
+fn resolve(root: &Path, target: &str) -> Option<PathBuf> {
+    let target = target.split(['?', '#']).next()?;
+    let mut path = root.to_path_buf();
+    for component in Path::new(target.trim_start_matches('/')).components() {
+        match component {
+            Component::Normal(part) => path.push(part),
+            Component::CurDir => {}
+            _ => return None,
+        }
+    }
+    if path.is_dir() {
+        path.push("index.html");
+    }
+    Some(path)
+}
+
+async fn respond(mut stream: TcpStream, root: &Path) -> color_eyre::Result<()> {
+    let mut reader = BufReader::new(&mut stream);
+    let mut request = String::new();
+    reader.read_line(&mut request).await?;
+    // The headers aren't needed, but they have to be read before answering
+    let mut line = String::new();
+    while reader.read_line(&mut line).await? > 2 {
+        line.clear();
+    }
+
+    let mut parts = request.split_whitespace();
+    let method = parts.next().unwrap_or_default();
+    let target = parts.next().unwrap_or("/");
+
+    let file = resolve(root, target).filter(|x| x.is_file());
+    let (status, body, mime) = match (method, &file) {
+        ("GET" | "HEAD", Some(file)) => (
+            "200 OK",
+            fs_err::tokio::read(file).await?,
+            content_type(file),
+        ),
+        ("GET" | "HEAD", None) => ("404 Not Found", b"Not found".to_vec(), "text/plain"),
+        _ => ("405 Method Not Allowed", vec![], "text/plain"),
+    };
+
+    // Threaded builds of love.js need SharedArrayBuffer, which browsers only
+    // enable on cross origin isolated pages
+    let header = format!(
+        "HTTP/1.1 {}\r\n\
+         Content-Type: {}\r\n\
+         Content-Length: {}\r\n\
+         Cross-Origin-Opener-Policy: same-origin\r\n\
+         Cross-Origin-Embedder-Policy: require-corp\r\n\
+         Cross-Origin-Resource-Policy: same-origin\r\n\
+         Cache-Control: no-cache\r\n\
+         Connection: close\r\n\r\n",
+        status,
+        mime,
+        body.len()
+    );
+    stream.write_all(header.as_bytes()).await?;
+    if method != "HEAD" {
+        stream.write_all(&body).await?;
+    }
+    stream.flush().await?;
+
+    let code = status.split(' ').next().unwrap_or_default();
+    println!(
+        "{} {} {}",
+        method,
+        target,
+        if code == "200" {
+            code.green()
+        } else {
+            code.red()
+        }
+    );
+    Ok(())
+}
+
+/// Hosts dist/Web locally
+pub async fn serve(path: Option<PathBuf>, port: u16) -> color_eyre::Result<()> {
+    let root = relative(path).join("dist").join(Target::Web.as_ref());
+    if !root.join("index.html").exists() {
+        return Err(eyre!("There is no web build in {}", root.display()))
+            .suggestion("Build it first with `kaledis build -p web`");
+    }
+
+    let listener = TcpListener::bind(("127.0.0.1", port)).await?;
+    println!(
+        "Serving {} at {}",
+        root.display(),
+        format!("http://127.0.0.1:{}", port).bold()
+    );
+    loop {
+        let (stream, _) = listener.accept().await?;
+        let root = root.clone();
+        tokio::spawn(async move {
+            if let Err(err) = respond(stream, &root).await {
+                tracing::warn!("Failed to answer a request: {}", err);
+            }
+        });
+    }
+}
@@ -0,0 +1,261 @@
+use std::path::{Path, PathBuf};
+
+use clap::Subcommand;
+use color_eyre::{
+    Section,
+    eyre::{Context, eyre},
+};
+use colored::Colorize;
+use indicatif::HumanBytes;
+use inquire::Confirm;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    home_manager::{Artifact, CURRENT_PLATFORM, HomeManager, Target},
+    toml_conf::KaledisConfig,
+    utils::relative,
+};
+
+pub static LOCK_FILE: &str = "love-toolchain.lock";
+
+#[derive(Subcommand, Debug)]
+pub enum ToolchainCommands {
+    #[clap(about = "Lists the installed LÖVE versions and their disk usage")]
+    List,
+    #[clap(about = "Downloads a LÖVE version")]
+    Install {
+        version: String,
+        #[arg(
+            short,
+            long,
+            help = "The targets to download, defaults to the current platform."
+        )]
+        targets: Option<Vec<Target>>,
+    },
+    #[clap(about = "Removes a LÖVE version")]
+    Remove {
+        version: String,
+        #[arg(short, long, help = "Only removes these targets.")]
+        targets: Option<Vec<Target>>,
+    },
+    #[clap(
+        about = "Removes every LÖVE version the project and the others built on this computer don't use"
+    )]
+    Prune {
+        path: Option<PathBuf>,
+        #[arg(short, long, help = "Other versions to keep.")]
+        keep: Vec<String>,
+        #[arg(long, help = "Also removes the versions of the other projects.")]
+        all: bool,
+        #[arg(short, long, help = "Removes them without asking.")]
+        yes: bool,
+    },
+    #[clap(about = "Adds a pre-downloaded LÖVE release, java runtime or jar to the cache")]
+    Import {
+        file: PathBuf,
+        #[arg(long, help = "Inferred from the file name when left out.")]
+        version: Option<String>,
+        #[arg(short, long, help = "Inferred from the file name when left out.")]
+        target: Option<Target>,
+    },
+    #[clap(about = "Prints where a LÖVE version is installed")]
+    Path {
+        version: String,
+        #[arg(short, long, help = "Defaults to the current platform.")]
+        target: Option<Target>,
+    },
+}
+
+/// Records which exact love builds a project was built with
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct ToolchainLock {
+    #[serde(default)]
+    pub artifact: Vec<Artifact>,
+}
+
+/// Adds the artifacts to the lock of the project, replacing the ones of the same target
+pub async fn update_lock(root: &Path, artifacts: Vec<Artifact>) -> color_eyre::Result<()> {
+    if artifacts.is_empty() {
+        return Ok(());
+    }
+    let path = root.join(LOCK_FILE);
+    let mut lock: ToolchainLock = match fs_err::tokio::read_to_string(&path).await {
+        Ok(text) => toml::from_str(&text)
+            .wrap_err_with(|| format!("Failed to read {}", path.display()))
+            .suggestion("Fix the file or delete it, the next build writes it again")?,
+        Err(_) => ToolchainLock::default(),
+    };
+    for artifact in artifacts {
+        lock.artifact.retain(|x| x.target != artifact.target);
+        lock.artifact.push(artifact);
+    }
+    lock.artifact.sort_by(|a, b| a.target.cmp(&b.target));
+
+    fs_err::tokio::write(
+        &path,
+        format!(
+            "# Generated by kaledis, records the LÖVE builds this project was built with\n{}",
+            toml::to_string(&lock)?
+        ),
+    )
+    .await?;
+    Ok(())
+}
+
+pub async fn toolchain(command: ToolchainCommands) -> color_eyre::Result<()> {
+    let home = HomeManager::new().await?;
+    match command {
+        ToolchainCommands::List => {
+            let installed = home.installed().await?;
+            if installed.is_empty() {
+                println!("No LÖVE versions installed");
+            }
+            for item in &installed {
+                let source = match &item.artifact {
+                    Some(Artifact {
+                        run_id: Some(run_id),
+                        ..
+                    }) => format!("CI run {}", run_id),
+                    Some(Artifact { url: Some(url), .. }) => url.clone(),
+                    _ => "unknown source, installed by an older kaledis".to_string(),
+                };
+                println!(
+                    "{:<10} {:<14} {:>10}  {}",
+                    item.version.bold(),
+                    item.target.as_ref(),
+                    HumanBytes(item.size).to_string(),
+                    source.dimmed()
+                );
+            }
+            let total: u64 = installed.iter().map(|x| x.size).sum();
+            println!("Total: {}", HumanBytes(total));
+        }
+        ToolchainCommands::Install { version, targets } => {
+            for target in targets.unwrap_or(vec![CURRENT_PLATFORM.clone()]) {
+                if target == Target::LoveFile {
+                    continue;
+                }
+                home.ensure_version(&version, target.clone()).await?;
+                println!("Installed LÖVE {} for {}", version, target.as_ref());
+            }
+        }
+        ToolchainCommands::Remove { version, targets } => {
+            let installed = home.installed().await?;
+            let removing: Vec<_> = installed
+                .iter()
+                .filter(|x| x.version == version)
+                .filter(|x| targets.as_ref().is_none_or(|t| t.contains(&x.target)))
+                .collect();
+            if removing.is_empty() {
+                return Err(eyre!("LÖVE {} is not installed", version))
+                    .suggestion("Run `kaledis toolchain list` to see the installed versions");
+            }
+            for item in removing {
+                home.remove(&item.version, &item.target).await?;
+                println!(
+                    "Removed LÖVE {} for {} ({})",
+                    item.version,
+                    item.target.as_ref(),
+                    HumanBytes(item.size)
+                );
+            }
+        }
+        ToolchainCommands::Prune {
+            path,
+            mut keep,
+            all,
+            yes,
+        } => {
+            let root = relative(path);
+            // Without the project it can't tell which versions are used, so nothing is removed
+            let config = KaledisConfig::from_toml_file(root.join("kaledis.toml"))
+                .wrap_err("Failed to read the kaledis.toml of the project")
+                .suggestion("Run it inside a project or pass the path of one")?;
+            keep.extend(config.love.pinned_version().map(|x| x.to_string()));
+            let root = root.canonicalize().unwrap_or(root);
+            let mut others: Vec<(String, PathBuf)> = vec![];
+            for project in home.projects().await {
+                if project == root {
+                    continue;
+                }
+                if let Ok(config) = KaledisConfig::from_toml_file(project.join("kaledis.toml"))
+                    && let Some(version) = config.love.pinned_version()
+                {
+                    others.push((version.to_string(), project));
+                }
+            }
+            home.clean_temp().await?;
+
+            let mut removing = vec![];
+            for item in home.installed().await? {
+                if keep.contains(&item.version) {
+                    continue;
+                }
+                match others.iter().find(|(version, _)| *version == item.version) {
+                    Some((_, project)) if !all => println!(
+                        "Keeping LÖVE {} for {}, {} uses it",
+                        item.version,
+                        item.target.as_ref(),
+                        project.display()
+                    ),
+                    _ => removing.push(item),
+                }
+            }
+            if removing.is_empty() {
+                println!("Nothing to remove");
+                return Ok(());
+            }
+
+            println!("These will be removed:");
+            for item in &removing {
+                println!(
+                    "  LÖVE {} for {} ({})",
+                    item.version,
+                    item.target.as_ref(),
+                    HumanBytes(item.size)
+                );
+            }
+            if !yes
+                && !Confirm::new("Remove them?")
+                    .with_default(false)
+                    .prompt()
+                    .wrap_err("Failed to ask for confirmation")
+                    .suggestion("Pass --yes to remove them without asking")?
+            {
+                return Ok(());
+            }
+            let mut freed = 0;
+            for item in removing {
+                home.remove(&item.version, &item.target).await?;
+                println!("Removed LÖVE {} for {}", item.version, item.target.as_ref());
+                freed += item.size;
+            }
+            println!("Freed {}", HumanBytes(freed));
+        }
+        ToolchainCommands::Import {
+            file,
+            version,
+            target,
+        } => {
+            let installed = home.import(&file, version, target).await?;
+            println!("Imported {}", installed);
+        }
+        ToolchainCommands::Path { version, target } => {
+            let target = target.unwrap_or(CURRENT_PLATFORM.clone());
+            let path = home.get_path(&version, target.clone()).await;
+            if !path.exists() {
+                return Err(eyre!(
+                    "LÖVE {} for {} is not installed",
+                    version,
+                    target.as_ref()
+                ))
+                .suggestion(format!(
+                    "Run `kaledis toolchain install {}` to download it",
+                    version
+                ));
+            }
+            println!("{}", path.display());
+        }
+    }
+    Ok(())
+}
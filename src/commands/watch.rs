@@ -19,13 +19,20 @@ use tracing::{info, warn};
 
 use crate::{
     android::DevServer,
-    commands::build::{Builder, Strategy},
-    home_manager::CURRENT_PLATFORM,
+    commands::build::{
+        Builder, Strategy,
+        assets::love_path,
+        layout::{is_ignored, read_ignore},
+        maps::{MAP_DEPENDENCIES, MAP_EXTENSIONS},
+        runtime::dev_executable,
+        shaders::SHADER_EXTENSIONS,
+    },
     utils::relative,
 };
 
 async fn spawn_file_reader(watching: Arc<RwLock<bool>>, local: &PathBuf, sender: Sender<Message>) {
     let local = local.clone();
+    let ignore = read_ignore(&local);
     tokio::spawn(async move {
         let (mut c, mut r) = AsyncDebouncer::new_with_channel(
             Duration::from_millis(1),
@@ -45,7 +52,12 @@ async fn spawn_file_reader(watching: Arc<RwLock<bool>>, local: &PathBuf, sender:
             }
             if data
                 .iter()
-                .filter(|x| !x.path.starts_with(local.join(".build")))
+                .filter(|x| {
+                    // The asset module is generated by the build itself
+                    !x.path.starts_with(local.join(".build"))
+                        && !x.path.starts_with(local.join(".kaledis"))
+                        && !is_ignored(&ignore, &x.path)
+                })
                 .collect::<Vec<_>>()
                 .len()
                 < 1
@@ -56,9 +68,16 @@ async fn spawn_file_reader(watching: Arc<RwLock<bool>>, local: &PathBuf, sender:
                 .send(Message::BuildProject(Some(
                     data.iter()
                         .map(|x| x.path.clone())
+                        .filter(|x| !is_ignored(&ignore, x))
                         .filter(|x| {
                             if let Some(ext) = x.extension() {
-                                if ext == "luau" {
+                                // Maps, the tilesets they load and shaders are processed again on hot reload
+                                let ext = ext.to_string_lossy().to_lowercase();
+                                if ext == "luau"
+                                    || MAP_EXTENSIONS.contains(&ext.as_str())
+                                    || MAP_DEPENDENCIES.contains(&ext.as_str())
+                                    || SHADER_EXTENSIONS.contains(&ext.as_str())
+                                {
                                     return true;
                                 }
                             };
@@ -136,21 +155,23 @@ pub async fn watch(base_path: Option<PathBuf>) {
     spawn_file_reader(watching, &local, sender.clone()).await;
 
     builder.clean_build_folder().await.unwrap();
+    if let Err(err) = builder.check_assets(&[]).await {
+        warn!("{}", err);
+    }
     builder.transpile().await;
-    let mut path = builder
-        .home
-        .get_path(&builder.config.love, CURRENT_PLATFORM.clone())
-        .await;
-
-    #[cfg(windows)]
-    path.push("love.exe");
-
-    #[cfg(target_os = "linux")]
-    path.push("love2d.AppImage");
+    // A half written map or sheet is reported, the next save converts it again
+    if let Err(err) = builder.add_assets(false).await {
+        warn!("{:#}", err);
+    }
+    let path = dev_executable(&builder.home, &builder.paths.root, &builder.config.love)
+        .await
+        .unwrap();
 
     let sppawn = async || {
+        // Only the downloaded AppImage needs it, system installs are already executable
         #[cfg(target_os="linux")]
-        if let Ok(mut chd) = Command::new("chmod")
+        if path.extension().is_some_and(|x| x == "AppImage")
+            && let Ok(mut chd) = Command::new("chmod")
             .current_dir(&path.parent().unwrap())
             .args(["+x", &path.to_string_lossy()])
             .spawn()
@@ -195,14 +216,23 @@ pub async fn watch(base_path: Option<PathBuf>) {
             if builder.config.hmr
                 && let Some(files) = &change
             {
-                builder.add_assets(None, false).await;
+                if let Err(err) = builder.add_assets(false).await {
+                    warn!("{:#}", err);
+                }
                 for file in files {
-                    builder._transpile_files(&file, &builder.paths.build).await;
+                    if file.extension().is_some_and(|x| x == "luau") {
+                        builder._transpile_files(&file, &builder.paths.build).await;
+                    }
                 }
             } else {
                 builder.clean_build_folder().await.unwrap();
+                if let Err(err) = builder.check_assets(&[]).await {
+                    warn!("{}", err);
+                }
                 let modules = builder.transpile().await;
-                builder.add_assets(None, false).await;
+                if let Err(err) = builder.add_assets(false).await {
+                    warn!("{:#}", err);
+                }
                 builder.handle_conf_file(modules).await;
             }
 
@@ -228,12 +258,25 @@ pub async fn watch(base_path: Option<PathBuf>) {
                             "update",
                             files
                                 .iter()
-                                .map(|x| {
-                                    x.strip_prefix(&builder.paths.src)
-                                        .expect("Invalid prefix path, report this error on github.")
-                                        .with_extension("lua")
-                                        .to_string_lossy()
-                                        .to_string()
+                                .filter_map(|x| {
+                                    if builder.is_shader_source(x) {
+                                        return love_path(&builder.paths.root, x);
+                                    }
+                                    if x.extension().is_some_and(|x| x != "luau") {
+                                        // Tilesets have no module, the maps using them were converted again
+                                        return builder
+                                            .map_module(x)
+                                            .map(|x| x.to_string_lossy().to_string());
+                                    }
+                                    Some(
+                                        x.strip_prefix(&builder.paths.src)
+                                            .expect(
+                                                "Invalid prefix path, report this error on github.",
+                                            )
+                                            .with_extension("lua")
+                                            .to_string_lossy()
+                                            .to_string(),
+                                    )
                                 })
                                 .join(",")
                                 .as_bytes()
@@ -88,6 +88,17 @@ impl<'a> NodeProcessor for Processor<'a> {
             let require = expr.get_value();
             let is_relative = require.starts_with(b"../") || require.starts_with(b"./");
             for preset in self.paths {
+                // require("@alias") loads the init file of the alias folder
+                if require == format!("@{}", preset.0).as_bytes() {
+                    let pth = path::absolute(self.project_root.join(&preset.1))
+                        .expect("Failed To Find Module");
+                    *expr = StringExpression::from_value(to_module_path(
+                        self.project_root_src,
+                        self.project_root,
+                        pth,
+                    ));
+                    return;
+                }
                 if let Some(requested_package) =
                     require.strip_prefix(format!("@{}/", preset.0).as_bytes())
                 {
@@ -5,9 +5,11 @@ use darklua_core::{
 };
 use fs_err::remove_file;
 use full_moon::{
-    tokenizer::{Token, TokenType},
+    ast::{Call, Expression, FunctionArgs, FunctionCall, Index, Prefix, Suffix},
+    tokenizer::{Token, TokenReference, TokenType},
     visitors::Visitor,
 };
+use ignore::gitignore::Gitignore;
 use indexmap::{IndexMap, IndexSet};
 use rayon::{
     ThreadPoolBuilder,
@@ -18,14 +20,17 @@ use std::{
     env::temp_dir,
     ffi::OsStr,
     io::Write,
-    path::PathBuf,
+    path::{Path, PathBuf},
     str::FromStr,
     sync::{Arc, Mutex},
 };
 use walkdir::WalkDir;
 
 use crate::{
-    commands::build::build_utils::{Paths, normalize_lua_path},
+    commands::build::{
+        build_utils::{Paths, normalize_lua_path},
+        layout::is_ignored,
+    },
     dalbit::{
         manifest::Manifest,
         modifiers::{Modifier, ModifyPathModifier},
@@ -95,6 +100,105 @@ impl Visitor for CollectUsedLibraries {
     }
 }
 
+/// LÖVE functions that take the path of an asset as the first argument
+pub const ASSET_LOADERS: [&str; 20] = [
+    "love.graphics.newImage",
+    "love.graphics.newFont",
+    "love.graphics.setNewFont",
+    "love.graphics.newImageFont",
+    "love.graphics.newShader",
+    "love.graphics.newVideo",
+    "love.graphics.newCubeImage",
+    "love.graphics.newArrayImage",
+    "love.graphics.newVolumeImage",
+    "love.graphics.newTexture",
+    "love.audio.newSource",
+    "love.sound.newSoundData",
+    "love.sound.newDecoder",
+    "love.image.newImageData",
+    "love.image.newCompressedData",
+    "love.video.newVideoStream",
+    "love.font.newRasterizer",
+    "love.font.newTrueTypeRasterizer",
+    "love.font.newBMFontRasterizer",
+    "love.mouse.newCursor",
+];
+
+/// They can also read what the game saved, so the path isn't always an asset
+pub const FILESYSTEM_READERS: [&str; 3] = [
+    "love.filesystem.read",
+    "love.filesystem.lines",
+    "love.filesystem.load",
+];
+
+/// Collects the paths given to LÖVE loaders and every string of a file
+#[derive(Debug, Default)]
+pub struct CollectAssetLiterals {
+    /// The function, the path and the line of each call
+    pub loaded: Vec<(String, String, usize)>,
+    pub literals: HashSet<String>,
+}
+
+fn string_literal(token: &TokenReference) -> Option<String> {
+    match token.token_type() {
+        TokenType::StringLiteral { literal, .. } => Some(literal.to_string()),
+        _ => None,
+    }
+}
+
+impl Visitor for CollectAssetLiterals {
+    fn visit_function_call(&mut self, function_call: &FunctionCall) {
+        let Prefix::Name(name) = function_call.prefix() else {
+            return;
+        };
+        let mut function = name.token().to_string();
+        for suffix in function_call.suffixes() {
+            match suffix {
+                Suffix::Index(Index::Dot { name, .. }) => {
+                    function.push('.');
+                    function.push_str(&name.token().to_string());
+                }
+                Suffix::Call(Call::AnonymousCall(args)) => {
+                    let token = match args {
+                        FunctionArgs::Parentheses { arguments, .. } => {
+                            match arguments.iter().next() {
+                                Some(Expression::String(token)) => token,
+                                _ => return,
+                            }
+                        }
+                        FunctionArgs::String(token) => token,
+                        _ => return,
+                    };
+                    let Some(path) = string_literal(token) else {
+                        return;
+                    };
+                    self.literals.insert(path.clone());
+                    // Shaders can also be given as code
+                    if function.ends_with("newShader") && path.contains(char::is_whitespace) {
+                        return;
+                    }
+                    if ASSET_LOADERS.contains(&function.as_str())
+                        || FILESYSTEM_READERS.contains(&function.as_str())
+                    {
+                        self.loaded
+                            .push((function, path, token.token().start_position().line()));
+                    }
+                    return;
+                }
+                _ => return,
+            }
+        }
+    }
+
+    fn visit_expression(&mut self, expression: &Expression) {
+        if let Expression::String(token) = expression
+            && let Some(literal) = string_literal(token)
+        {
+            self.literals.insert(literal);
+        }
+    }
+}
+
 pub fn clean_polyfill() {
     let polyfill_output = dirs::cache_dir()
         .expect("Unable to find Cache directory")
@@ -176,31 +280,13 @@ impl From<PolyfillCache> for InjectPolyfill {
     }
 }
 
-// This is heavily customized to suffice the needs of kaledis in regards of performance
-// The reason we transformed this to a sync process
-// is to support multi threading
-fn private_process(
+/// The modifiers every transpiled file goes through
+fn transpiling_modifiers(
     manifest: &Manifest,
-    input: &PathBuf,
-    output: &PathBuf,
-    bundle: bool,
     paths: Option<&Paths>,
     aliases: &[(String, String)],
-    used_modules: Option<Arc<Mutex<IndexSet<Modules>>>>,
-    additional_modifiers: Option<Vec<Modifier>>,
-    polyfill: Option<InjectPolyfill>,
-) -> color_eyre::Result<Vec<PathBuf>> {
-    let resources = Resources::from_file_system();
-    let tpm = if output.is_dir() {
-        &temp_dir().join("result")
-    } else {
-        output
-    };
-
+) -> color_eyre::Result<Vec<Modifier>> {
     let mut modifiers = Vec::new();
-    if let Some(mut new_modifiers) = additional_modifiers {
-        modifiers.append(&mut new_modifiers);
-    }
 
     // Love specific rules
     if let Some(paths) = paths {
@@ -233,39 +319,117 @@ fn private_process(
             modifiers.push(Modifier::from_str(name)?);
         }
     }
+    Ok(modifiers)
+}
 
-    let (rules, mut fullmoon_visitors) = modifiers.into_iter().fold(
-        (Vec::new(), Vec::new()),
-        |(mut rules, mut fullmoon_visitors), modifier| {
-            match modifier {
-                Modifier::DarkluaRule(darklua_rule) => rules.push(darklua_rule),
-                Modifier::FullMoonVisitor(fullmoon_visitor) => {
-                    fullmoon_visitors.push(fullmoon_visitor);
-                }
-            }
-            (rules, fullmoon_visitors)
-        },
-    );
-
-    let mut options = Options::new(input).with_configuration({
-        let mut config = Configuration::empty();
-        if bundle {
-            config = config.with_bundle_configuration(BundleConfiguration::new(
-                BundleRequireMode::Luau(Default::default()),
-            ));
+/// The sources of a folder with where darklua writes them, or None when nothing is ignored
+fn kept_sources(
+    input: &Path,
+    output: &Path,
+    ignore: &Gitignore,
+) -> Option<Vec<(PathBuf, PathBuf)>> {
+    let mut ignored = false;
+    let mut sources = Vec::new();
+    for entry in WalkDir::new(input).into_iter().filter_map(Result::ok) {
+        let path = entry.path();
+        if !entry.file_type().is_file()
+            || !matches!(
+                path.extension().and_then(OsStr::to_str),
+                Some("lua") | Some("luau")
+            )
+        {
+            continue;
         }
-        config = config.with_generator(GeneratorParameters::RetainLines);
+        if is_ignored(ignore, path) {
+            ignored = true;
+            continue;
+        }
+        let relative = path.strip_prefix(input).ok()?;
+        sources.push((path.to_path_buf(), output.join(relative)));
+    }
+    ignored.then_some(sources)
+}
 
-        rules
-            .into_iter()
-            .fold(config, |config, rule| config.with_rule(rule))
-    });
+// This is heavily customized to suffice the needs of kaledis in regards of performance
+// The reason we transformed this to a sync process
+// is to support multi threading
+fn private_process(
+    manifest: &Manifest,
+    input: &PathBuf,
+    output: &PathBuf,
+    bundle: bool,
+    paths: Option<&Paths>,
+    aliases: &[(String, String)],
+    used_modules: Option<Arc<Mutex<IndexSet<Modules>>>>,
+    additional_modifiers: Option<Vec<Modifier>>,
+    polyfill: Option<InjectPolyfill>,
+) -> color_eyre::Result<Vec<PathBuf>> {
+    let resources = Resources::from_file_system();
+    let tpm = if output.is_dir() {
+        &temp_dir().join("result")
+    } else {
+        output
+    };
+    // Outputs of earlier runs would be picked up as created files
+    if output.is_dir() && tpm.exists() {
+        fs_err::remove_dir_all(tpm)?;
+    }
+
+    // Files left out by .kaledisignore never reach darklua, so when there are some
+    // the other files are processed one by one
+    let jobs = paths
+        .filter(|_| input.is_dir() && output.is_dir())
+        .and_then(|paths| kept_sources(input, tpm, &paths.ignore))
+        .unwrap_or_else(|| vec![(input.into(), tpm.clone())]);
+
+    let mut additional_modifiers = additional_modifiers;
+    let mut fullmoon_visitors = Vec::new();
+    let mut success_count = 0;
+    let mut errors = Vec::new();
+    for (source, destination) in jobs {
+        let mut modifiers = Vec::new();
+        // Only single runs are given extra modifiers, the polyfill has no ignored files
+        if let Some(mut new_modifiers) = additional_modifiers.take() {
+            modifiers.append(&mut new_modifiers);
+        }
+        modifiers.append(&mut transpiling_modifiers(manifest, paths, aliases)?);
+
+        let (rules, visitors) = modifiers.into_iter().fold(
+            (Vec::new(), Vec::new()),
+            |(mut rules, mut fullmoon_visitors), modifier| {
+                match modifier {
+                    Modifier::DarkluaRule(darklua_rule) => rules.push(darklua_rule),
+                    Modifier::FullMoonVisitor(fullmoon_visitor) => {
+                        fullmoon_visitors.push(fullmoon_visitor);
+                    }
+                }
+                (rules, fullmoon_visitors)
+            },
+        );
+        if fullmoon_visitors.is_empty() {
+            fullmoon_visitors = visitors;
+        }
 
-    options = options.with_output(tpm);
-    let result = darklua_core::process(&resources, options).map_err(|e| eyre!(e))?;
+        let options = Options::new(source)
+            .with_configuration({
+                let mut config = Configuration::empty();
+                if bundle {
+                    config = config.with_bundle_configuration(BundleConfiguration::new(
+                        BundleRequireMode::Luau(Default::default()),
+                    ));
+                }
+                config = config.with_generator(GeneratorParameters::RetainLines);
+
+                rules
+                    .into_iter()
+                    .fold(config, |config, rule| config.with_rule(rule))
+            })
+            .with_output(destination);
+        let result = darklua_core::process(&resources, options).map_err(|e| eyre!(e))?;
+        success_count += result.success_count();
+        errors.extend(result.collect_errors().iter().map(ToString::to_string));
+    }
 
-    let success_count = result.success_count();
-    let errors = result.collect_errors();
     let error_count = errors.len();
     if error_count > 0 {
         eprintln!(
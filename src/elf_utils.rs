@@ -0,0 +1,225 @@
+use color_eyre::eyre::{ContextCompat, eyre};
+
+// Minimal ELF reader, just enough to find and patch the sections
+// the AppImage runtime reserves (.upd_info, .sha256_sig and .sig_key)
+
+#[derive(Debug, Clone)]
+pub struct ElfSection {
+    pub name: String,
+    pub offset: u64,
+    pub size: u64,
+}
+
+struct ElfHeader {
+    is_64: bool,
+    section_offset: u64,
+    section_entry_size: u64,
+    section_count: u64,
+    names_index: u64,
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Option<u64> {
+    Some(u16::from_le_bytes(data.get(offset..offset + 2)?.try_into().ok()?) as u64)
+}
+fn read_u32(data: &[u8], offset: usize) -> Option<u64> {
+    Some(u32::from_le_bytes(data.get(offset..offset + 4)?.try_into().ok()?) as u64)
+}
+fn read_u64(data: &[u8], offset: usize) -> Option<u64> {
+    Some(u64::from_le_bytes(
+        data.get(offset..offset + 8)?.try_into().ok()?,
+    ))
+}
+
+fn parse_header(data: &[u8]) -> Option<ElfHeader> {
+    match data.get(4)? {
+        1 => Some(ElfHeader {
+            is_64: false,
+            section_offset: read_u32(data, 0x20)?,
+            section_entry_size: read_u16(data, 0x2E)?,
+            section_count: read_u16(data, 0x30)?,
+            names_index: read_u16(data, 0x32)?,
+        }),
+        2 => Some(ElfHeader {
+            is_64: true,
+            section_offset: read_u64(data, 0x28)?,
+            section_entry_size: read_u16(data, 0x3A)?,
+            section_count: read_u16(data, 0x3C)?,
+            names_index: read_u16(data, 0x3E)?,
+        }),
+        _ => None,
+    }
+}
+
+fn read_header(data: &[u8]) -> color_eyre::Result<ElfHeader> {
+    if data.get(0..4) != Some(b"\x7fELF") {
+        return Err(eyre!("Not an ELF file"));
+    }
+    // Every AppImage runtime love ships is little endian
+    if data.get(5) != Some(&1) {
+        return Err(eyre!("Only little endian ELF files are supported"));
+    }
+    parse_header(data).wrap_err("Invalid ELF header")
+}
+
+/// Size of the ELF file described by the header, the AppImage runtime uses
+/// this same calculation to find where the squashfs starts.
+pub fn elf_size(data: &[u8]) -> color_eyre::Result<u64> {
+    let header = read_header(data)?;
+    Ok(header.section_offset + header.section_entry_size * header.section_count)
+}
+
+pub fn sections(data: &[u8]) -> color_eyre::Result<Vec<ElfSection>> {
+    let header = read_header(data)?;
+
+    let mut raw = vec![];
+    for index in 0..header.section_count {
+        let start = (header.section_offset + index * header.section_entry_size) as usize;
+        let entry = if header.is_64 {
+            (
+                read_u32(data, start),
+                read_u64(data, start + 24),
+                read_u64(data, start + 32),
+            )
+        } else {
+            (
+                read_u32(data, start),
+                read_u32(data, start + 16),
+                read_u32(data, start + 20),
+            )
+        };
+        let (Some(name), Some(offset), Some(size)) = entry else {
+            return Err(eyre!("Section header {} is out of bounds", index));
+        };
+        raw.push((name, offset, size));
+    }
+
+    let (_, names_offset, names_size) = *raw
+        .get(header.names_index as usize)
+        .wrap_err("Missing section names table")?;
+    let names = data
+        .get(names_offset as usize..(names_offset + names_size) as usize)
+        .wrap_err("Section names table is out of bounds")?;
+
+    Ok(raw
+        .into_iter()
+        .map(|(name, offset, size)| {
+            let name = names
+                .get(name as usize..)
+                .and_then(|x| x.split(|c| *c == 0).next())
+                .map(|x| String::from_utf8_lossy(x).to_string())
+                .unwrap_or_default();
+            ElfSection { name, offset, size }
+        })
+        .collect())
+}
+
+pub fn find_section(data: &[u8], name: &str) -> color_eyre::Result<ElfSection> {
+    sections(data)?
+        .into_iter()
+        .find(|x| x.name == name)
+        .wrap_err_with(|| format!("The runtime has no {} section", name))
+}
+
+/// Overwrites the contents of a section, the remaining space is zeroed
+pub fn write_section(data: &mut [u8], name: &str, contents: &[u8]) -> color_eyre::Result<()> {
+    let section = find_section(data, name)?;
+    if contents.len() as u64 > section.size {
+        return Err(eyre!(
+            "Contents don't fit in the {} section ({} > {} bytes)",
+            name,
+            contents.len(),
+            section.size
+        ));
+    }
+    let start = section.offset as usize;
+    let area = data
+        .get_mut(start..start + section.size as usize)
+        .wrap_err("Section is out of bounds")?;
+    area.fill(0);
+    area[..contents.len()].copy_from_slice(contents);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const UPD_INFO: usize = 64;
+    const NAMES: usize = 80;
+    const HEADERS: usize = 104;
+
+    // 64 bit ELF with a 16 byte .upd_info section, laid out as
+    // header, .upd_info, .shstrtab and then the section headers
+    fn elf() -> Vec<u8> {
+        let names = b"\0.upd_info\0.shstrtab\0";
+        let mut data = vec![0; HEADERS + 3 * 64];
+        data[..4].copy_from_slice(b"\x7fELF");
+        data[4] = 2;
+        data[5] = 1;
+        data[0x28..0x30].copy_from_slice(&(HEADERS as u64).to_le_bytes());
+        data[0x3A..0x3C].copy_from_slice(&64u16.to_le_bytes());
+        data[0x3C..0x3E].copy_from_slice(&3u16.to_le_bytes());
+        data[0x3E..0x40].copy_from_slice(&2u16.to_le_bytes());
+        data[UPD_INFO..UPD_INFO + 16].fill(0xAA);
+        data[NAMES..NAMES + names.len()].copy_from_slice(names);
+
+        let sections = [(1u32, UPD_INFO, 16usize), (11, NAMES, names.len())];
+        for (index, (name, offset, size)) in sections.into_iter().enumerate() {
+            let start = HEADERS + (index + 1) * 64;
+            data[start..start + 4].copy_from_slice(&name.to_le_bytes());
+            data[start + 24..start + 32].copy_from_slice(&(offset as u64).to_le_bytes());
+            data[start + 32..start + 40].copy_from_slice(&(size as u64).to_le_bytes());
+        }
+        data
+    }
+
+    #[test]
+    fn reads_the_sections() {
+        let data = elf();
+        assert_eq!(elf_size(&data).unwrap(), data.len() as u64);
+        let names: Vec<String> = sections(&data)
+            .unwrap()
+            .into_iter()
+            .map(|x| x.name)
+            .collect();
+        assert_eq!(names, ["", ".upd_info", ".shstrtab"]);
+        let section = find_section(&data, ".upd_info").unwrap();
+        assert_eq!((section.offset, section.size), (UPD_INFO as u64, 16));
+    }
+
+    #[test]
+    fn writes_and_zeroes_the_section() {
+        let mut data = elf();
+        let original = data.clone();
+        write_section(&mut data, ".upd_info", b"zsync|game").unwrap();
+
+        assert_eq!(&data[UPD_INFO..UPD_INFO + 10], b"zsync|game");
+        assert!(data[UPD_INFO + 10..UPD_INFO + 16].iter().all(|x| *x == 0));
+        assert_eq!(data[..UPD_INFO], original[..UPD_INFO]);
+        assert_eq!(data[UPD_INFO + 16..], original[UPD_INFO + 16..]);
+
+        write_section(&mut data, ".upd_info", &[]).unwrap();
+        assert!(data[UPD_INFO..UPD_INFO + 16].iter().all(|x| *x == 0));
+    }
+
+    #[test]
+    fn rejects_contents_that_do_not_fit() {
+        let mut data = elf();
+        let original = data.clone();
+        let error = write_section(&mut data, ".upd_info", &[1; 17]).unwrap_err();
+        assert!(error.to_string().contains("17 > 16"));
+        assert_eq!(data, original);
+        assert!(write_section(&mut data, ".sig_key", b"key").is_err());
+    }
+
+    #[test]
+    fn rejects_files_that_are_not_elf() {
+        assert!(sections(b"MZ not an elf").is_err());
+        let mut data = elf();
+        data[5] = 2;
+        assert!(elf_size(&data).is_err());
+        data.truncate(HEADERS + 64);
+        data[5] = 1;
+        assert!(sections(&data).is_err());
+    }
+}
@@ -1,45 +1,163 @@
 use clap::ValueEnum;
-use color_eyre::eyre::Context;
+use color_eyre::{
+    Section,
+    eyre::{Context, ContextCompat, eyre},
+};
 use dirs::home_dir;
-use fs_err::tokio::{self as fs, File};
+use fs_err::tokio as fs;
 use reqwest::Client;
-use serde::Deserialize;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
 use sha2::Digest;
 use std::{
+    collections::BTreeMap,
     io::Cursor,
     path::{Path, PathBuf},
+    sync::atomic::{AtomicBool, Ordering},
 };
 use strum_macros::AsRefStr;
-use tokio::io::AsyncWriteExt;
 use zip::ZipArchive;
 
-static APKTOOL_LOCATION: &str =
-    "https://github.com/iBotPeaches/Apktool/releases/download/v2.12.1/apktool_2.12.1.jar";
+static GITHUB_URL: &str = "https://github.com";
+static GITHUB_API_URL: &str = "https://api.github.com";
+static NPM_URL: &str = "https://registry.npmjs.org";
+// https://github.com/Davidobot/love.js, runs 11.x games
+static LOVEJS_LOCATION: &str = "love.js/-/love.js-11.4.1.tgz";
+// Relative to GITHUB_URL, so everything can be downloaded from a mirror
+static APKTOOL_LOCATION: &str = "iBotPeaches/Apktool/releases/download/v2.12.1/apktool_2.12.1.jar";
 // Idk but, handling external binaries is something i want to do safely and sparingly
 static APKTOOL_HASH: &[u8; 32] =
     &hex_literal::hex!("66cf4524a4a45a7f56567d08b2c9b6ec237bcdd78cee69fd4a59c8a0243aeafa");
+// The jars without a pinned hash are checked against the digest github lists for them
+static UBER_APK_SIGNER_LOCATION: &str =
+    "patrickfav/uber-apk-signer/releases/download/v1.3.0/uber-apk-signer-1.3.0.jar";
+static BUNDLETOOL_LOCATION: &str =
+    "google/bundletool/releases/download/1.18.1/bundletool-all-1.18.1.jar";
+
+pub static DEBUG_KEY_ALIAS: &str = "androiddebugkey";
+pub static DEBUG_KEY_PASSWORD: &str = "android";
 
-#[cfg(windows)]
+#[cfg(all(windows, target_arch = "x86"))]
+pub static CURRENT_PLATFORM: Target = Target::WindowsX86;
+// Windows on arm runs the x64 build through emulation
+#[cfg(all(windows, not(target_arch = "x86")))]
 pub static CURRENT_PLATFORM: Target = Target::Windows;
-#[cfg(target_os = "linux")]
+#[cfg(all(target_os = "linux", target_arch = "aarch64"))]
+pub static CURRENT_PLATFORM: Target = Target::LinuxAarch64;
+#[cfg(all(target_os = "linux", not(target_arch = "aarch64")))]
 pub static CURRENT_PLATFORM: Target = Target::LinuxAppImage;
 #[cfg(target_os = "macos")]
 pub static CURRENT_PLATFORM: Target = Target::Macos;
 
+/// Where a downloaded love build came from
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Artifact {
+    pub version: String,
+    pub target: String,
+    pub url: Option<String>,
+    pub sha256: Option<String>,
+    // Only for builds downloaded from the love CI
+    pub run_id: Option<u64>,
+}
+
+pub struct Installed {
+    pub version: String,
+    pub target: Target,
+    pub size: u64,
+    pub artifact: Option<Artifact>,
+}
+
 pub struct HomeManager {
     pub path: PathBuf,
     pub client: Client,
+    // Overridable with KALEDIS_GITHUB_URL, e.g. to test against a local server
+    pub github_url: String,
+    // Base urls replacing "https://github.com/<repo>/releases/download" of a repository
+    pub mirrors: BTreeMap<String, String>,
+    pub offline: bool,
+    // Overridable with KALEDIS_GITHUB_API, e.g. to test against a mock of the api
+    pub api_url: String,
+    pub github_token: Option<String>,
+    // Overridable with KALEDIS_NPM_URL, love.js is distributed through npm
+    pub npm_url: String,
+}
+
+/// Set by the --offline flag
+pub static OFFLINE: AtomicBool = AtomicBool::new(false);
+
+/// ~/.kaledis/config.toml
+#[derive(Deserialize, Debug, Default)]
+struct HomeConfig {
+    github: Option<String>,
+    github_api: Option<String>,
+    github_token: Option<String>,
+    npm: Option<String>,
+    #[serde(default)]
+    mirrors: BTreeMap<String, String>,
+    #[serde(default)]
+    offline: bool,
 }
 
-#[derive(AsRefStr, Debug, PartialEq, Eq, Clone, ValueEnum)]
+// The names in kaledis.toml are the ones of the command line
+#[derive(AsRefStr, Debug, PartialEq, Eq, Clone, ValueEnum, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "kebab-case")]
 pub enum Target {
+    #[value(alias = "windows-x64")]
+    #[serde(alias = "windows-x64")]
     Windows,
+    #[value(name = "windows-x86")]
+    WindowsX86,
+    #[value(alias = "linux-x86_64")]
+    #[serde(alias = "linux-x86_64")]
     LinuxAppImage,
+    #[value(name = "linux-aarch64")]
+    LinuxAarch64,
     Android,
+    AndroidBundle,
+    // The love builds for macos are universal, so they run on both intel and apple silicon
     Macos,
+    // love.js, it doesn't depend on the love version of the project
+    Web,
     LoveFile,
 }
 
+impl Target {
+    pub fn is_windows(&self) -> bool {
+        matches!(self, Target::Windows | Target::WindowsX86)
+    }
+
+    /// The name of the target in the command line and kaledis.toml
+    pub fn name(&self) -> String {
+        self.to_possible_value()
+            .map(|x| x.get_name().to_string())
+            .unwrap_or_default()
+    }
+
+    pub fn is_android(&self) -> bool {
+        matches!(self, Target::Android | Target::AndroidBundle)
+    }
+
+    /// Whether listing this target also means another one, the main target of a platform
+    /// stands for its variants in assets.rules
+    pub fn covers(&self, other: &Target) -> bool {
+        self == other
+            || matches!(
+                (self, other),
+                (Target::Android, Target::AndroidBundle)
+                    | (Target::Windows, Target::WindowsX86)
+                    | (Target::LinuxAppImage, Target::LinuxAarch64)
+            )
+    }
+
+    /// The target whose love build is downloaded, the bundle is made from the android apk
+    pub fn download_target(self) -> Target {
+        match self {
+            Target::AndroidBundle => Target::Android,
+            target => target,
+        }
+    }
+}
+
 impl HomeManager {
     pub async fn new() -> color_eyre::Result<Self> {
         let kaledis_dir = home_dir()
@@ -67,16 +185,358 @@ impl HomeManager {
             // todo: log error with debug flag
         };
 
+        let config: HomeConfig = match fs::read_to_string(kaledis_dir.join("config.toml")).await {
+            Ok(text) => toml::from_str(&text).context("Reading ~/.kaledis/config.toml")?,
+            Err(_) => HomeConfig::default(),
+        };
+        let env = |name: &str| std::env::var(name).ok().filter(|x| !x.is_empty());
+
+        let mut mirrors = config.mirrors;
+        for (name, repo) in [
+            ("KALEDIS_LOVE_MIRROR", "love2d/love"),
+            ("KALEDIS_JAVA_MIRROR", "adoptium/temurin11-binaries"),
+            ("KALEDIS_APKTOOL_MIRROR", "iBotPeaches/Apktool"),
+        ] {
+            if let Some(mirror) = env(name) {
+                mirrors.insert(repo.to_string(), mirror);
+            }
+        }
+
         Ok(Self {
             path: kaledis_dir,
             client: Client::new(),
+            github_url: env("KALEDIS_GITHUB_URL")
+                .or(config.github)
+                .unwrap_or(GITHUB_URL.to_string())
+                .trim_end_matches('/')
+                .to_string(),
+            mirrors,
+            api_url: env("KALEDIS_GITHUB_API")
+                .or(config.github_api)
+                .unwrap_or(GITHUB_API_URL.to_string())
+                .trim_end_matches('/')
+                .to_string(),
+            github_token: env("KALEDIS_GITHUB_TOKEN")
+                .or(env("GITHUB_TOKEN"))
+                .or(env("GH_TOKEN"))
+                .or(config.github_token),
+            npm_url: env("KALEDIS_NPM_URL")
+                .or(config.npm)
+                .unwrap_or(NPM_URL.to_string())
+                .trim_end_matches('/')
+                .to_string(),
+            offline: OFFLINE.load(Ordering::Relaxed)
+                || config.offline
+                || env("KALEDIS_OFFLINE").is_some_and(|x| x != "0" && x != "false"),
         })
     }
 
+    /// Turns a github path like "love2d/love/releases/download/11.5/file" into an url,
+    /// mirrors of a repository replace everything up to "releases/download"
+    fn github(&self, path: &str) -> String {
+        let mut parts = path.splitn(5, '/');
+        let repo = format!(
+            "{}/{}",
+            parts.next().unwrap_or_default(),
+            parts.next().unwrap_or_default()
+        );
+        match (self.mirrors.get(&repo), parts.nth(2)) {
+            (Some(mirror), Some(file)) => format!("{}/{}", mirror.trim_end_matches('/'), file),
+            _ => format!("{}/{}", self.github_url, path),
+        }
+    }
+
+    /// A new empty folder inside ~/.kaledis, so it can be renamed into place atomically
+    async fn temp_dir(&self) -> color_eyre::Result<PathBuf> {
+        let nanos = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos();
+        let dir = self
+            .path
+            .join("tmp")
+            .join(format!("{}-{}", std::process::id(), nanos));
+        fs::create_dir_all(&dir)
+            .await
+            .context("Creating temporary folder")?;
+        Ok(dir)
+    }
+
+    /// Checksums from ~/.kaledis/checksums.txt, in the same format `sha256sum` outputs
+    async fn known_checksum(&self, url: &str) -> Option<[u8; 32]> {
+        let name = url.rsplit('/').next()?;
+        let checksums = fs::read_to_string(self.path.join("checksums.txt"))
+            .await
+            .ok()?;
+        checksums.lines().find_map(|line| {
+            let (hash, file) = line.split_once(char::is_whitespace)?;
+            if file.trim().trim_start_matches('*') != name {
+                return None;
+            }
+            hex::decode(hash).ok()?.try_into().ok()
+        })
+    }
+
+    /// The sha256 the publisher lists for a github release asset, from the digest github
+    /// keeps of it or a `<file>.sha256.txt` next to it like adoptium publishes
+    async fn published_checksum(&self, location: &str) -> Option<[u8; 32]> {
+        if self.offline {
+            return None;
+        }
+        let mut parts = location.splitn(6, '/');
+        let (owner, repo) = (parts.next()?, parts.next()?);
+        let (tag, name) = (parts.nth(2)?, parts.next()?);
+
+        let url = format!(
+            "{}/repos/{}/{}/releases/tags/{}",
+            self.api_url, owner, repo, tag
+        );
+        let mut request = self
+            .client
+            .get(&url)
+            .header("Accept", "application/vnd.github+json")
+            .header("User-Agent", "kaledis");
+        if let Some(token) = &self.github_token {
+            request = request.bearer_auth(token);
+        }
+        let release = match request.send().await.and_then(|x| x.error_for_status()) {
+            Ok(response) => response.json::<Release>().await.ok(),
+            Err(_) => None,
+        };
+        let digest = release.and_then(|release| {
+            release
+                .assets
+                .into_iter()
+                .find(|x| x.name == name)?
+                .digest
+                .as_deref()
+                .and_then(parse_digest)
+        });
+        if digest.is_some() {
+            return digest;
+        }
+
+        let text = self
+            .client
+            .get(format!("{}.sha256.txt", self.github(location)))
+            .send()
+            .await
+            .and_then(|x| x.error_for_status())
+            .ok()?
+            .text()
+            .await
+            .ok()?;
+        hex::decode(text.split_whitespace().next()?)
+            .ok()?
+            .try_into()
+            .ok()
+    }
+
+    /// Downloads a file and checks it against `expected` or the checksums file
+    async fn download(
+        &self,
+        url: &str,
+        expected: Option<&[u8; 32]>,
+    ) -> color_eyre::Result<tokio_util::bytes::Bytes> {
+        self.check_online(url)?;
+        let bytes = self
+            .client
+            .get(url)
+            .send()
+            .await
+            .and_then(|x| x.error_for_status())
+            .with_context(|| format!("Downloading {}", url))?
+            .bytes()
+            .await
+            .with_context(|| format!("Downloading {}", url))?;
+
+        self.verify(url, &bytes, expected).await?;
+        Ok(bytes)
+    }
+
+    fn check_online(&self, what: &str) -> color_eyre::Result<()> {
+        if self.offline {
+            return Err(eyre!("{} is not in the cache and kaledis is offline", what))
+                .suggestion("Seed the cache with `kaledis toolchain import <file>` or go online");
+        }
+        Ok(())
+    }
+
+    async fn verify(
+        &self,
+        url: &str,
+        bytes: &[u8],
+        expected: Option<&[u8; 32]>,
+    ) -> color_eyre::Result<()> {
+        let digest: [u8; 32] = sha2::Sha256::digest(bytes).into();
+        match expected.copied().or(self.known_checksum(url).await) {
+            Some(expected) if expected != digest => Err(eyre!(
+                "Checksum mismatch for {}\nExpected {}\nGot      {}",
+                url,
+                hex::encode(expected),
+                hex::encode(digest)
+            ))
+            .suggestion("The file is corrupted or was changed, try again later"),
+            Some(_) => Ok(()),
+            None => {
+                tracing::warn!(
+                    "No known checksum for {}, its sha256 is {}",
+                    url,
+                    hex::encode(digest)
+                );
+                Ok(())
+            }
+        }
+    }
+
+    /// Writes through a temporary file, so the output never exists half written
+    async fn install_file(&self, output: &Path, contents: &[u8]) -> color_eyre::Result<()> {
+        let temp = self.temp_dir().await?;
+        let file = temp.join("download");
+        fs::write(&file, contents).await?;
+        if let Some(parent) = output.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+        fs::rename(&file, output)
+            .await
+            .with_context(|| format!("Installing {}", output.display()))?;
+        fs::remove_dir(temp).await?;
+        Ok(())
+    }
+
+    async fn install_jar(
+        &self,
+        location: &str,
+        output: &Path,
+        expected: Option<&[u8; 32]>,
+    ) -> color_eyre::Result<()> {
+        if output.exists() {
+            return Ok(());
+        }
+        let expected = match expected {
+            Some(expected) => Some(*expected),
+            None => self.published_checksum(location).await,
+        };
+        let bytes = self
+            .download(&self.github(location), expected.as_ref())
+            .await?;
+        self.install_file(output, &bytes).await
+    }
+
+    /// Seeds the cache with a pre-downloaded release of LÖVE, java or one of the jars,
+    /// returns a description of what was installed
+    pub async fn import(
+        &self,
+        file: &Path,
+        version: Option<String>,
+        target: Option<Target>,
+    ) -> color_eyre::Result<String> {
+        let name = file
+            .file_name()
+            .and_then(|x| x.to_str())
+            .wrap_err("Invalid file name")?
+            .to_string();
+        let bytes = tokio_util::bytes::Bytes::from(fs::read(file).await?);
+
+        let jars = [
+            (
+                APKTOOL_LOCATION,
+                self.get_apktool_path(),
+                Some(APKTOOL_HASH),
+            ),
+            (UBER_APK_SIGNER_LOCATION, self.get_signer_path(), None),
+            (BUNDLETOOL_LOCATION, self.get_bundletool_path(), None),
+        ];
+        for (location, output, expected) in jars {
+            if location.ends_with(&format!("/{}", name)) {
+                let expected = match expected {
+                    Some(expected) => Some(*expected),
+                    None => self.published_checksum(location).await,
+                };
+                self.verify(&self.github(location), &bytes, expected.as_ref())
+                    .await?;
+                self.install_file(&output, &bytes).await?;
+                return Ok(name);
+            }
+        }
+
+        if LOVEJS_LOCATION.ends_with(&format!("/{}", name)) {
+            self.verify(&self.npm(), &bytes, None).await?;
+            self.install_lovejs(bytes).await?;
+            return Ok("love.js".to_string());
+        }
+
+        let (java, hash) = Self::java_release();
+        if java.ends_with(&format!("/{}", name)) {
+            let hash = match hash {
+                Some(hash) => Some(hash),
+                None => self.published_checksum(&java).await,
+            };
+            self.verify(&self.github(&java), &bytes, hash.as_ref())
+                .await?;
+            if self.path.join("java").exists() && !self.java_marker().exists() {
+                fs::remove_dir_all(self.path.join("java")).await?;
+            }
+            self.install_java(bytes).await?;
+            return Ok("the java runtime".to_string());
+        }
+
+        // love-11.5-win64.zip and friends
+        let inferred = name.strip_prefix("love-").and_then(|rest| {
+            [
+                Target::Windows,
+                Target::WindowsX86,
+                Target::LinuxAppImage,
+                Target::LinuxAarch64,
+                Target::Macos,
+                Target::Android,
+            ]
+            .into_iter()
+            .find_map(|target| {
+                let suffix = love_file_name("", &target);
+                let suffix = suffix.strip_prefix("love-")?;
+                let version = rest.strip_suffix(suffix)?;
+                Some((version.to_string(), target))
+            })
+        });
+        let (version, target) = match (version, target, inferred) {
+            (Some(version), Some(target), _) => (version, target),
+            (version, target, Some((inferred_version, inferred_target))) => (
+                version.unwrap_or(inferred_version),
+                target.unwrap_or(inferred_target),
+            ),
+            _ => {
+                return Err(eyre!("Can't tell what {} contains", name)).suggestion(
+                    "Pass --version and --target, or keep the name of the release asset",
+                );
+            }
+        };
+        let target = target.download_target();
+        if target == Target::LoveFile || target == Target::Web {
+            return Err(eyre!("{} doesn't use a LÖVE release", target.as_ref()));
+        }
+
+        let location = format!(
+            "love2d/love/releases/download/{}/{}",
+            version,
+            love_file_name(&version, &target)
+        );
+        let url = self.github(&location);
+        let expected = self.published_checksum(&location).await;
+        self.verify(&url, &bytes, expected.as_ref()).await?;
+        self.install_version(&version, target.clone(), bytes, Some(url))
+            .await?;
+        Ok(format!("LÖVE {} for {}", version, target.as_ref()))
+    }
+
     pub async fn get_path(&self, version: &str, platform: Target) -> PathBuf {
+        let platform = platform.download_target();
+        if platform == Target::Web {
+            return self.get_lovejs_path();
+        }
         let pth = self.path.join(version).join(platform.as_ref());
-        if let Target::Windows = platform {
-            return pth.join(format!("love-{}-win64", version));
+        if platform.is_windows() {
+            return pth.join(windows_folder(base_version(version), &platform));
         }
         pth
     }
@@ -92,329 +552,1364 @@ impl HomeManager {
     pub fn get_apktool_path(&self) -> PathBuf {
         self.path.join("java").join("tool.java")
     }
+    pub fn get_keytool_path(&self) -> PathBuf {
+        let mut path = self.get_java_path();
+        #[cfg(windows)]
+        path.set_file_name("keytool.exe");
+        #[cfg(not(windows))]
+        path.set_file_name("keytool");
+        path
+    }
+    pub fn get_signer_path(&self) -> PathBuf {
+        self.path.join("java").join("uber-apk-signer.jar")
+    }
+    pub fn get_debug_keystore_path(&self) -> PathBuf {
+        self.path.join("debug.keystore")
+    }
 
-    pub async fn ensure_apktool(&self) -> color_eyre::Result<()> {
-        let jv = self.path.join("java").join("tool.java");
-        if jv.exists() {
+    pub fn get_lovejs_path(&self) -> PathBuf {
+        self.path.join("lovejs")
+    }
+    pub fn get_bundletool_path(&self) -> PathBuf {
+        self.path.join("java").join("bundletool.jar")
+    }
+    pub fn get_aapt2_path(&self) -> PathBuf {
+        #[cfg(windows)]
+        return self.path.join("java").join("aapt2.exe");
+        #[cfg(not(windows))]
+        return self.path.join("java").join("aapt2");
+    }
+    /// Where apktool installs the android framework (1.apk) used to link resources
+    pub fn get_framework_path(&self) -> PathBuf {
+        self.path.join("java").join("framework")
+    }
+
+    pub async fn ensure_signer(&self) -> color_eyre::Result<()> {
+        self.install_jar(UBER_APK_SIGNER_LOCATION, &self.get_signer_path(), None)
+            .await
+    }
+
+    pub async fn ensure_bundletool(&self) -> color_eyre::Result<()> {
+        self.install_jar(BUNDLETOOL_LOCATION, &self.get_bundletool_path(), None)
+            .await
+    }
+
+    /// Extracts the aapt2 binary apktool ships inside its jar
+    pub async fn ensure_aapt2(&self) -> color_eyre::Result<()> {
+        self.ensure_apktool().await?;
+        let output = self.get_aapt2_path();
+        if output.exists() {
             return Ok(());
         }
 
-        let response = self.client.get(APKTOOL_LOCATION).send().await.unwrap();
-        let bytes = response.bytes().await.unwrap();
+        #[cfg(windows)]
+        let candidates = [
+            "prebuilt/windows/aapt2_64.exe",
+            "prebuilt/windows/aapt2.exe",
+        ];
+        #[cfg(target_os = "linux")]
+        let candidates = ["prebuilt/linux/aapt2_64", "prebuilt/linux/aapt2"];
+        #[cfg(target_os = "macos")]
+        let candidates = ["prebuilt/macosx/aapt2_64", "prebuilt/macosx/aapt2"];
 
-        let result = sha2::Sha256::digest(&bytes);
+        let jar = fs::read(self.get_apktool_path()).await?;
+        let mut archive = ZipArchive::new(Cursor::new(jar))?;
+        let name = candidates
+            .into_iter()
+            .find(|x| archive.index_for_name(x).is_some())
+            .context("The apktool jar has no aapt2 for this platform")?;
 
-        assert_eq!(result[..], *APKTOOL_HASH);
+        let mut binary = vec![];
+        std::io::copy(&mut archive.by_name(name)?, &mut binary)?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let temp = self.temp_dir().await?.join("aapt2");
+            fs::write(&temp, binary).await?;
+            fs::set_permissions(&temp, std::fs::Permissions::from_mode(0o755)).await?;
+            fs::rename(&temp, &output)
+                .await
+                .context("Installing aapt2")?;
+        }
+        #[cfg(not(unix))]
+        self.install_file(&output, &binary).await?;
 
-        let mut file = File::create(jv)
-            .await
-            .context("Creating apktool java file")?;
-        file.write_all(&bytes)
+        Ok(())
+    }
+
+    /// Creates the same kind of debug keystore the Android SDK uses
+    pub async fn ensure_debug_keystore(&self, force: bool) -> color_eyre::Result<PathBuf> {
+        let keystore = self.get_debug_keystore_path();
+        if keystore.exists() {
+            if !force {
+                return Ok(keystore);
+            }
+            fs::remove_file(&keystore).await?;
+        }
+
+        self.ensure_java().await?;
+        let status = tokio::process::Command::new(self.get_keytool_path())
+            .args([
+                "-genkeypair",
+                "-keystore",
+                &keystore.to_string_lossy(),
+                "-storetype",
+                "JKS",
+                "-alias",
+                DEBUG_KEY_ALIAS,
+                "-storepass",
+                DEBUG_KEY_PASSWORD,
+                "-keypass",
+                DEBUG_KEY_PASSWORD,
+                "-keyalg",
+                "RSA",
+                "-keysize",
+                "2048",
+                "-validity",
+                "10000",
+                "-dname",
+                "CN=Android Debug,O=Android,C=US",
+            ])
+            .status()
             .await
-            .context("Writing apktool java file")?;
+            .context("Failed to spawn keytool")?;
+        if !status.success() {
+            return Err(color_eyre::eyre::eyre!("keytool failed with {}", status));
+        }
 
-        Ok(())
+        Ok(keystore)
+    }
+
+    pub async fn ensure_apktool(&self) -> color_eyre::Result<()> {
+        self.install_jar(
+            APKTOOL_LOCATION,
+            &self.get_apktool_path(),
+            Some(APKTOOL_HASH),
+        )
+        .await
     }
 
     // jdk-11.0.30+7-jre
-    pub async fn ensure_java(&self) -> color_eyre::Result<()> {
-        #[cfg(windows)]
-        let url = "https://github.com/adoptium/temurin11-binaries/releases/download/jdk-11.0.30%2B7/OpenJDK11U-jre_x64_windows_hotspot_11.0.30_7.zip";
-        #[cfg(windows)]
-        let hash =
-            hex_literal::hex!("db7fe2f05857074e73ef2bb10bfb95556ad110cf1ba0c82d101f93b3a93862ff");
+    /// The java runtime of the host, the ones without a pinned hash are checked against
+    /// the .sha256.txt adoptium publishes next to them
+    fn java_release() -> (String, Option<[u8; 32]>) {
+        let base = "adoptium/temurin11-binaries/releases/download/jdk-11.0.30%2B7";
+        #[cfg(all(windows, target_arch = "x86"))]
+        let release = ("OpenJDK11U-jre_x86-32_windows_hotspot_11.0.30_7.zip", None);
+        // There is no java 11 for windows on arm, the x64 one runs through emulation
+        #[cfg(all(windows, not(target_arch = "x86")))]
+        let release = (
+            "OpenJDK11U-jre_x64_windows_hotspot_11.0.30_7.zip",
+            Some(hex_literal::hex!(
+                "db7fe2f05857074e73ef2bb10bfb95556ad110cf1ba0c82d101f93b3a93862ff"
+            )),
+        );
 
-        #[cfg(target_os = "linux")]
-        let url = "https://github.com/adoptium/temurin11-binaries/releases/download/jdk-11.0.30%2B7/OpenJDK11U-jre_aarch64_linux_hotspot_11.0.30_7.tar.gz";
-        #[cfg(target_os = "linux")]
-        let hash =
-            hex_literal::hex!("9d6a8d3a33c308bbc7332e4c2e2f9a94fbbc56417863496061ef6defef9c5391");
+        #[cfg(all(target_os = "linux", target_arch = "aarch64"))]
+        let release = (
+            "OpenJDK11U-jre_aarch64_linux_hotspot_11.0.30_7.tar.gz",
+            Some(hex_literal::hex!(
+                "9d6a8d3a33c308bbc7332e4c2e2f9a94fbbc56417863496061ef6defef9c5391"
+            )),
+        );
+        #[cfg(all(target_os = "linux", not(target_arch = "aarch64")))]
+        let release = ("OpenJDK11U-jre_x64_linux_hotspot_11.0.30_7.tar.gz", None);
 
-        #[cfg(target_os = "macos")]
-        let url = "https://github.com/adoptium/temurin11-binaries/releases/download/jdk-11.0.30%2B7/OpenJDK11U-jdk_aarch64_mac_hotspot_11.0.30_7.tar.gz";
-        #[cfg(target_os = "macos")]
-        let hash =
-            hex_literal::hex!("d7b52d25d6f7aae2d4d85191d84bc132b80d061006dcd5f76ca79f277c3acb28");
+        #[cfg(all(target_os = "macos", target_arch = "aarch64"))]
+        let release = (
+            "OpenJDK11U-jdk_aarch64_mac_hotspot_11.0.30_7.tar.gz",
+            Some(hex_literal::hex!(
+                "d7b52d25d6f7aae2d4d85191d84bc132b80d061006dcd5f76ca79f277c3acb28"
+            )),
+        );
+        #[cfg(all(target_os = "macos", not(target_arch = "aarch64")))]
+        let release = ("OpenJDK11U-jdk_x64_mac_hotspot_11.0.30_7.tar.gz", None);
 
-        let jv = self.path.join("java");
-        if jv.exists() {
+        (format!("{}/{}", base, release.0), release.1)
+    }
+
+    pub async fn ensure_java(&self) -> color_eyre::Result<()> {
+        if self.java_marker().exists() {
             return Ok(());
         }
 
-        let response = self.client.get(url).send().await.unwrap();
-
-        let bytes = response.bytes().await.unwrap();
-        let result = sha2::Sha256::digest(&bytes);
+        let (location, hash) = Self::java_release();
+        let hash = match hash {
+            Some(hash) => Some(hash),
+            None => self.published_checksum(&location).await,
+        };
         // If this fails, the contents of the java runtime are different from when i got them.
         // So it's better to not run then.
-        assert_eq!(result[..], hash);
-        #[cfg(windows)]
+        let bytes = self
+            .download(&self.github(&location), hash.as_ref())
+            .await?;
+        self.install_java(bytes).await
+    }
+
+    // Written once the runtime is fully extracted, the java folder also holds the jars
+    fn java_marker(&self) -> PathBuf {
+        self.path.join("java").join(".java-complete")
+    }
+
+    async fn install_java(&self, bytes: tokio_util::bytes::Bytes) -> color_eyre::Result<()> {
+        let jv = self.path.join("java");
+        let hash = sha2::Sha256::digest(&bytes);
+        let staging = self.temp_dir().await?;
         {
-            use color_eyre::{Section, eyre::Context};
-            use fs_err::tokio::create_dir_all;
+            let staging = staging.clone();
+            tokio::task::spawn_blocking(move || -> color_eyre::Result<()> {
+                #[cfg(windows)]
+                extract_zip(Cursor::new(bytes), staging).context("Extracting java folder")?;
+                #[cfg(not(windows))]
+                {
+                    let decoder = flate2::bufread::GzDecoder::new(Cursor::new(bytes));
+                    tar::Archive::new(decoder)
+                        .unpack(staging)
+                        .context("Extracting java folder")?;
+                }
+                Ok(())
+            })
+            .await
+            .context("Extracting the java runtime")??;
+        }
 
-            let jv = self.path.join("java");
-            create_dir_all(&jv)
-                .await
-                .context("Creating java dir")
-                .suggestion("Try removing ~/.kaledis/java folder")?;
+        fs::create_dir_all(&jv).await.context("Creating java dir")?;
+        let mut entries = fs::read_dir(&staging).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let destination = jv.join(entry.file_name());
+            if destination.exists() {
+                fs::remove_dir_all(&destination).await?;
+            }
+            fs::rename(entry.path(), destination).await?;
+        }
+        fs::remove_dir(staging).await?;
+        fs::write(self.java_marker(), hex::encode(hash)).await?;
 
+        Ok(())
+    }
+
+    fn npm(&self) -> String {
+        format!("{}/{}", self.npm_url, LOVEJS_LOCATION)
+    }
+
+    /// Downloads love.js, its compat and release (threaded) builds end up in ~/.kaledis/lovejs
+    pub async fn ensure_lovejs(&self) -> color_eyre::Result<()> {
+        // The folder is renamed into place once everything is extracted
+        if self.get_lovejs_path().exists() {
+            return Ok(());
+        }
+        let bytes = self.download(&self.npm(), None).await?;
+        self.install_lovejs(bytes).await
+    }
+
+    async fn install_lovejs(&self, bytes: tokio_util::bytes::Bytes) -> color_eyre::Result<()> {
+        let staging = self.temp_dir().await?;
+        let extracted = staging.join("package");
+        {
+            let extracted = extracted.clone();
             tokio::task::spawn_blocking(move || {
-                extract_zip(Cursor::new(bytes), jv);
+                let decoder = flate2::bufread::GzDecoder::new(Cursor::new(bytes));
+                tar::Archive::new(decoder).unpack(extracted)
             })
+            .await?
+            .context("Extracting love.js")?;
+        }
+
+        let output = staging.join("lovejs");
+        fs::create_dir_all(&output).await?;
+        for build in ["compat", "release"] {
+            // The builds are folders with a love.js and love.wasm, wherever npm put them
+            let folder = walkdir::WalkDir::new(&extracted)
+                .into_iter()
+                .filter_map(Result::ok)
+                .find(|x| x.file_name() == build && x.path().join("love.wasm").is_file())
+                .wrap_err_with(|| format!("The love.js package has no {} build", build))?;
+            fs::rename(folder.path(), output.join(build)).await?;
+        }
+
+        let lovejs = self.get_lovejs_path();
+        if lovejs.exists() {
+            fs::remove_dir_all(&lovejs).await?;
+        }
+        fs::rename(&output, &lovejs)
+            .await
+            .context("Installing love.js")?;
+        fs::remove_dir_all(staging).await?;
+        Ok(())
+    }
+
+    // Kept next to the target folder, so it isn't copied along with the love files
+    fn artifact_path(&self, version: &str, platform: &Target) -> PathBuf {
+        self.path.join(version).join(format!(
+            "{}.toml",
+            platform.clone().download_target().as_ref()
+        ))
+    }
+
+    async fn write_artifact(&self, artifact: &Artifact) -> color_eyre::Result<()> {
+        let folder = self.path.join(&artifact.version);
+        fs::create_dir_all(&folder).await?;
+        fs::write(
+            folder.join(format!("{}.toml", artifact.target)),
+            toml::to_string(artifact)?,
+        )
+        .await
+        .context("Writing artifact info")?;
+        Ok(())
+    }
+
+    /// Where a love build came from, None for builds downloaded by older kaledis versions
+    pub async fn get_artifact(&self, version: &str, platform: &Target) -> Option<Artifact> {
+        let text = fs::read_to_string(self.artifact_path(version, platform))
             .await
-            .context("Extracting the zip")?;
+            .ok()?;
+        toml::from_str(&text).ok()
+    }
+
+    /// Every love build in ~/.kaledis
+    pub async fn installed(&self) -> color_eyre::Result<Vec<Installed>> {
+        let mut installed = vec![];
+        let mut versions = fs::read_dir(&self.path).await?;
+        while let Some(version) = versions.next_entry().await? {
+            if !version.file_type().await?.is_dir() {
+                continue;
+            }
+            let version_name = version.file_name().to_string_lossy().to_string();
+            for target in Target::value_variants() {
+                let path = version.path().join(target.as_ref());
+                if !path.is_dir() {
+                    continue;
+                }
+                installed.push(Installed {
+                    size: dir_size(&path),
+                    artifact: self.get_artifact(&version_name, target).await,
+                    version: version_name.clone(),
+                    target: target.clone(),
+                });
+            }
         }
-        #[cfg(not(windows))]
-        {
-            use flate2::bufread::GzDecoder;
-            use fs_err::tokio::create_dir_all;
+        installed
+            .sort_by(|a, b| (&a.version, a.target.as_ref()).cmp(&(&b.version, b.target.as_ref())));
+        Ok(installed)
+    }
+
+    /// Removes what interrupted downloads left behind
+    pub async fn clean_temp(&self) -> color_eyre::Result<()> {
+        let temp = self.path.join("tmp");
+        if temp.exists() {
+            fs::remove_dir_all(temp).await?;
+        }
+        Ok(())
+    }
 
-            let jv = self.path.join("java");
-            if !jv.exists() {
-                create_dir_all(&jv).await.context("Extracting the zip")?;
+    // One root per line, prune keeps the versions these projects use
+    fn projects_path(&self) -> PathBuf {
+        self.path.join("projects.txt")
+    }
 
-                let decoder = GzDecoder::new(Cursor::new(bytes));
-                let mut archive = tar::Archive::new(decoder);
-                archive.unpack(jv).context("Extracting java folder")?;
+    /// Remembers a project that was built with the cache
+    pub async fn register_project(&self, root: &Path) -> color_eyre::Result<()> {
+        let root = fs::canonicalize(root).await?;
+        let mut projects = self.projects().await;
+        if projects.contains(&root) {
+            return Ok(());
+        }
+        projects.push(root);
+        let text: Vec<String> = projects
+            .iter()
+            .map(|x| x.to_string_lossy().to_string())
+            .collect();
+        fs::write(self.projects_path(), text.join("\n") + "\n")
+            .await
+            .context("Writing the list of projects")?;
+        Ok(())
+    }
+
+    /// The projects built with the cache that still exist
+    pub async fn projects(&self) -> Vec<PathBuf> {
+        let text = fs::read_to_string(self.projects_path())
+            .await
+            .unwrap_or_default();
+        text.lines()
+            .filter(|x| !x.trim().is_empty())
+            .map(PathBuf::from)
+            .filter(|x| x.join("kaledis.toml").is_file())
+            .collect()
+    }
+
+    pub async fn remove(&self, version: &str, platform: &Target) -> color_eyre::Result<()> {
+        let path = self.path.join(version).join(platform.as_ref());
+        if path.exists() {
+            fs::remove_dir_all(&path).await?;
+        }
+        for file in [
+            self.artifact_path(version, platform),
+            self.version_marker(version, platform),
+        ] {
+            if file.exists() {
+                fs::remove_file(file).await?;
             }
         }
+        // Removes the version folder once it has no targets left
+        let folder = self.path.join(version);
+        if fs::read_dir(&folder).await?.next_entry().await?.is_none() {
+            fs::remove_dir(folder).await?;
+        }
         Ok(())
     }
 
     // Has to be like 11.5 | 11.3 etc
-    // version 12 is only available when gh cli is available
-    pub async fn ensure_version(&self, version: &str, platform: Target) {
+    // version 12 comes from the love2d/love CI
+    pub async fn ensure_version(&self, version: &str, platform: Target) -> color_eyre::Result<()> {
+        let platform = platform.download_target();
+        if platform == Target::LoveFile {
+            return Ok(());
+        }
+        if platform == Target::Web {
+            return self.ensure_lovejs().await;
+        }
         let output_version = self.path.join(version).join(platform.as_ref());
 
+        // Installs are staged and renamed into place, then marked as complete. A folder
+        // without the marker was interrupted or comes from an older kaledis, so it is
+        // downloaded again and only replaced once the new one is ready
         if output_version.exists() {
-            return;
+            if self.version_marker(version, &platform).exists() {
+                return Ok(());
+            }
+            if self.offline {
+                tracing::warn!(
+                    "LÖVE {} for {} may be incomplete, it is used as it is since kaledis is offline",
+                    version,
+                    platform.as_ref()
+                );
+                return Ok(());
+            }
         }
 
         if version.starts_with("12") {
-            check_gh_available().expect(
-                "Love2D version 12 requires the GitHub CLI (gh).\n\
-                 Please install it from: https://cli.github.com\n\
-                 After installing, run 'gh auth login' to authenticate.",
-            );
-            download_via_gh(platform, &output_version, version)
-                .await
-                .expect("Failed to download Love2D v12 via gh CLI");
-            return;
+            self.check_online(&format!("LÖVE {} for {}", version, platform.as_ref()))?;
+            let staging = self.temp_dir().await?;
+            let downloaded = self
+                .download_ci_artifact(platform.clone(), &staging, version)
+                .await;
+            if downloaded.is_err() {
+                let _ = fs::remove_dir_all(&staging).await;
+            }
+            let (run_id, sha256) =
+                downloaded.context("Failed to download LÖVE 12 from the love2d/love CI")?;
+            let artifact = Artifact {
+                version: version.to_string(),
+                target: platform.as_ref().to_string(),
+                url: None,
+                sha256,
+                run_id: Some(run_id),
+            };
+            return self.finish_version(staging, artifact).await;
         }
 
-        let exe_name = match platform {
-            Target::LoveFile => "".to_string(),
-            Target::Android => format!("love-{}-android.apk", version),
-            Target::LinuxAppImage => format!("love-{}-x86_64.AppImage", version),
-            Target::Macos => format!("love-{}-macos.zip", version),
-            Target::Windows => format!("love-{}-win64.zip", version),
-        };
-
-        let response = self
-            .client
-            .get(format!(
-                "https://github.com/love2d/love/releases/download/{}/{}",
-                version, exe_name
-            ))
-            .send()
+        let location = format!(
+            "love2d/love/releases/download/{}/{}",
+            version,
+            love_file_name(version, &platform)
+        );
+        let url = self.github(&location);
+        let expected = self.published_checksum(&location).await;
+        let bytes = self.download(&url, expected.as_ref()).await?;
+        self.install_version(version, platform, bytes, Some(url))
             .await
-            .unwrap();
-
-        let bytes = response.bytes().await.unwrap();
+    }
 
+    /// Unpacks a downloaded or imported release of LÖVE into the cache
+    pub async fn install_version(
+        &self,
+        version: &str,
+        platform: Target,
+        bytes: tokio_util::bytes::Bytes,
+        url: Option<String>,
+    ) -> color_eyre::Result<()> {
+        let sha256 = hex::encode(sha2::Sha256::digest(&bytes));
+        let staging = self.temp_dir().await?;
         match platform {
-            Target::Android | Target::LinuxAppImage => {
-                std::fs::create_dir_all(&output_version).unwrap();
-                let mut file = std::fs::File::create_new(output_version.join(
-                    if let Target::Android = platform {
-                        "love2d.apk"
-                    } else {
-                        "love2d.AppImage"
-                    },
-                ))
-                .unwrap();
-                std::io::copy(&mut Cursor::new(bytes), &mut file).unwrap();
+            Target::Android | Target::LinuxAppImage | Target::LinuxAarch64 => {
+                let name = if let Target::Android = platform {
+                    "love2d.apk"
+                } else {
+                    "love2d.AppImage"
+                };
+                fs::write(staging.join(name), &bytes).await?;
             }
             _ => {
-                tokio::task::spawn_blocking(move || {
-                    extract_zip(Cursor::new(bytes), output_version);
-                })
-                .await
-                .unwrap();
+                let staging = staging.clone();
+                tokio::task::spawn_blocking(move || extract_zip(Cursor::new(bytes), staging))
+                    .await?
+                    .context("Extracting the zip")?;
             }
         }
+        let artifact = Artifact {
+            version: version.to_string(),
+            target: platform.as_ref().to_string(),
+            url,
+            sha256: Some(sha256),
+            run_id: None,
+        };
+        self.finish_version(staging, artifact).await
+    }
+
+    /// Moves a staged version into place and records where it came from
+    async fn finish_version(&self, staging: PathBuf, artifact: Artifact) -> color_eyre::Result<()> {
+        let version = &artifact.version;
+        let output_version = self.path.join(version).join(&artifact.target);
+        if output_version.exists() {
+            fs::remove_dir_all(&output_version).await?;
+        }
+        fs::create_dir_all(self.path.join(version)).await?;
+        fs::rename(&staging, &output_version)
+            .await
+            .context("Installing the downloaded version")?;
+        self.write_artifact(&artifact).await?;
+        let platform = Target::value_variants()
+            .iter()
+            .find(|x| x.as_ref() == artifact.target)
+            .wrap_err("Unknown target in the artifact info")?;
+        fs::write(self.version_marker(version, platform), "")
+            .await
+            .context("Marking the version as installed")?;
+        Ok(())
+    }
+
+    // Next to the target folder like the artifact info, written once the install is done
+    fn version_marker(&self, version: &str, platform: &Target) -> PathBuf {
+        self.path.join(version).join(format!(
+            ".{}-complete",
+            platform.clone().download_target().as_ref()
+        ))
     }
 }
 
-fn check_gh_available() -> color_eyre::Result<()> {
-    match std::process::Command::new("gh")
-        .arg("--version")
-        .stdout(std::process::Stdio::null())
-        .stderr(std::process::Stdio::null())
-        .status()
-    {
-        Ok(status) if status.success() => Ok(()),
-        _ => Err(color_eyre::eyre::eyre!(
-            "GitHub CLI (gh) is not installed or not found in PATH.\n\
-             Love2D version 12 requires the GitHub CLI to download CI artifacts.\n\
-             Install it from: https://cli.github.com"
-        )),
+/// Name of the release asset of LÖVE for a target
+pub fn love_file_name(version: &str, platform: &Target) -> String {
+    match platform {
+        Target::LoveFile | Target::Web => "".to_string(),
+        Target::Android | Target::AndroidBundle => format!("love-{}-android.apk", version),
+        Target::LinuxAppImage => format!("love-{}-x86_64.AppImage", version),
+        Target::LinuxAarch64 => format!("love-{}-aarch64.AppImage", version),
+        Target::Macos => format!("love-{}-macos.zip", version),
+        Target::Windows | Target::WindowsX86 => {
+            format!("{}.zip", windows_folder(version, platform))
+        }
     }
 }
 
+/// The folder inside the windows zips, love-11.5-win64 or love-11.5-win32
+fn windows_folder(version: &str, platform: &Target) -> String {
+    match platform {
+        Target::WindowsX86 => format!("love-{}-win32", version),
+        _ => format!("love-{}-win64", version),
+    }
+}
+
+pub fn dir_size(path: &Path) -> u64 {
+    walkdir::WalkDir::new(path)
+        .into_iter()
+        .filter_map(Result::ok)
+        .filter_map(|x| x.metadata().ok())
+        .filter(|x| x.is_file())
+        .map(|x| x.len())
+        .sum()
+}
+
+#[derive(Deserialize)]
+struct WorkflowRuns {
+    workflow_runs: Vec<WorkflowRun>,
+}
+
+#[derive(Deserialize)]
+struct WorkflowRun {
+    id: u64,
+    conclusion: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct CiArtifacts {
+    artifacts: Vec<CiArtifact>,
+}
+
+#[derive(Deserialize)]
+struct CiArtifact {
+    name: String,
+    archive_download_url: String,
+    #[serde(default)]
+    expired: bool,
+    // "sha256:<hex>", only present on newer artifacts
+    digest: Option<String>,
+}
+
 #[derive(Deserialize)]
-struct GhRun {
-    #[serde(rename = "databaseId")]
-    database_id: u64,
+struct Release {
+    assets: Vec<ReleaseAsset>,
+}
+
+#[derive(Deserialize)]
+struct ReleaseAsset {
+    name: String,
+    // "sha256:<hex>", github only has it for assets uploaded since mid 2025
+    digest: Option<String>,
+}
+
+/// The hash of a "sha256:<hex>" digest of the github api
+fn parse_digest(digest: &str) -> Option<[u8; 32]> {
+    hex::decode(digest.strip_prefix("sha256:")?)
+        .ok()?
+        .try_into()
+        .ok()
+}
+
+/// Which CI run a LÖVE 12 version points to
+#[derive(Debug, PartialEq, Eq)]
+enum CiPin<'a> {
+    Latest,
+    Run(u64),
+    Commit(&'a str),
+}
+
+/// Splits versions like "12.0+run.123456" or "12.0+commit.1a2b3c" into the version and the pin
+fn parse_ci_version(version: &str) -> color_eyre::Result<(&str, CiPin<'_>)> {
+    let Some((base, pin)) = version.split_once('+') else {
+        return Ok((version, CiPin::Latest));
+    };
+    let pin = if let Some(run) = pin.strip_prefix("run.") {
+        CiPin::Run(
+            run.parse()
+                .with_context(|| format!("Invalid run id in {}", version))?,
+        )
+    } else if let Some(commit) = pin.strip_prefix("commit.") {
+        CiPin::Commit(commit)
+    } else {
+        return Err(eyre!("Unknown pin {} in {}", pin, version))
+            .suggestion("Use a version like 12.0+run.123456 or 12.0+commit.<sha>");
+    };
+    Ok((base, pin))
+}
+
+/// The version without the CI pin, which is what the artifacts are named after
+pub fn base_version(version: &str) -> &str {
+    version.split('+').next().unwrap_or(version)
 }
 
 /// Returns the artifact name used in the love2d/love CI workflow for the given target.
 fn gh_artifact_name(platform: &Target) -> &'static str {
     match platform {
         Target::Windows => "love-windows-x64",
+        Target::WindowsX86 => "love-windows-x86",
         Target::LinuxAppImage => "love-linux-X64.AppImage",
+        Target::LinuxAarch64 => "love-linux-ARM64.AppImage",
         Target::Macos => "love-macos",
-        Target::Android => "love-android.apk",
-        Target::LoveFile => unreachable!("LoveFile does not need a download"),
+        Target::Android | Target::AndroidBundle => "love-android.apk",
+        Target::LoveFile | Target::Web => unreachable!("{} has no CI artifact", platform.as_ref()),
     }
 }
 
-async fn download_via_gh(
-    platform: Target,
-    output_version: &PathBuf,
-    version: &str,
-) -> color_eyre::Result<()> {
-    // 1. Get the latest successful run ID
-    let run_output = tokio::process::Command::new("gh")
-        .args([
-            "run",
-            "list",
-            "--branch",
-            "main",
-            "--status",
-            "success",
-            "--limit",
-            "1",
-            "--repo",
-            "love2d/love",
-            "--json",
-            "databaseId",
-        ])
-        .output()
-        .await
-        .context("Failed to run 'gh run list'")?;
-
-    if !run_output.status.success() {
-        return Err(color_eyre::eyre::eyre!(
-            "'gh run list' failed: {}",
-            String::from_utf8_lossy(&run_output.stderr)
-        ));
+impl HomeManager {
+    async fn api_get<T: serde::de::DeserializeOwned>(
+        &self,
+        token: &str,
+        path: &str,
+    ) -> color_eyre::Result<T> {
+        let url = format!("{}/repos/love2d/love/{}", self.api_url, path);
+        self.client
+            .get(&url)
+            .header("Accept", "application/vnd.github+json")
+            .header("X-GitHub-Api-Version", "2022-11-28")
+            .header("User-Agent", "kaledis")
+            .bearer_auth(token)
+            .send()
+            .await?
+            .error_for_status()
+            .with_context(|| format!("Requesting {}", url))?
+            .json()
+            .await
+            .with_context(|| format!("Reading the response of {}", url))
     }
 
-    let runs: Vec<GhRun> = serde_json::from_slice(&run_output.stdout)
-        .context("Failed to parse 'gh run list' JSON output")?;
-
-    let run = runs
-        .first()
-        .ok_or_else(|| color_eyre::eyre::eyre!("No successful CI runs found for love2d/love"))?;
+    /// Downloads the artifact of a target from a love2d/love CI run,
+    /// returns the run it came from and the sha256 of the downloaded file
+    async fn download_ci_artifact(
+        &self,
+        platform: Target,
+        output_version: &PathBuf,
+        version: &str,
+    ) -> color_eyre::Result<(u64, Option<String>)> {
+        // Artifacts can only be downloaded with a token, even for public repositories
+        let token = self
+            .github_token
+            .as_deref()
+            .wrap_err(
+                "LÖVE 12 is downloaded from the CI of love2d/love, which needs a GitHub token",
+            )
+            .suggestion(
+                "Set GITHUB_TOKEN or github_token in ~/.kaledis/config.toml, \
+                 a token without any scopes works",
+            )?;
+        let (version, pin) = parse_ci_version(version)?;
 
-    let run_id = run.database_id.to_string();
-    let artifact_name = gh_artifact_name(&platform);
+        let run = match pin {
+            CiPin::Run(id) => {
+                let run: WorkflowRun = self.api_get(token, &format!("actions/runs/{}", id)).await?;
+                if run.conclusion.as_deref() != Some("success") {
+                    tracing::warn!("CI run {} didn't succeed, its artifacts may be missing", id);
+                }
+                run.id
+            }
+            CiPin::Latest | CiPin::Commit(_) => {
+                let filter = match pin {
+                    CiPin::Commit(commit) => format!("head_sha={}", commit),
+                    _ => "branch=main".to_string(),
+                };
+                let runs: WorkflowRuns = self
+                    .api_get(
+                        token,
+                        &format!("actions/runs?{}&status=success&per_page=1", filter),
+                    )
+                    .await?;
+                runs.workflow_runs
+                    .first()
+                    .map(|x| x.id)
+                    .wrap_err("No successful CI runs found for love2d/love")?
+            }
+        };
 
-    println!(
-        "Downloading Love2D v12 artifact '{}' from run {}...",
-        artifact_name, run_id
-    );
+        let artifact_name = gh_artifact_name(&platform);
+        let artifacts: CiArtifacts = self
+            .api_get(
+                token,
+                &format!("actions/runs/{}/artifacts?name={}", run, artifact_name),
+            )
+            .await?;
+        let artifact = artifacts
+            .artifacts
+            .into_iter()
+            .find(|x| x.name == artifact_name)
+            .wrap_err_with(|| format!("CI run {} has no artifact {}", run, artifact_name))?;
+        if artifact.expired {
+            return Err(eyre!("The artifacts of CI run {} expired", run))
+                .suggestion("Pin a newer run, or use the latest one by removing the pin");
+        }
 
-    std::fs::create_dir_all(output_version)
-        .context("Failed to create output directory for Love2D v12")?;
+        println!(
+            "Downloading LÖVE {} artifact '{}' from run {}...",
+            version, artifact_name, run
+        );
+        // The download url redirects to storage that rejects the token, reqwest drops it on redirects
+        let bytes = self
+            .client
+            .get(&artifact.archive_download_url)
+            .header("User-Agent", "kaledis")
+            .bearer_auth(token)
+            .send()
+            .await?
+            .error_for_status()
+            .context("Downloading the artifact")?
+            .bytes()
+            .await?;
+        let expected = artifact.digest.as_deref().and_then(parse_digest);
+        self.verify(&artifact.archive_download_url, &bytes, expected.as_ref())
+            .await?;
 
-    let download_output = tokio::process::Command::new("gh")
-        .args([
-            "run",
-            "download",
-            &run_id,
-            "--name",
-            artifact_name,
-            "--repo",
-            "love2d/love",
-            "--dir",
-            &output_version.to_string_lossy(),
-        ])
-        .output()
-        .await
-        .context("Failed to run 'gh run download'")?;
-
-    if !download_output.status.success() {
-        // Clean up the empty directory on failure
-        let _ = std::fs::remove_dir_all(output_version);
-        return Err(color_eyre::eyre::eyre!(
-            "'gh run download' failed: {}",
-            String::from_utf8_lossy(&download_output.stderr)
-        ));
-    }
+        let output = output_version.clone();
+        tokio::task::spawn_blocking(move || extract_zip(Cursor::new(bytes), output))
+            .await?
+            .context("Extracting the artifact")?;
 
-    match platform {
-        Target::Windows => {
-            let zip_name = format!("love-{}-win64.zip", version);
-            let zip_path = output_version.join(&zip_name);
-            if zip_path.exists() {
-                println!("Extracting {}...", zip_name);
-                let bytes =
-                    std::fs::read(&zip_path).context("Failed to read the downloaded zip")?;
-                extract_zip(Cursor::new(bytes.into()), output_version.clone());
+        let mut sha256 = None;
+        match platform {
+            Target::Windows | Target::WindowsX86 => {
+                let zip_name = love_file_name(version, &platform);
+                let zip_path = output_version.join(&zip_name);
+                if zip_path.exists() {
+                    println!("Extracting {}...", zip_name);
+                    let bytes =
+                        std::fs::read(&zip_path).context("Failed to read the downloaded zip")?;
+                    sha256 = Some(hex::encode(sha2::Sha256::digest(&bytes)));
+                    extract_zip(Cursor::new(bytes.into()), output_version.clone())
+                        .with_context(|| format!("Extracting {}", zip_name))?;
+                }
             }
-        }
-        Target::Macos => {
-            let zip_path = output_version.join("love-macos.zip");
-            if zip_path.exists() {
-                println!("Extracting love-macos.zip...");
-                let bytes =
-                    std::fs::read(&zip_path).context("Failed to read the downloaded zip")?;
-                extract_zip(Cursor::new(bytes.into()), output_version.clone());
+            Target::Macos => {
+                let zip_path = output_version.join("love-macos.zip");
+                if zip_path.exists() {
+                    println!("Extracting love-macos.zip...");
+                    let bytes =
+                        std::fs::read(&zip_path).context("Failed to read the downloaded zip")?;
+                    sha256 = Some(hex::encode(sha2::Sha256::digest(&bytes)));
+                    extract_zip(Cursor::new(bytes.into()), output_version.clone())
+                        .context("Extracting love-macos.zip")?;
+                }
             }
-        }
-        Target::LinuxAppImage => {
-            for entry in std::fs::read_dir(output_version)? {
-                let entry = entry?;
-                let path = entry.path();
-                if path.extension().and_then(|s| s.to_str()) == Some("AppImage") {
-                    std::fs::rename(&path, output_version.join("love2d.AppImage"))?;
-                    break;
+            Target::LinuxAppImage | Target::LinuxAarch64 => {
+                for entry in std::fs::read_dir(output_version)? {
+                    let entry = entry?;
+                    let path = entry.path();
+                    if path.extension().and_then(|s| s.to_str()) == Some("AppImage") {
+                        sha256 = Some(hex::encode(sha2::Sha256::digest(std::fs::read(&path)?)));
+                        std::fs::rename(&path, output_version.join("love2d.AppImage"))?;
+                        break;
+                    }
                 }
             }
-        }
-        Target::Android => {
-            let apk_path = output_version.join("app-normal-record-release-unsigned.apk");
-            if apk_path.exists() {
-                std::fs::rename(apk_path, output_version.join("love2d.apk"))?;
+            Target::Android => {
+                let apk_path = output_version.join("app-normal-record-release-unsigned.apk");
+                if apk_path.exists() {
+                    sha256 = Some(hex::encode(sha2::Sha256::digest(std::fs::read(&apk_path)?)));
+                    std::fs::rename(apk_path, output_version.join("love2d.apk"))?;
+                }
             }
+            _ => {}
         }
-        _ => {}
-    }
 
-    println!("Love2D v12 artifact downloaded successfully.");
-    Ok(())
+        println!("LÖVE {} artifact downloaded successfully.", version);
+        Ok((run, sha256))
+    }
 }
 
-pub fn extract_zip(bytes: Cursor<tokio_util::bytes::Bytes>, output: PathBuf) {
-    let mut archive = ZipArchive::new(bytes).unwrap();
+pub fn extract_zip(
+    bytes: Cursor<tokio_util::bytes::Bytes>,
+    output: PathBuf,
+) -> color_eyre::Result<()> {
+    let mut archive = ZipArchive::new(bytes).context("Reading the zip")?;
 
     for i in 0..archive.len() {
-        let mut file = archive.by_index(i).unwrap();
+        let mut file = archive.by_index(i)?;
 
-        let outpath = Path::new(&output).join(file.name());
+        // Entries pointing outside of the output are refused instead of written there
+        let outpath = output.join(
+            file.enclosed_name()
+                .wrap_err_with(|| format!("Invalid path {} in the zip", file.name()))?,
+        );
 
         if file.is_dir() {
-            std::fs::create_dir_all(&outpath).unwrap();
+            std::fs::create_dir_all(&outpath)?;
         } else {
             if let Some(parent) = outpath.parent() {
-                std::fs::create_dir_all(parent).unwrap();
+                std::fs::create_dir_all(parent)?;
+            }
+
+            // The macos frameworks depend on symlinks, so they have to be kept
+            #[cfg(unix)]
+            if file.is_symlink() {
+                let mut target = String::new();
+                std::io::Read::read_to_string(&mut file, &mut target)?;
+                std::os::unix::fs::symlink(target, &outpath)
+                    .with_context(|| format!("Linking {}", outpath.display()))?;
+                continue;
             }
 
-            let mut outfile = std::fs::File::create(&outpath).unwrap();
-            std::io::copy(&mut file, &mut outfile).unwrap();
+            let mut outfile = fs_err::File::create(&outpath)?;
+            std::io::copy(&mut file, &mut outfile)
+                .with_context(|| format!("Extracting {}", file.name()))?;
+
+            #[cfg(unix)]
+            if let Some(mode) = file.unix_mode() {
+                use std::os::unix::fs::PermissionsExt;
+                fs_err::set_permissions(&outpath, std::fs::Permissions::from_mode(mode & 0o777))?;
+            }
         }
     }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zip_utils::Zipper;
+    use serde_json::json;
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    /// A local stand-in for github and its api, it answers the paths of the routes and 404
+    /// to the rest. The routes get the url of the server, for responses that link to it
+    async fn serve_with(routes: impl FnOnce(&str) -> Vec<(String, Vec<u8>)>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let routes = routes(&url);
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let routes = routes.clone();
+                tokio::spawn(async move {
+                    let mut request = vec![];
+                    let mut buf = [0; 1024];
+                    while !request.windows(4).any(|x| x == b"\r\n\r\n") {
+                        match stream.read(&mut buf).await {
+                            Ok(0) | Err(_) => return,
+                            Ok(read) => request.extend_from_slice(&buf[..read]),
+                        }
+                    }
+                    let request = String::from_utf8_lossy(&request);
+                    // Routes without a query match any query
+                    let full = request.split_whitespace().nth(1).unwrap_or_default();
+                    let path = full.split('?').next().unwrap_or_default();
+                    let (status, body) = match routes
+                        .iter()
+                        .find(|(route, _)| route == full || route == path)
+                    {
+                        Some((_, body)) => ("200 OK", body.clone()),
+                        None => ("404 Not Found", vec![]),
+                    };
+                    let head = format!(
+                        "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                        status,
+                        body.len()
+                    );
+                    let _ = stream.write_all(head.as_bytes()).await;
+                    let _ = stream.write_all(&body).await;
+                });
+            }
+        });
+        url
+    }
+
+    async fn serve(routes: Vec<(&str, Vec<u8>)>) -> String {
+        let routes = routes
+            .into_iter()
+            .map(|(path, body)| (path.to_string(), body))
+            .collect();
+        serve_with(|_| routes).await
+    }
+
+    fn home(name: &str, url: &str) -> HomeManager {
+        let path = std::env::temp_dir().join(format!("kaledis-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path).unwrap();
+        HomeManager {
+            path,
+            client: Client::new(),
+            github_url: url.to_string(),
+            mirrors: BTreeMap::new(),
+            offline: false,
+            api_url: url.to_string(),
+            github_token: None,
+            npm_url: url.to_string(),
+        }
+    }
+
+    const APPIMAGE: &str = "/love2d/love/releases/download/11.5/love-11.5-x86_64.AppImage";
+
+    #[tokio::test]
+    async fn installs_and_records_a_download() {
+        let url = serve(vec![(APPIMAGE, b"appimage".to_vec())]).await;
+        let home = home("install", &url);
+        home.ensure_version("11.5", Target::LinuxAppImage)
+            .await
+            .unwrap();
+
+        let installed = home.path.join("11.5/LinuxAppImage/love2d.AppImage");
+        assert_eq!(std::fs::read(installed).unwrap(), b"appimage");
+        let artifact = home
+            .get_artifact("11.5", &Target::LinuxAppImage)
+            .await
+            .unwrap();
+        assert_eq!(artifact.url, Some(format!("{}{}", url, APPIMAGE)));
+        assert_eq!(
+            artifact.sha256,
+            Some(hex::encode(sha2::Sha256::digest(b"appimage")))
+        );
+        // The staging folder was renamed into place and marked as complete
+        let temp = home.path.join("tmp");
+        assert!(std::fs::read_dir(temp).unwrap().next().is_none());
+        assert!(home.path.join("11.5/.LinuxAppImage-complete").exists());
+    }
+
+    /// The github api answer for a release of LÖVE 11.5 with the digest of an asset
+    fn release(name: &str, contents: &[u8]) -> (&'static str, Vec<u8>) {
+        let digest = format!("sha256:{}", hex::encode(sha2::Sha256::digest(contents)));
+        let body = json!({ "assets": [{ "name": name, "digest": digest }] });
+        (
+            "/repos/love2d/love/releases/tags/11.5",
+            body.to_string().into_bytes(),
+        )
+    }
+
+    #[tokio::test]
+    async fn verifies_the_published_checksum() {
+        let url = serve(vec![
+            (APPIMAGE, b"corrupted".to_vec()),
+            release("love-11.5-x86_64.AppImage", b"appimage"),
+        ])
+        .await;
+        let home = home("published", &url);
+        assert!(
+            home.ensure_version("11.5", Target::LinuxAppImage)
+                .await
+                .is_err()
+        );
+        assert!(!home.path.join("11.5").exists());
+
+        let url = serve(vec![
+            (APPIMAGE, b"appimage".to_vec()),
+            release("love-11.5-x86_64.AppImage", b"appimage"),
+        ])
+        .await;
+        let home = self::home("published", &url);
+        home.ensure_version("11.5", Target::LinuxAppImage)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn reads_checksum_files_next_to_the_asset() {
+        let sidecar = format!("{}.sha256.txt", APPIMAGE);
+        let checksum = format!(
+            "{}  love-11.5-x86_64.AppImage\n",
+            hex::encode(sha2::Sha256::digest(b"appimage"))
+        );
+        let url = serve(vec![
+            (APPIMAGE, b"corrupted".to_vec()),
+            (&sidecar, checksum.into_bytes()),
+        ])
+        .await;
+        let home = home("sidecar", &url);
+        assert!(
+            home.ensure_version("11.5", Target::LinuxAppImage)
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn rejects_a_corrupted_download() {
+        let url = serve(vec![(APPIMAGE, b"corrupted".to_vec())]).await;
+        let home = home("corrupted", &url);
+        let expected = hex::encode(sha2::Sha256::digest(b"appimage"));
+        std::fs::write(
+            home.path.join("checksums.txt"),
+            format!("{}  love-11.5-x86_64.AppImage\n", expected),
+        )
+        .unwrap();
+
+        assert!(
+            home.ensure_version("11.5", Target::LinuxAppImage)
+                .await
+                .is_err()
+        );
+        assert!(!home.path.join("11.5").exists());
+    }
+
+    #[tokio::test]
+    async fn reports_missing_releases() {
+        let url = serve(vec![]).await;
+        let home = home("missing", &url);
+        assert!(
+            home.ensure_version("11.5", Target::LinuxAppImage)
+                .await
+                .is_err()
+        );
+        assert!(!home.path.join("11.5").exists());
+    }
+
+    #[tokio::test]
+    async fn keeps_unmarked_installs_offline() {
+        // Nothing answers, so it would fail if it downloaded again
+        let mut home = home("older", "http://127.0.0.1:9");
+        home.offline = true;
+        let folder = home.path.join("11.5/LinuxAppImage");
+        std::fs::create_dir_all(&folder).unwrap();
+        std::fs::write(folder.join("love2d.AppImage"), b"partial").unwrap();
+
+        home.ensure_version("11.5", Target::LinuxAppImage)
+            .await
+            .unwrap();
+        assert_eq!(
+            std::fs::read(folder.join("love2d.AppImage")).unwrap(),
+            b"partial"
+        );
+    }
+
+    #[tokio::test]
+    async fn repairs_unmarked_installs() {
+        let url = serve(vec![(APPIMAGE, b"appimage".to_vec())]).await;
+        let home = home("repair", &url);
+        let folder = home.path.join("11.5/LinuxAppImage");
+        std::fs::create_dir_all(&folder).unwrap();
+        std::fs::write(folder.join("love2d.AppImage"), b"partial").unwrap();
+
+        home.ensure_version("11.5", Target::LinuxAppImage)
+            .await
+            .unwrap();
+        assert_eq!(
+            std::fs::read(folder.join("love2d.AppImage")).unwrap(),
+            b"appimage"
+        );
+        assert!(home.path.join("11.5/.LinuxAppImage-complete").exists());
+    }
+
+    #[test]
+    fn refuses_zip_entries_outside_of_the_output() {
+        let output = std::env::temp_dir().join(format!("kaledis-zip-{}", std::process::id()));
+        let mut zipper = Zipper::new();
+        zipper.add_buffer("../escaped.txt", b"escaped").unwrap();
+        let bytes = tokio_util::bytes::Bytes::from(zipper.finish());
+        assert!(extract_zip(Cursor::new(bytes), output.join("inner")).is_err());
+        assert!(!output.join("escaped.txt").exists());
+
+        let mut zipper = Zipper::new();
+        zipper.add_buffer("folder/file.txt", b"file").unwrap();
+        let bytes = tokio_util::bytes::Bytes::from(zipper.finish());
+        extract_zip(Cursor::new(bytes), output.clone()).unwrap();
+        assert_eq!(
+            std::fs::read(output.join("folder/file.txt")).unwrap(),
+            b"file"
+        );
+        std::fs::remove_dir_all(output).unwrap();
+    }
+
+    #[test]
+    fn covers_the_variants_of_a_platform() {
+        let targets: Vec<Target> = toml::Value::try_from(["android", "windows", "linux-x86_64"])
+            .unwrap()
+            .try_into()
+            .unwrap();
+        for (target, variant) in targets.iter().zip([
+            Target::AndroidBundle,
+            Target::WindowsX86,
+            Target::LinuxAarch64,
+        ]) {
+            assert!(target.covers(target));
+            assert!(target.covers(&variant));
+            assert!(!variant.covers(target));
+        }
+        assert!(!Target::Macos.covers(&Target::Web));
+    }
+
+    #[test]
+    fn parses_ci_versions() {
+        assert_eq!(parse_ci_version("12.0").unwrap(), ("12.0", CiPin::Latest));
+        assert_eq!(
+            parse_ci_version("12.0+run.123456").unwrap(),
+            ("12.0", CiPin::Run(123456))
+        );
+        assert_eq!(
+            parse_ci_version("12.0+commit.1a2b3c").unwrap(),
+            ("12.0", CiPin::Commit("1a2b3c"))
+        );
+        assert!(parse_ci_version("12.0+run.latest").is_err());
+        assert!(parse_ci_version("12.0+tag.1").is_err());
+        assert_eq!(base_version("12.0+run.123456"), "12.0");
+    }
+
+    /// A CI run with the artifact of the linux AppImage, zipped like github serves it
+    async fn serve_ci(run: u64, expired: bool) -> String {
+        let mut zipper = Zipper::new();
+        zipper
+            .add_buffer("love-12.0-x86_64.AppImage", b"appimage")
+            .unwrap();
+        let zip = zipper.finish();
+        serve_with(|url| {
+            let runs = json!({ "workflow_runs": [{ "id": run, "conclusion": "success" }] });
+            let artifacts = json!({
+                "artifacts": [{
+                    "name": "love-linux-X64.AppImage",
+                    "archive_download_url": format!("{}/artifacts/1/zip", url),
+                    "expired": expired,
+                    "digest": format!("sha256:{}", hex::encode(sha2::Sha256::digest(&zip))),
+                }]
+            });
+            vec![
+                (
+                    "/repos/love2d/love/actions/runs".to_string(),
+                    runs.to_string().into_bytes(),
+                ),
+                (
+                    format!("/repos/love2d/love/actions/runs/{}", run),
+                    runs["workflow_runs"][0].to_string().into_bytes(),
+                ),
+                (
+                    format!("/repos/love2d/love/actions/runs/{}/artifacts", run),
+                    artifacts.to_string().into_bytes(),
+                ),
+                ("/artifacts/1/zip".to_string(), zip),
+            ]
+        })
+        .await
+    }
+
+    fn ci_home(name: &str, url: &str) -> HomeManager {
+        let mut home = home(name, url);
+        home.github_token = Some("token".to_string());
+        home
+    }
+
+    #[tokio::test]
+    async fn downloads_pinned_ci_runs() {
+        let url = serve_ci(42, false).await;
+        let home = ci_home("ci-run", &url);
+        home.ensure_version("12.0+run.42", Target::LinuxAppImage)
+            .await
+            .unwrap();
+
+        let folder = home.path.join("12.0+run.42/LinuxAppImage");
+        assert_eq!(
+            std::fs::read(folder.join("love2d.AppImage")).unwrap(),
+            b"appimage"
+        );
+        let artifact = home
+            .get_artifact("12.0+run.42", &Target::LinuxAppImage)
+            .await
+            .unwrap();
+        assert_eq!(artifact.run_id, Some(42));
+        assert_eq!(
+            artifact.sha256,
+            Some(hex::encode(sha2::Sha256::digest(b"appimage")))
+        );
+    }
+
+    #[tokio::test]
+    async fn downloads_the_latest_ci_run() {
+        let url = serve_ci(7, false).await;
+        let home = ci_home("ci-latest", &url);
+        home.ensure_version("12.0", Target::LinuxAppImage)
+            .await
+            .unwrap();
+        let artifact = home
+            .get_artifact("12.0", &Target::LinuxAppImage)
+            .await
+            .unwrap();
+        assert_eq!(artifact.run_id, Some(7));
+    }
+
+    #[tokio::test]
+    async fn rejects_expired_or_missing_ci_artifacts() {
+        let url = serve_ci(42, true).await;
+        let home = ci_home("ci-expired", &url);
+        assert!(
+            home.ensure_version("12.0+run.42", Target::LinuxAppImage)
+                .await
+                .is_err()
+        );
+        // The run has no artifact for macos
+        assert!(
+            home.ensure_version("12.0+run.42", Target::Macos)
+                .await
+                .is_err()
+        );
+        assert!(!home.path.join("12.0+run.42").exists());
+    }
+
+    #[tokio::test]
+    async fn needs_a_token_for_ci_artifacts() {
+        let url = serve_ci(42, false).await;
+        let home = home("ci-token", &url);
+        assert!(
+            home.ensure_version("12.0+run.42", Target::LinuxAppImage)
+                .await
+                .is_err()
+        );
+    }
+
+    // The x64 linux runtime has no pinned hash, so it goes by the one adoptium publishes
+    #[cfg(all(target_os = "linux", not(target_arch = "aarch64")))]
+    #[tokio::test]
+    async fn checks_the_java_runtime_against_the_published_checksum() {
+        let mut archive = tar::Builder::new(flate2::write::GzEncoder::new(
+            vec![],
+            flate2::Compression::fast(),
+        ));
+        let mut header = tar::Header::new_gnu();
+        header.set_size(4);
+        header.set_mode(0o755);
+        header.set_cksum();
+        archive
+            .append_data(&mut header, "jdk-11.0.30+7-jre/bin/java", &b"java"[..])
+            .unwrap();
+        let runtime = archive.into_inner().unwrap().finish().unwrap();
+
+        let (location, _) = HomeManager::java_release();
+        let path = format!("/{}", location);
+        let sidecar = format!("{}.sha256.txt", path);
+        for (name, published, installed) in [
+            ("java-corrupted", b"other".to_vec(), false),
+            ("java", runtime.clone(), true),
+        ] {
+            let checksum = hex::encode(sha2::Sha256::digest(&published));
+            let url = serve(vec![
+                (&path, runtime.clone()),
+                (&sidecar, checksum.into_bytes()),
+            ])
+            .await;
+            let home = home(name, &url);
+            assert_eq!(home.ensure_java().await.is_ok(), installed);
+            assert_eq!(home.get_java_path().exists(), installed);
+        }
+    }
+
+    /// A jar with only a manifest, like the ones the tools are distributed as
+    fn jar() -> Vec<u8> {
+        let mut zipper = Zipper::new();
+        zipper
+            .add_buffer("META-INF/MANIFEST.MF", b"Manifest-Version: 1.0\r\n\r\n")
+            .unwrap();
+        zipper.finish()
+    }
+
+    /// Serves a jar and the release listing the digest of `published` for it
+    async fn serve_jar(location: &str, published: &[u8]) -> String {
+        let (repo, rest) = location.split_at(location.find("/releases/download/").unwrap());
+        let (tag, name) = rest
+            .trim_start_matches("/releases/download/")
+            .split_once('/')
+            .unwrap();
+        let digest = format!("sha256:{}", hex::encode(sha2::Sha256::digest(published)));
+        let release = json!({ "assets": [{ "name": name, "digest": digest }] });
+        serve(vec![
+            (&format!("/{}", location), jar()),
+            (
+                &format!("/repos/{}/releases/tags/{}", repo, tag),
+                release.to_string().into_bytes(),
+            ),
+        ])
+        .await
+    }
+
+    #[tokio::test]
+    async fn checks_the_signer_against_its_published_digest() {
+        let url = serve_jar(UBER_APK_SIGNER_LOCATION, b"other").await;
+        let home = home("signer-corrupted", &url);
+        assert!(home.ensure_signer().await.is_err());
+        assert!(!home.get_signer_path().exists());
+
+        let url = serve_jar(UBER_APK_SIGNER_LOCATION, &jar()).await;
+        let home = self::home("signer", &url);
+        home.ensure_signer().await.unwrap();
+        assert_eq!(std::fs::read(home.get_signer_path()).unwrap(), jar());
+    }
+
+    #[tokio::test]
+    async fn checks_bundletool_against_its_published_digest() {
+        let url = serve_jar(BUNDLETOOL_LOCATION, b"other").await;
+        let home = home("bundletool-corrupted", &url);
+        assert!(home.ensure_bundletool().await.is_err());
+        assert!(!home.get_bundletool_path().exists());
+
+        let url = serve_jar(BUNDLETOOL_LOCATION, &jar()).await;
+        let home = self::home("bundletool", &url);
+        home.ensure_bundletool().await.unwrap();
+        assert_eq!(std::fs::read(home.get_bundletool_path()).unwrap(), jar());
+    }
+
+    #[tokio::test]
+    async fn remembers_each_project_once() {
+        let home = home("projects", "http://127.0.0.1:9");
+        let project = home.path.join("game");
+        std::fs::create_dir_all(&project).unwrap();
+        std::fs::write(project.join("kaledis.toml"), "").unwrap();
+        let gone = home.path.join("gone");
+        std::fs::create_dir_all(&gone).unwrap();
+
+        home.register_project(&project).await.unwrap();
+        home.register_project(&gone).await.unwrap();
+        home.register_project(&project.join("../game")).await.unwrap();
+        // Projects without a kaledis.toml anymore are skipped
+        assert_eq!(home.projects().await, vec![project.canonicalize().unwrap()]);
+    }
 }
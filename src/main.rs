@@ -2,10 +2,13 @@ mod android;
 mod commands;
 mod dalbit;
 mod editpe;
+mod elf_utils;
 mod home_manager;
 mod toml_conf;
 mod utils;
+mod xml_utils;
 mod zip_utils;
+mod zsync_utils;
 
 use std::{process::ExitCode, thread};
 
@@ -65,6 +68,7 @@ fn run() -> ExitCode {
         .enable_time()
         .build()
         .unwrap();
+    home_manager::OFFLINE.store(args.offline, std::sync::atomic::Ordering::Relaxed);
     rt.block_on(handle_commands(args.cli));
     ExitCode::SUCCESS
 }
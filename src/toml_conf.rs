@@ -1,12 +1,20 @@
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, HashMap},
     fmt::Display,
     fs::read_to_string,
     path::{Path, PathBuf},
 };
 
-use crate::dalbit::polyfill::{DEFAULT_INJECTION_PATH, Polyfill};
+use crate::{
+    dalbit::polyfill::{DEFAULT_INJECTION_PATH, Polyfill},
+    home_manager::Target,
+    xml_utils::XmlElement,
+};
 use clap_serde_derive::serde::Serialize;
+use color_eyre::{
+    Section,
+    eyre::{Context, eyre},
+};
 use schemars::JsonSchema;
 use serde::Deserialize;
 
@@ -48,7 +56,13 @@ mod defaults {
     default_create!(u32, 0, u32_0);
     default_create!(u32, 800, u32_800);
     default_create!(u32, 600, u32_600);
+    default_create!(u32, 2048, u32_2048);
+    // Same as love.js
+    default_create!(u64, 16777216, web_memory);
+    default_create!(String, "assets".to_string(), assets_alias);
     default_create!(String, "Untitled".to_string(), untitled);
+    default_create!(String, "public.app-category.games".to_string(), mac_category);
+    default_create!(String, "KALEDIS_KEYSTORE_PASSWORD".to_string(), store_password_env);
     // default_create!(String, "11.5".to_string(), love_version);
     pub fn default_orientation() -> Orientation {
         Orientation::Landscape
@@ -258,8 +272,10 @@ pub struct Project {
 
 #[derive(Serialize, Deserialize, Debug, JsonSchema)]
 pub struct LayoutConfig {
+    /// Globs of the assets inside the .love, entries starting with ! take files out like in a .gitignore
     #[serde(default)]
     pub bundle: Vec<String>,
+    /// Globs of the assets shipped next to the game, entries starting with ! take files out
     #[serde(default)]
     pub external: Vec<String>,
     #[serde(default)]
@@ -282,6 +298,243 @@ pub struct LoveConfig {
 
 pub struct MacosConfig {
     pub id: String,
+    /// The version shown to users (CFBundleShortVersionString)
+    pub version: Option<String>,
+    /// The build number (CFBundleVersion), if not provided we will use the version
+    pub build_version: Option<String>,
+    /// Copyright notice (NSHumanReadableCopyright)
+    pub copyright: Option<String>,
+    /// App Store category (LSApplicationCategoryType)
+    #[serde(default = "defaults::mac_category")]
+    pub category: String,
+    /// Minimum macOS version required, like "10.15" (LSMinimumSystemVersion)
+    pub minimum_system_version: Option<String>,
+    /// Packages the .app in a zip ready for distribution
+    #[serde(default = "defaults::fn_true")]
+    #[schemars(with = "Option<bool>")]
+    pub zip: bool,
+    /// Enables retina resolution (NSHighResolutionCapable)
+    #[serde(default = "defaults::fn_true")]
+    #[schemars(with = "Option<bool>")]
+    pub high_resolution: bool,
+    /// Keys to remove from the Info.plist of love
+    #[serde(default)]
+    pub plist_remove: Vec<String>,
+    /// Arbitrary Info.plist keys, they override anything kaledis sets
+    #[serde(default)]
+    pub plist: BTreeMap<String, serde_json::Value>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, JsonSchema, Default)]
+pub enum AppImageCompression {
+    #[default]
+    Zstd,
+    Gzip,
+    /// Only use it if the runtime of your love version supports it
+    Lz4,
+}
+
+#[derive(Serialize, Deserialize, Debug, JsonSchema)]
+pub struct WebConfig {
+    /// Memory available to the game in bytes, raise it if the game runs out of memory loading assets
+    #[serde(default = "defaults::web_memory")]
+    #[schemars(with = "Option<u64>")]
+    pub memory: u64,
+    /// Uses the threaded build of love.js, it needs the COOP/COEP headers that `kaledis serve` sends
+    #[serde(default = "defaults::fn_false")]
+    #[schemars(with = "Option<bool>")]
+    pub threaded: bool,
+    /// The canvas width, if not provided we will use the window width of conf.toml
+    pub width: Option<u32>,
+    /// The canvas height, if not provided we will use the window height of conf.toml
+    pub height: Option<u32>,
+}
+
+impl Default for WebConfig {
+    fn default() -> Self {
+        Self {
+            memory: defaults::web_memory(),
+            threaded: false,
+            width: None,
+            height: None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
+pub struct AssetsConfig {
+    /// Generates a typed module with the path of every asset matched by the layout, required with the alias
+    #[serde(default = "defaults::fn_true")]
+    #[schemars(with = "Option<bool>")]
+    pub generate: bool,
+    /// The alias the module is required with, like require("@assets")
+    #[serde(default = "defaults::assets_alias")]
+    #[schemars(with = "Option<String>")]
+    pub alias: String,
+    /// Folders of PNGs packed into texture atlases, by the name they are required with
+    #[serde(default)]
+    pub atlas: BTreeMap<String, AtlasConfig>,
+    /// Shrinks the bundled assets of release builds
+    pub optimize: Option<OptimizeConfig>,
+    /// Tiled and LDtk maps converted into Lua modules
+    pub maps: Option<MapsConfig>,
+    /// Sprite sheets exported by Aseprite turned into Lua modules
+    pub aseprite: Option<AsepriteConfig>,
+    /// TrueType fonts rasterized into bitmap fonts, by the name they are required with
+    #[serde(default)]
+    pub fonts: BTreeMap<String, FontConfig>,
+    /// Where the files of layout.bundle go inside the game and which targets get them
+    #[serde(default)]
+    pub rules: Vec<AssetRule>,
+}
+
+impl Default for AssetsConfig {
+    fn default() -> Self {
+        Self {
+            generate: true,
+            alias: defaults::assets_alias(),
+            atlas: BTreeMap::new(),
+            optimize: None,
+            maps: None,
+            aseprite: None,
+            fonts: BTreeMap::new(),
+            rules: vec![],
+        }
+    }
+}
+
+// A map is converted next to the code, so assets/maps/level.tmx is loaded with
+// require("assets.maps.level") and the raw files are left out of the game
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
+pub struct MapsConfig {
+    /// Globs of the Tiled (.tmx, .tmj) and LDtk (.ldtk) files to convert
+    pub files: Vec<String>,
+}
+
+// Each sheet is exported with File > Export Sprite Sheet and "JSON Data" checked, the module
+// goes next to the code like the maps and the image is bundled even if the layout misses it
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
+pub struct AsepriteConfig {
+    /// Globs of the .json files exported with the sheets
+    pub files: Vec<String>,
+}
+
+// For each target, the first rule matching a file and listing the target decides where it goes.
+// Files only matched by the rules of other targets are left out, so the hd and sd versions of
+// the art can go to the same folder for desktop and android. Dev uses the desktop it runs on
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
+pub struct AssetRule {
+    /// Glob of the bundled files the rule moves, like "art/export/**"
+    pub from: String,
+    /// Where they go inside the game. A folder ending with / keeps their path after the folder
+    /// of the glob, anything else is the new path of a single file
+    pub to: String,
+    /// The targets that get the files, all of them when it is missing. android, windows and
+    /// linux-x86_64 also stand for android-bundle, windows-x86 and linux-aarch64
+    pub targets: Option<Vec<Target>>,
+}
+
+// The image goes to fonts/<name>.png with fonts/<name>.fnt for BMFont, and fonts/<name>.lua
+// returns where they are, so require("fonts.<name>").load() gives the LÖVE font
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
+pub struct FontConfig {
+    /// The .ttf or .otf file, it is left out of the game
+    pub file: String,
+    /// Height of the font in pixels, pixel fonts stay crisp at multiples of their design size
+    pub size: f32,
+    /// The characters rasterized, printable ASCII by default
+    pub glyphs: Option<String>,
+    #[serde(default)]
+    pub format: FontFormat,
+    /// Smooth edges, turn it off for pixel art so every pixel is either drawn or not
+    #[serde(default = "defaults::fn_true")]
+    #[schemars(with = "Option<bool>")]
+    pub antialias: bool,
+    /// Transparent pixels between glyphs
+    #[serde(default = "defaults::u32_1")]
+    #[schemars(with = "Option<u32>")]
+    pub padding: u32,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, JsonSchema, Default, PartialEq, Eq)]
+pub enum FontFormat {
+    /// A .fnt with a packed image, keeps the kerning of the font
+    #[default]
+    BMFont,
+    /// A strip of glyphs for love.graphics.newImageFont, without kerning
+    ImageFont,
+}
+
+// The results are cached in .kaledis/optimized by the hash of the original
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
+pub struct OptimizeConfig {
+    /// Re-encodes the PNGs losslessly at the highest compression, dropping their metadata
+    #[serde(default = "defaults::fn_true")]
+    #[schemars(with = "Option<bool>")]
+    pub png: bool,
+    /// Scales the PNGs of the android builds, like 0.5 for half the resolution.
+    /// The game has to draw them scaled up to keep their size on screen
+    pub mobile_scale: Option<f32>,
+}
+
+// The pages are written to atlas/<name>_<page>.png with an atlas/<name>.lua module
+// holding the quads, so require("atlas.<name>") loads it
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
+pub struct AtlasConfig {
+    /// The folder with the PNGs, sprites are named by their path inside it without the extension
+    pub folder: String,
+    /// Transparent pixels between sprites
+    #[serde(default = "defaults::u32_1")]
+    #[schemars(with = "Option<u32>")]
+    pub padding: u32,
+    /// Pixels the edges of each sprite are repeated outwards, stops bleeding when filtering
+    #[serde(default = "defaults::u32_0")]
+    #[schemars(with = "Option<u32>")]
+    pub extrude: u32,
+    /// Maximum width and height of a page, sprites that don't fit go to another page
+    #[serde(default = "defaults::u32_2048")]
+    #[schemars(with = "Option<u32>")]
+    pub max_size: u32,
+    /// Rounds the size of the pages up to a power of two, max_size has to be one too
+    #[serde(default = "defaults::fn_false")]
+    #[schemars(with = "Option<bool>")]
+    pub power_of_two: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, JsonSchema)]
+pub struct LinuxConfig {
+    /// AppImage update information written into the runtime, e.g. "zsync|https://example.com/game.AppImage.zsync"
+    pub update_information: Option<String>,
+    /// Generates a .zsync file next to the AppImage, defaults to true when the update information uses zsync
+    pub zsync: Option<bool>,
+    /// Signs the AppImage with gpg, embedding the signature and the public key
+    #[serde(default = "defaults::fn_false")]
+    #[schemars(with = "Option<bool>")]
+    pub sign: bool,
+    /// The gpg key used to sign, if not provided the default key is used
+    pub sign_key: Option<String>,
+    /// Compression used in the AppImage squashfs
+    #[serde(default)]
+    #[schemars(with = "Option<AppImageCompression>")]
+    pub compression: AppImageCompression,
+    /// The compression level, zstd goes from 1 to 22 and gzip from 1 to 9
+    pub compression_level: Option<u32>,
+    /// The squashfs block size, a power of two between 4096 and 1048576
+    pub block_size: Option<u32>,
+}
+
+#[derive(Serialize, Deserialize, Debug, JsonSchema)]
+pub struct AndroidSigningConfig {
+    /// Path to the keystore, relative to the project
+    pub keystore: String,
+    /// The alias of the key inside the keystore
+    pub alias: String,
+    /// Environment variable holding the keystore password
+    #[serde(default = "defaults::store_password_env")]
+    #[schemars(with = "Option<String>")]
+    pub store_password_env: String,
+    /// Environment variable holding the key password, if not provided the keystore password is used
+    pub key_password_env: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug, JsonSchema)]
@@ -310,88 +563,197 @@ pub struct AndroidConfig {
     pub audio_pro: bool,
     #[serde(default)]
     pub audio_low_latency: bool,
+    // If not provided the debug keystore from `kaledis android keygen` is used
+    pub signing: Option<AndroidSigningConfig>,
+    /// Minimum android sdk, written to the manifest and apktool.yml
+    pub min_sdk: Option<u32>,
+    /// Target android sdk, written to the manifest and apktool.yml
+    pub target_sdk: Option<u32>,
+    /// Extra permissions, "CAMERA" is the same as "android.permission.CAMERA"
+    #[serde(default)]
+    pub permissions: Vec<String>,
+    /// The android:icon resource of the application, defaults to the generated icons
+    pub icon: Option<String>,
+    /// Foreground layer of the adaptive icon, defaults to the icon of the project
+    pub icon_foreground: Option<String>,
+    /// Background layer of the adaptive icon, an image or a color like "#1E1E2E"
+    pub icon_background: Option<String>,
+    /// How custom_android_manifest is used
+    #[serde(default)]
+    #[schemars(with = "Option<ManifestMode>")]
+    pub manifest_mode: ManifestMode,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, JsonSchema, Default)]
+pub enum ManifestMode {
+    /// The custom manifest replaces the generated one
+    #[default]
+    Replace,
+    /// The elements of the custom manifest are added on top of the generated one
+    Merge,
 }
 
 impl AndroidConfig {
-    pub fn to_string(&self, project_name: &str) -> String {
-        format!(
-            r#"<?xml version="1.0" encoding="utf-8"?>
-<manifest package="{}"
-        android:versionCode="{}"
-        android:versionName="{}"
-        android:installLocation="auto"
-		xmlns:android="http://schemas.android.com/apk/res/android">
-    <uses-permission android:name="android.permission.INTERNET" />
-    <uses-permission android:name="android.permission.VIBRATE" />
-    <uses-permission android:name="android.permission.BLUETOOTH" />
-    <uses-permission android:name="android.permission.WRITE_EXTERNAL_STORAGE" android:maxSdkVersion="18" />
-    {}
+    pub fn display_name(&self, project_name: &str) -> String {
+        self.game_name
+            .clone()
+            .unwrap_or_else(|| project_name.to_owned())
+    }
+    pub fn display_version(&self) -> String {
+        self.version_name
+            .clone()
+            .unwrap_or_else(|| self.version_code.to_string())
+    }
 
-    <!-- OpenGL ES 2.0 -->
-    <uses-feature android:glEsVersion="0x00020000" />
-    <!-- Touchscreen support -->
-    <uses-feature android:name="android.hardware.touchscreen" android:required="{}" />
-    <!-- Game controller support -->
-    <uses-feature android:name="android.hardware.bluetooth" android:required="{}" />
-    <uses-feature android:name="android.hardware.gamepad" android:required="{}" />
-    <uses-feature android:name="android.hardware.usb.host" android:required="{}" />
-    <!-- External mouse input events -->
-    <uses-feature android:name="android.hardware.type.pc" android:required="{}" />
-    <!-- Low latency audio -->
-    <uses-feature android:name="android.hardware.audio.low_latency" android:required="{}" />
-    <uses-feature android:name="android.hardware.audio.pro" android:required="{}" />
-
-    <application
-            android:allowBackup="true"
-            android:icon="@drawable/love"
-            android:label="{}"
-            android:usesCleartextTraffic="true" >
-        <activity
-                android:name="org.love2d.android.GameActivity"
-                android:exported="true"
-                android:configChanges="orientation|screenSize|smallestScreenSize|screenLayout|keyboard|keyboardHidden|navigation"
-                android:label="{}"
-                android:launchMode="singleInstance"
-                android:screenOrientation="{}"
-                android:resizeableActivity="false"
-                android:theme="@android:style/Theme.NoTitleBar.Fullscreen" >
-            <intent-filter>
-                <action android:name="android.intent.action.MAIN" />
-                <category android:name="android.intent.category.LAUNCHER" />
-                <category android:name="tv.ouya.intent.category.GAME" />
-            </intent-filter>
-            <intent-filter>
-                <action android:name="android.hardware.usb.action.USB_DEVICE_ATTACHED" />
-            </intent-filter>
-        </activity>
-    </application>
-</manifest>
-        "#,
-            self.game_id,
-            &self.version_code,
-            self.version_name
-                .as_ref()
-                .unwrap_or(&self.version_code.to_string()),
-            if self.uses_microphone {
-                "<uses-permission android:name=\"android.permission.RECORD_AUDIO\" />"
-            } else {
-                ""
-            },
-            self.touchscreen,
-            self.bluetooth,
-            self.gamepad,
-            self.usb_host,
-            self.external_mouse_input,
-            self.audio_low_latency,
-            self.audio_pro,
-            self.game_name.as_ref().unwrap_or(&project_name.to_owned()),
-            self.game_name.as_ref().unwrap_or(&project_name.to_owned()),
-            if let Orientation::Landscape = self.orientation {
-                "landscape"
+    /// Values usable as `${name}` inside a custom android manifest
+    pub fn placeholders(&self, project_name: &str) -> Vec<(&'static str, String)> {
+        vec![
+            ("game_id", self.game_id.clone()),
+            ("version_code", self.version_code.to_string()),
+            ("version_name", self.display_version()),
+            ("game_name", self.display_name(project_name)),
+            ("project_name", project_name.to_owned()),
+            ("orientation", self.orientation_name().to_string()),
+            ("icon", self.icon_resource()),
+            (
+                "min_sdk",
+                self.min_sdk.map(|x| x.to_string()).unwrap_or_default(),
+            ),
+            (
+                "target_sdk",
+                self.target_sdk.map(|x| x.to_string()).unwrap_or_default(),
+            ),
+        ]
+    }
+
+    pub fn has_adaptive_icon(&self) -> bool {
+        self.icon_foreground.is_some() || self.icon_background.is_some()
+    }
+    pub fn icon_resource(&self) -> String {
+        match &self.icon {
+            Some(icon) => icon.clone(),
+            None if self.has_adaptive_icon() => "@mipmap/ic_launcher".to_string(),
+            None => "@drawable/love".to_string(),
+        }
+    }
+
+    fn orientation_name(&self) -> &'static str {
+        if let Orientation::Landscape = self.orientation {
+            "landscape"
+        } else {
+            "portrait"
+        }
+    }
+
+    pub fn manifest(&self, project_name: &str) -> XmlElement {
+        let permission = |name: &str| {
+            let name = if name.contains('.') {
+                name.to_string()
             } else {
-                "portrait"
+                format!("android.permission.{}", name)
+            };
+            XmlElement::new("uses-permission").attr("android:name", name)
+        };
+        let feature = |name: &str, required: bool| {
+            XmlElement::new("uses-feature")
+                .attr("android:name", name)
+                .attr("android:required", required)
+        };
+
+        let mut manifest = XmlElement::new("manifest")
+            .attr("package", &self.game_id)
+            .attr("android:versionCode", self.version_code)
+            .attr("android:versionName", self.display_version())
+            .attr("android:installLocation", "auto")
+            .attr("xmlns:android", "http://schemas.android.com/apk/res/android")
+            .child(permission("INTERNET"))
+            .child(permission("VIBRATE"))
+            .child(permission("BLUETOOTH"))
+            .child(permission("WRITE_EXTERNAL_STORAGE").attr("android:maxSdkVersion", 18));
+        if self.uses_microphone {
+            manifest = manifest.child(permission("RECORD_AUDIO"));
+        }
+        for name in &self.permissions {
+            manifest = manifest.child(permission(name));
+        }
+        if self.min_sdk.is_some() || self.target_sdk.is_some() {
+            let mut sdk = XmlElement::new("uses-sdk");
+            if let Some(min_sdk) = self.min_sdk {
+                sdk.set_attr("android:minSdkVersion", min_sdk);
             }
-        )
+            if let Some(target_sdk) = self.target_sdk {
+                sdk.set_attr("android:targetSdkVersion", target_sdk);
+            }
+            manifest = manifest.child(sdk);
+        }
+
+        let name = self.display_name(project_name);
+        let mut application = XmlElement::new("application")
+            .attr("android:allowBackup", true)
+            .attr("android:icon", self.icon_resource());
+        if self.icon.is_none() && self.has_adaptive_icon() {
+            application.set_attr("android:roundIcon", "@mipmap/ic_launcher_round");
+        }
+        manifest
+            .comment("OpenGL ES 2.0")
+            .child(XmlElement::new("uses-feature").attr("android:glEsVersion", "0x00020000"))
+            .comment("Touchscreen support")
+            .child(feature("android.hardware.touchscreen", self.touchscreen))
+            .comment("Game controller support")
+            .child(feature("android.hardware.bluetooth", self.bluetooth))
+            .child(feature("android.hardware.gamepad", self.gamepad))
+            .child(feature("android.hardware.usb.host", self.usb_host))
+            .comment("External mouse input events")
+            .child(feature("android.hardware.type.pc", self.external_mouse_input))
+            .comment("Low latency audio")
+            .child(feature(
+                "android.hardware.audio.low_latency",
+                self.audio_low_latency,
+            ))
+            .child(feature("android.hardware.audio.pro", self.audio_pro))
+            .child(
+                application
+                    .attr("android:label", &name)
+                    .attr("android:usesCleartextTraffic", true)
+                    .child(
+                        XmlElement::new("activity")
+                            .attr("android:name", "org.love2d.android.GameActivity")
+                            .attr("android:exported", true)
+                            .attr(
+                                "android:configChanges",
+                                "orientation|screenSize|smallestScreenSize|screenLayout|keyboard|keyboardHidden|navigation",
+                            )
+                            .attr("android:label", &name)
+                            .attr("android:launchMode", "singleInstance")
+                            .attr("android:screenOrientation", self.orientation_name())
+                            .attr("android:resizeableActivity", false)
+                            .attr(
+                                "android:theme",
+                                "@android:style/Theme.NoTitleBar.Fullscreen",
+                            )
+                            .child(
+                                XmlElement::new("intent-filter")
+                                    .child(
+                                        XmlElement::new("action")
+                                            .attr("android:name", "android.intent.action.MAIN"),
+                                    )
+                                    .child(XmlElement::new("category").attr(
+                                        "android:name",
+                                        "android.intent.category.LAUNCHER",
+                                    ))
+                                    .child(XmlElement::new("category").attr(
+                                        "android:name",
+                                        "tv.ouya.intent.category.GAME",
+                                    )),
+                            )
+                            .child(XmlElement::new("intent-filter").child(
+                                XmlElement::new("action").attr(
+                                    "android:name",
+                                    "android.hardware.usb.action.USB_DEVICE_ATTACHED",
+                                ),
+                            )),
+                    ),
+            )
     }
 }
 
@@ -404,6 +766,9 @@ pub struct KaledisConfig {
     // If not provided we will use the project_name
     pub android: Option<AndroidConfig>,
     pub mac: Option<MacosConfig>,
+    pub linux: Option<LinuxConfig>,
+    pub web: Option<WebConfig>,
+    pub assets: Option<AssetsConfig>,
     pub custom_android_manifest: Option<String>,
     pub icon: Option<String>,
     #[serde(default)]
@@ -416,14 +781,110 @@ pub struct KaledisConfig {
     #[serde(default = "defaults::fn_true")]
     #[schemars(with = "Option<bool>")]
     pub hmr: bool,
-    // for custom ports you have to provide a folder for each platform
-    pub love: String,
+    pub love: LoveRuntime,
+}
+
+/// The LÖVE the project runs on
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
+#[serde(untagged)]
+pub enum LoveRuntime {
+    /// A released version downloaded by kaledis like "11.5", or "system" to run the love on PATH
+    Version(String),
+    /// A custom build of LÖVE, like a patched fork
+    Custom(CustomLoveConfig),
+}
+
+// For custom ports you have to provide a folder for each platform,
+// laid out like the ones kaledis downloads into ~/.kaledis/<version>/<target>
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
+pub struct CustomLoveConfig {
+    /// The version the build is based on, it is downloaded for targets without a folder
+    pub version: String,
+    /// The love executable, or a folder containing it, used by Dev
+    pub path: Option<String>,
+    /// Folder with the 64 bit love.exe and its dlls
+    pub windows: Option<String>,
+    /// Folder with the 32 bit love.exe and its dlls
+    pub windows_x86: Option<String>,
+    /// Folder with the x86_64 love2d.AppImage
+    pub linux: Option<String>,
+    /// Folder with the aarch64 love2d.AppImage
+    pub linux_aarch64: Option<String>,
+    /// Folder with love.app
+    pub macos: Option<String>,
+    /// Folder with love2d.apk
+    pub android: Option<String>,
+    /// Folder with the compat and release builds of love.js
+    pub web: Option<String>,
+}
+
+impl LoveRuntime {
+    pub fn is_system(&self) -> bool {
+        matches!(self, LoveRuntime::Version(version) if version == "system")
+    }
+
+    /// The version written in the config, none for "system"
+    pub fn pinned_version(&self) -> Option<&str> {
+        match self {
+            LoveRuntime::Version(_) if self.is_system() => None,
+            LoveRuntime::Version(version) => Some(version),
+            LoveRuntime::Custom(custom) => Some(&custom.version),
+        }
+    }
+
+    /// Custom runtime folder of a target, relative to the project
+    pub fn custom_folder(&self, target: &Target) -> Option<&str> {
+        let LoveRuntime::Custom(custom) = self else {
+            return None;
+        };
+        match target.clone().download_target() {
+            Target::Windows => custom.windows.as_deref(),
+            Target::WindowsX86 => custom.windows_x86.as_deref(),
+            Target::LinuxAppImage => custom.linux.as_deref(),
+            Target::LinuxAarch64 => custom.linux_aarch64.as_deref(),
+            Target::Macos => custom.macos.as_deref(),
+            Target::Android => custom.android.as_deref(),
+            Target::Web => custom.web.as_deref(),
+            _ => None,
+        }
+    }
+}
+
+impl LinuxConfig {
+    /// Checks the values serde accepts but the AppImage build would reject
+    pub fn validate(&self) -> color_eyre::Result<()> {
+        let Some(level) = self.compression_level else {
+            return Ok(());
+        };
+        let levels = match self.compression {
+            AppImageCompression::Zstd => 1..=22,
+            AppImageCompression::Gzip => 1..=9,
+            AppImageCompression::Lz4 => return Ok(()),
+        };
+        if !levels.contains(&level) {
+            return Err(eyre!(
+                "Invalid compression_level {} for {:?}",
+                level,
+                self.compression
+            ))
+            .suggestion(format!(
+                "Use a level between {} and {}, or remove compression_level to use the default",
+                levels.start(),
+                levels.end()
+            ));
+        }
+        Ok(())
+    }
 }
 
 impl KaledisConfig {
     pub fn from_toml_file<P: AsRef<Path>>(path: P) -> color_eyre::Result<Self> {
         let data = read_to_string(path)?;
-        Ok(toml::from_str(&data)?)
+        let config: Self = toml::from_str(&data)?;
+        if let Some(linux) = &config.linux {
+            linux.validate().wrap_err("Invalid [linux] config")?;
+        }
+        Ok(config)
     }
 }
 
@@ -524,3 +985,24 @@ end"#,
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn linux(text: &str) -> LinuxConfig {
+        toml::from_str(text).unwrap()
+    }
+
+    #[test]
+    fn validates_the_compression_level() {
+        assert!(linux("compression_level = 22").validate().is_ok());
+        assert!(linux("compression_level = 23").validate().is_err());
+        assert!(linux("compression_level = 0").validate().is_err());
+        assert!(linux("compression = \"Gzip\"\ncompression_level = 9").validate().is_ok());
+        assert!(linux("compression = \"Gzip\"\ncompression_level = 12").validate().is_err());
+        // lz4 ignores the level with a warning when building
+        assert!(linux("compression = \"Lz4\"\ncompression_level = 40").validate().is_ok());
+        assert!(linux("").validate().is_ok());
+    }
+}
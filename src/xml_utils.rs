@@ -0,0 +1,206 @@
+use color_eyre::eyre::{ContextCompat, eyre};
+use quick_xml::{Reader, XmlVersion, events::Event};
+
+// Small XML tree used to generate and merge documents like AndroidManifest.xml
+// and to read Tiled maps, the text of an element is kept joined in a single string
+
+#[derive(Debug, Clone)]
+pub enum XmlNode {
+    Element(XmlElement),
+    Comment(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct XmlElement {
+    pub name: String,
+    pub attributes: Vec<(String, String)>,
+    pub children: Vec<XmlNode>,
+    pub text: String,
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+impl XmlElement {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            attributes: vec![],
+            children: vec![],
+            text: String::new(),
+        }
+    }
+
+    pub fn attr(mut self, key: &str, value: impl ToString) -> Self {
+        self.set_attr(key, value);
+        self
+    }
+    pub fn child(mut self, element: XmlElement) -> Self {
+        self.children.push(XmlNode::Element(element));
+        self
+    }
+    pub fn comment(mut self, text: &str) -> Self {
+        self.children.push(XmlNode::Comment(text.to_string()));
+        self
+    }
+
+    /// Sets an attribute, replacing it if it already exists
+    pub fn set_attr(&mut self, key: &str, value: impl ToString) {
+        let value = value.to_string();
+        match self.attributes.iter_mut().find(|(name, _)| name == key) {
+            Some((_, old)) => *old = value,
+            None => self.attributes.push((key.to_string(), value)),
+        }
+    }
+    pub fn get_attr(&self, key: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(name, _)| name == key)
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn elements(&self) -> impl Iterator<Item = &XmlElement> {
+        self.children.iter().filter_map(|x| match x {
+            XmlNode::Element(element) => Some(element),
+            XmlNode::Comment(_) => None,
+        })
+    }
+    pub fn elements_mut(&mut self) -> impl Iterator<Item = &mut XmlElement> {
+        self.children.iter_mut().filter_map(|x| match x {
+            XmlNode::Element(element) => Some(element),
+            XmlNode::Comment(_) => None,
+        })
+    }
+
+    fn write(&self, output: &mut String, depth: usize) {
+        let indent = "    ".repeat(depth);
+        output.push_str(&indent);
+        output.push('<');
+        output.push_str(&self.name);
+        for (key, value) in &self.attributes {
+            // Long tags like <activity> read better with one attribute per line
+            if self.attributes.len() > 2 {
+                output.push('\n');
+                output.push_str(&indent);
+                output.push_str("        ");
+            } else {
+                output.push(' ');
+            }
+            output.push_str(&format!("{}=\"{}\"", key, escape(value)));
+        }
+        if self.children.is_empty() {
+            match self.text.trim() {
+                "" => output.push_str(" />\n"),
+                text => output.push_str(&format!(">{}</{}>\n", escape(text), self.name)),
+            }
+            return;
+        }
+        output.push_str(">\n");
+        for child in &self.children {
+            match child {
+                XmlNode::Element(element) => element.write(output, depth + 1),
+                XmlNode::Comment(text) => {
+                    output.push_str(&format!("{}    <!-- {} -->\n", indent, text))
+                }
+            }
+        }
+        output.push_str(&format!("{}</{}>\n", indent, self.name));
+    }
+
+    pub fn to_document(&self) -> String {
+        let mut output = String::from("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n");
+        self.write(&mut output, 0);
+        output
+    }
+
+    /// Parses a document, returning its root element
+    pub fn parse(text: &str) -> color_eyre::Result<XmlElement> {
+        let mut reader = Reader::from_str(text);
+        let mut stack: Vec<XmlElement> = vec![];
+        let mut root = None;
+
+        loop {
+            let (element, is_empty) = match reader.read_event()? {
+                Event::Start(tag) => (tag, false),
+                Event::Empty(tag) => (tag, true),
+                Event::End(_) => {
+                    let element = stack.pop().wrap_err("Unbalanced closing tag")?;
+                    match stack.last_mut() {
+                        Some(parent) => parent.children.push(XmlNode::Element(element)),
+                        None => root = Some(element),
+                    }
+                    continue;
+                }
+                Event::Comment(text) => {
+                    if let Some(parent) = stack.last_mut() {
+                        parent
+                            .children
+                            .push(XmlNode::Comment(text.xml10_content().trim().to_string()));
+                    }
+                    continue;
+                }
+                Event::Text(text) => {
+                    if let Some(parent) = stack.last_mut() {
+                        parent.text.push_str(&text.xml10_content());
+                    }
+                    continue;
+                }
+                Event::CData(text) => {
+                    if let Some(parent) = stack.last_mut() {
+                        parent.text.push_str(&text.xml10_content());
+                    }
+                    continue;
+                }
+                Event::GeneralRef(reference) => {
+                    let character = match reference.resolve_char_ref()? {
+                        Some(character) => character,
+                        None => match reference.xml10_content().as_ref() {
+                            "lt" => '<',
+                            "gt" => '>',
+                            "amp" => '&',
+                            "apos" => '\'',
+                            "quot" => '"',
+                            name => return Err(eyre!("Unknown entity &{};", name)),
+                        },
+                    };
+                    if let Some(parent) = stack.last_mut() {
+                        parent.text.push(character);
+                    }
+                    continue;
+                }
+                Event::Eof => break,
+                _ => continue,
+            };
+
+            let mut current = XmlElement::new(element.name().as_ref());
+            for attribute in element.attributes() {
+                let attribute = attribute.map_err(quick_xml::Error::from)?;
+                current.attributes.push((
+                    attribute.key.as_ref().to_string(),
+                    attribute
+                        .normalized_value(XmlVersion::Implicit1_0)?
+                        .to_string(),
+                ));
+            }
+
+            if !is_empty {
+                stack.push(current);
+            } else {
+                match stack.last_mut() {
+                    Some(parent) => parent.children.push(XmlNode::Element(current)),
+                    None => root = Some(current),
+                }
+            }
+        }
+
+        if !stack.is_empty() {
+            return Err(eyre!("Unclosed tag <{}>", stack.last().unwrap().name));
+        }
+        root.wrap_err("The document has no root element")
+    }
+}
@@ -19,15 +19,15 @@ impl Zipper {
         let data = self.inner.finish().unwrap();
         data.into_inner()
     }
-    // pub async fn add_buffer(&mut self, name: &str, buffer: &[u8]) -> ZipResult<()> {
-    //     let zip = &mut self.inner;
-    //     zip.start_file(
-    //         name,
-    //         SimpleFileOptions::default().compression_method(zip::CompressionMethod::Stored),
-    //     )?;
-    //     let _ = zip.write(buffer)?;
-    //     Ok(())
-    // }
+    pub fn add_buffer(&mut self, name: &str, buffer: &[u8]) -> ZipResult<()> {
+        let zip = &mut self.inner;
+        zip.start_file(
+            name,
+            SimpleFileOptions::default().compression_method(zip::CompressionMethod::Stored),
+        )?;
+        zip.write_all(buffer)?;
+        Ok(())
+    }
 
     // Copies an file into a zip stripping it's root
     pub fn add_rootless(&mut self, name: &Path, root: &PathBuf) -> color_eyre::Result<()> {
@@ -43,6 +43,46 @@ impl Zipper {
         std::io::copy(&mut file, zip)?;
         Ok(())
     }
+    /// Zips a folder under `prefix` keeping symlinks and unix permissions,
+    /// app bundles don't work without them
+    pub fn put_folder_with_permissions(
+        &mut self,
+        folder: &Path,
+        prefix: &Path,
+    ) -> color_eyre::Result<()> {
+        for entry in WalkDir::new(folder).into_iter().filter_map(Result::ok) {
+            let path = entry.path();
+            let name = prefix
+                .join(path.strip_prefix(folder)?)
+                .to_string_lossy()
+                .replace('\\', "/");
+
+            #[cfg(unix)]
+            let mode = {
+                use std::os::unix::fs::PermissionsExt;
+                entry.metadata()?.permissions().mode() & 0o777
+            };
+            // There is no way to know what is executable here
+            #[cfg(not(unix))]
+            let mode = 0o755;
+
+            let options = SimpleFileOptions::default()
+                .compression_method(zip::CompressionMethod::Deflated)
+                .unix_permissions(mode);
+            if entry.file_type().is_symlink() {
+                let target = std::fs::read_link(path)?;
+                self.inner
+                    .add_symlink(name, target.to_string_lossy(), options)?;
+            } else if entry.file_type().is_dir() {
+                self.inner.add_directory(name, options)?;
+            } else {
+                self.inner.start_file(name, options)?;
+                let mut file = std::fs::File::open(path)?;
+                std::io::copy(&mut file, &mut self.inner)?;
+            }
+        }
+        Ok(())
+    }
     pub fn put_folder_recursively(&mut self, folder: &PathBuf) -> color_eyre::Result<()> {
         if !folder.is_dir() {
             return Err(color_eyre::eyre::eyre!("Not a directory"));
@@ -0,0 +1,181 @@
+use std::{
+    io::{BufWriter, Read, Write},
+    path::Path,
+};
+
+use md4::Md4;
+use sha1::{Digest, Sha1};
+
+// Generates .zsync control files the same way zsyncmake 0.6.2 does,
+// which is what AppImageUpdate expects to find for delta updates
+
+fn rsum(block: &[u8]) -> [u8; 4] {
+    let mut a: u16 = 0;
+    let mut b: u16 = 0;
+    let mut len = block.len();
+    for byte in block {
+        a = a.wrapping_add(*byte as u16);
+        b = b.wrapping_add((len as u16).wrapping_mul(*byte as u16));
+        len -= 1;
+    }
+    let mut result = [0; 4];
+    result[..2].copy_from_slice(&a.to_be_bytes());
+    result[2..].copy_from_slice(&b.to_be_bytes());
+    result
+}
+
+fn read_block(reader: &mut impl Read, buffer: &mut [u8]) -> std::io::Result<usize> {
+    let mut read = 0;
+    while read < buffer.len() {
+        let amount = reader.read(&mut buffer[read..])?;
+        if amount == 0 {
+            break;
+        }
+        read += amount;
+    }
+    Ok(read)
+}
+
+fn block_size(length: u64) -> usize {
+    if length < 100_000_000 { 2048 } else { 4096 }
+}
+
+/// Bytes of each block checksum that are written, mirrors the
+/// calculation zsyncmake does so clients read the same amounts
+fn hash_lengths(length: u64, block_size: usize) -> (usize, usize, usize) {
+    let seq_matches = if length > block_size as u64 { 2 } else { 1 };
+    if length == 0 {
+        return (seq_matches, 2, 3);
+    }
+    let len = length as f64;
+    let blocks = (1 + length / block_size as u64) as f64;
+    let rsum_bytes =
+        (((len.ln() + (block_size as f64).ln()) / 2f64.ln() - 8.6) / seq_matches as f64 / 8.0)
+            .ceil()
+            .clamp(2.0, 4.0) as usize;
+    let checksum_bytes = ((20.0 + (len.ln() + blocks.ln()) / 2f64.ln()) / seq_matches as f64 / 8.0)
+        .ceil()
+        .max(((7.9 + (20.0 + blocks.ln() / 2f64.ln())) / 8.0).trunc())
+        .min(16.0) as usize;
+    (seq_matches, rsum_bytes, checksum_bytes)
+}
+
+/// Writes `output` with the block checksums of `file`.
+/// `url` is where the client downloads the file from, relative to the .zsync file.
+pub fn make_zsync(file: &Path, url: &str, output: &Path) -> color_eyre::Result<()> {
+    let length = fs_err::metadata(file)?.len();
+    let block_size = block_size(length);
+
+    let mut reader = std::io::BufReader::new(fs_err::File::open(file)?);
+    let mut sha1 = Sha1::new();
+    let mut sums = vec![];
+    let mut buffer = vec![0; block_size];
+    loop {
+        let read = read_block(&mut reader, &mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        sha1.update(&buffer[..read]);
+        // The last block is padded with zeros
+        buffer[read..].fill(0);
+        sums.push((rsum(&buffer), Md4::digest(&buffer)));
+        if read < block_size {
+            break;
+        }
+    }
+
+    let (seq_matches, rsum_bytes, checksum_bytes) = hash_lengths(length, block_size);
+
+    let filename = file
+        .file_name()
+        .map(|x| x.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    let mut out = BufWriter::new(fs_err::File::create(output)?);
+    write!(
+        out,
+        "zsync: 0.6.2\nFilename: {}\nBlocksize: {}\nLength: {}\nHash-Lengths: {},{},{}\nURL: {}\nSHA-1: {}\n\n",
+        filename,
+        block_size,
+        length,
+        seq_matches,
+        rsum_bytes,
+        checksum_bytes,
+        url,
+        hex::encode(sha1.finalize())
+    )?;
+    for (rsum, checksum) in sums {
+        out.write_all(&rsum[4 - rsum_bytes..])?;
+        out.write_all(&checksum[..checksum_bytes])?;
+    }
+    out.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> std::path::PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("kaledis-zsync-{}-{}", name, std::process::id()));
+        let _ = fs_err::remove_dir_all(&dir);
+        fs_err::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn computes_the_rolling_checksum() {
+        // a = 1 + 2 + 3, b = 3 * 1 + 2 * 2 + 1 * 3
+        assert_eq!(rsum(&[1, 2, 3]), [0, 6, 0, 10]);
+    }
+
+    #[test]
+    fn uses_the_same_hash_lengths_as_zsyncmake() {
+        assert_eq!(block_size(99_999_999), 2048);
+        assert_eq!(block_size(100_000_000), 4096);
+
+        assert_eq!(hash_lengths(3, 2048), (1, 2, 3));
+        assert_eq!(hash_lengths(5000, 2048), (2, 2, 3));
+        // What zsyncmake writes for a typical 100MB AppImage
+        assert_eq!(hash_lengths(100_000_000, 4096), (2, 2, 5));
+        assert_eq!(hash_lengths(1_000_000_000, 4096), (2, 3, 5));
+    }
+
+    #[test]
+    fn writes_the_control_file() {
+        let dir = temp_dir("control");
+        let file = dir.join("game.AppImage");
+        fs_err::write(&file, b"abc").unwrap();
+        let output = dir.join("game.AppImage.zsync");
+        make_zsync(&file, "game.AppImage", &output).unwrap();
+
+        let mut expected = b"zsync: 0.6.2\nFilename: game.AppImage\nBlocksize: 2048\nLength: 3\n\
+            Hash-Lengths: 1,2,3\nURL: game.AppImage\n\
+            SHA-1: a9993e364706816aba3e25717850c26c9cd0d89d\n\n"
+            .to_vec();
+        // rsum and MD4 of "abc" padded with zeros to the block size
+        expected.extend([0x2e, 0xd8, 0x5c, 0xac, 0xce]);
+        assert_eq!(fs_err::read(&output).unwrap(), expected);
+        fs_err::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn does_not_pad_files_that_fill_their_blocks() {
+        let dir = temp_dir("blocks");
+        let file = dir.join("game.AppImage");
+        fs_err::write(&file, vec![7; 4096]).unwrap();
+        let output = dir.join("game.AppImage.zsync");
+        make_zsync(&file, "game.AppImage", &output).unwrap();
+
+        let data = fs_err::read(&output).unwrap();
+        let text = String::from_utf8_lossy(&data);
+        assert!(text.contains("Hash-Lengths: 2,2,3\n"));
+        let header = text.find("\n\n").unwrap() + 2;
+        let block = [7; 2048];
+        let mut sum = rsum(&block)[2..].to_vec();
+        sum.extend(&Md4::digest(block)[..3]);
+        assert_eq!(data[header..], [sum.clone(), sum].concat());
+        fs_err::remove_dir_all(&dir).unwrap();
+    }
+}
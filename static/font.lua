@@ -0,0 +1,8 @@
+-- Generated by kaledis from {{SOURCE}}, changes are overwritten
+local font = {{FONT}}
+
+function font.load()
+	return {{LOAD}}
+end
+
+return font
@@ -0,0 +1,20 @@
+// Generated by kaledis, mounts game.data as /game.love before love starts
+Module.preRun = Module.preRun || [];
+Module.preRun.push(function (instance) {
+    var module = instance || Module;
+    module.addRunDependency("game.love");
+    fetch("game.data")
+        .then(function (response) {
+            if (!response.ok) {
+                throw new Error("Failed to load game.data: " + response.status);
+            }
+            return response.arrayBuffer();
+        })
+        .then(function (buffer) {
+            module.FS_createDataFile("/", "game.love", new Uint8Array(buffer), true, true, true);
+            module.removeRunDependency("game.love");
+        })
+        .catch(function (error) {
+            Module.setStatus(error.message);
+        });
+});